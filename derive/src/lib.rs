@@ -0,0 +1,23 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Implement `DesyncComponent`, so the component can be tracked with
+/// `AppDesyncExt::track_desync_derived`. The component must also implement `Component` and
+/// `Serialize`, which is checked here rather than at registration
+#[proc_macro_derive(TrackDesync)]
+pub fn derive_track_desync(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::bevy_mod_desync::DesyncComponent for #name #type_generics
+            #where_clause
+        {
+            fn track_desync(app: &mut ::bevy_mod_desync::__macro::App) {
+                ::bevy_mod_desync::AppDesyncExt::track_desync::<Self>(app);
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,83 @@
+//! A rollback session under ggrs's sync test, which simulates each frame again a few frames later
+//! and compares the checksums it saved both times. [`GgrsChecksumPlugin`] works the CRC of the
+//! tracked state into each saved checksum, so a frame which simulates differently the second time,
+//! e.g. because a system reads state that isn't rolled back, is reported by ggrs as a mismatched
+//! checksum.
+//!
+//! Run with `cargo run --example ggrs --features ggrs`
+use bevy_app::{App, Startup};
+use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
+use bevy_ggrs::{
+    ggrs::SessionBuilder, AddRollbackCommandExtension, Checksum, GgrsApp, GgrsConfig, GgrsPlugin,
+    GgrsSchedule, LocalInputs, LocalPlayers, PlayerInputs, ReadInputs, RollbackFrameCount, Session,
+};
+use bevy_mod_desync::*;
+use bevy_time::{TimePlugin, TimeUpdateStrategy};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+
+const FPS: usize = 60;
+const FRAMES: usize = 30;
+
+type Config = GgrsConfig<u8>;
+
+#[derive(Clone, Copy, Component, Serialize)]
+struct Position(i32);
+
+fn spawn_player(mut commands: Commands) {
+    commands.spawn((Position(0), TrackDesync)).add_rollback();
+}
+
+/// Every player's input for the next frame, derived from the frame so the run is repeatable
+fn read_inputs(mut commands: Commands, players: Res<LocalPlayers>, frame: Res<RollbackFrameCount>) {
+    let input = (frame.0 % 3) as u8;
+    let inputs = players.0.iter().map(|handle| (*handle, input)).collect();
+    commands.insert_resource(LocalInputs::<Config>(inputs));
+}
+
+fn move_player(inputs: Res<PlayerInputs<Config>>, mut query: Query<&mut Position>) {
+    for mut position in query.iter_mut() {
+        position.0 += inputs[0].0 as i32;
+    }
+}
+
+fn main() {
+    let session = SessionBuilder::<Config>::new()
+        .with_num_players(1)
+        .with_check_distance(2)
+        .start_synctest_session()
+        .unwrap();
+
+    let mut app = App::new();
+    app.add_plugins(TimePlugin)
+        // step exactly one rollback frame per update
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.01 / FPS as f64,
+        )))
+        .add_plugins(GgrsPlugin::<Config>::default())
+        .set_rollback_schedule_fps(FPS)
+        .rollback_component_with_copy::<Position>()
+        .add_plugins(DesyncPlugin {
+            schedule: Some(GgrsSchedule.intern()),
+            tick_source: Some(Arc::new(|world| {
+                world.resource::<RollbackFrameCount>().0 as u64
+            })),
+            rollback_checksums: Some(16),
+            ..Default::default()
+        })
+        .add_plugins(GgrsChecksumPlugin)
+        .add_systems(Startup, spawn_player)
+        .add_systems(ReadInputs, read_inputs)
+        .add_systems(GgrsSchedule, move_player.before(DesyncSet::CalculateCrc))
+        .insert_resource(Session::SyncTest(session));
+    app.track_desync::<Position>();
+
+    for _ in 0..FRAMES {
+        app.update();
+    }
+
+    let frame = app.world.resource::<RollbackFrameCount>().0;
+    let checksum = app.world.resource::<Checksum>().0;
+    println!("frame {frame} saved with checksum {checksum:#x}");
+    assert!(frame > 0, "no frames were simulated");
+}
@@ -0,0 +1,111 @@
+//! Two peers stepping the same simulation from a shared input stream, exchanging CRCs over an
+//! in-process channel. Partway through, one peer's state is corrupted and the desync is detected
+//! from the exchanged messages.
+//!
+//! Run with `cargo run --example networked`
+use bevy_app::{App, Update};
+use bevy_ecs::prelude::*;
+use bevy_mod_desync::*;
+use serde::Serialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+const TICKS: u64 = 20;
+const CORRUPT_AFTER_TICK: u64 = 10;
+
+#[derive(Component, Serialize)]
+struct Position(i64);
+
+/// The input for the current tick, shared by both peers
+#[derive(Default, Resource)]
+struct Input(i64);
+
+fn apply_input(input: Res<Input>, mut query: Query<&mut Position>) {
+    for mut position in query.iter_mut() {
+        position.0 += input.0;
+    }
+}
+
+struct Peer {
+    app: App,
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl Peer {
+    fn new(sender: Sender<Vec<u8>>, receiver: Receiver<Vec<u8>>) -> Self {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Position>();
+        app.init_resource::<Input>()
+            .add_systems(Update, apply_input);
+        app.world.spawn((Position(0), TrackDesync));
+        app.world.spawn((Position(100), TrackDesync));
+        Peer {
+            app,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Step the simulation and send the CRC calculated this tick to the other peer
+    fn step(&mut self, input: i64) {
+        self.app.world.resource_mut::<Input>().0 = input;
+        self.app.update();
+        let history = self.app.world.resource::<CrcHistory>();
+        let &(tick, crc) = history.iter().last().unwrap();
+        self.sender
+            .send(CrcMessage { tick, crc }.encode().to_vec())
+            .unwrap();
+    }
+
+    /// Check received CRCs against our own history, returning the ticks which didn't match
+    fn check_received(&self) -> Vec<u64> {
+        let history = self.app.world.resource::<CrcHistory>();
+        self.receiver
+            .try_iter()
+            .filter_map(|bytes| CrcMessage::decode(&bytes))
+            .filter(|message| history.get(message.tick) != Some(message.crc))
+            .map(|message| message.tick)
+            .collect()
+    }
+}
+
+fn main() {
+    let (send_a, receive_b) = channel();
+    let (send_b, receive_a) = channel();
+    let mut peer_a = Peer::new(send_a, receive_a);
+    let mut peer_b = Peer::new(send_b, receive_b);
+
+    // a deterministic input stream both peers agree on
+    let inputs = (0..TICKS as i64).map(|i| (i * 7) % 5 - 2);
+
+    let mut first_desync = None;
+    for (tick, input) in inputs.enumerate() {
+        peer_a.step(input);
+        peer_b.step(input);
+
+        let desyncs_a = peer_a.check_received();
+        let desyncs_b = peer_b.check_received();
+        // both peers should agree on whether they're in sync
+        assert_eq!(desyncs_a, desyncs_b);
+        for tick in desyncs_a {
+            println!("desync detected at tick {tick}");
+            first_desync.get_or_insert(tick);
+        }
+
+        if tick as u64 == CORRUPT_AFTER_TICK {
+            println!("corrupting peer b after tick {tick}");
+            let mut query = peer_b.app.world.query::<&mut Position>();
+            query.iter_mut(&mut peer_b.app.world).next().unwrap().0 += 1;
+        }
+    }
+
+    // the CRC is calculated at the start of the tick, so the corruption is seen on the next one
+    assert_eq!(first_desync, Some(CORRUPT_AFTER_TICK + 1));
+    println!("ok");
+}
+
+#[test]
+fn networked() {
+    main();
+}
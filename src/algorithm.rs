@@ -0,0 +1,158 @@
+use crc::{Crc, Digest, CRC_16_IBM_SDLC, CRC_32_ISO_HDLC, CRC_64_XZ};
+
+static IBM_16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+static ISO_HDLC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+static XZ_64: Crc<u64> = Crc::<u64>::new(&CRC_64_XZ);
+
+/// Checksum the world is hashed with. A 16 bit CRC collides often enough that large worlds hashed
+/// every tick of a long match can report the same CRC for different states, so pick a wider one
+/// there. Only [`crate::FullCrc`], [`crate::RollbackChecksums`], the report and resource CRCs
+/// carry the full width. [`crate::Crc`], [`crate::StrictCrc`], [`crate::CrcHistory`],
+/// [`crate::RollingCrc`], [`crate::CrcMessage`] and [`crate::DesyncDetected`] keep its low 16
+/// bits, so compare [`crate::FullCrc`] where a collision would go unnoticed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CrcAlgorithm {
+    /// CRC-16/IBM-SDLC
+    #[default]
+    Ibm16,
+    /// CRC-32/ISO-HDLC, as used by zlib
+    Crc32IsoHdlc,
+    /// CRC-64/XZ
+    Crc64Xz,
+}
+
+impl CrcAlgorithm {
+    /// Name of the algorithm, as in [`crate::TrackingConfig::crc`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            CrcAlgorithm::Ibm16 => "CRC-16/IBM-SDLC",
+            CrcAlgorithm::Crc32IsoHdlc => "CRC-32/ISO-HDLC",
+            CrcAlgorithm::Crc64Xz => "CRC-64/XZ",
+        }
+    }
+
+    /// Width of the checksum in bits
+    pub fn width(&self) -> u32 {
+        match self {
+            CrcAlgorithm::Ibm16 => 16,
+            CrcAlgorithm::Crc32IsoHdlc => 32,
+            CrcAlgorithm::Crc64Xz => 64,
+        }
+    }
+
+    pub fn checksum(&self, bytes: &[u8]) -> u64 {
+        match self {
+            CrcAlgorithm::Ibm16 => IBM_16.checksum(bytes) as u64,
+            CrcAlgorithm::Crc32IsoHdlc => ISO_HDLC_32.checksum(bytes) as u64,
+            CrcAlgorithm::Crc64Xz => XZ_64.checksum(bytes),
+        }
+    }
+
+    /// The polynomial with its bits reversed, as every supported algorithm shifts its register
+    /// right
+    pub(crate) fn reflected_poly(&self) -> u64 {
+        let poly = match self {
+            CrcAlgorithm::Ibm16 => CRC_16_IBM_SDLC.poly as u64,
+            CrcAlgorithm::Crc32IsoHdlc => CRC_32_ISO_HDLC.poly as u64,
+            CrcAlgorithm::Crc64Xz => CRC_64_XZ.poly,
+        };
+        poly.reverse_bits() >> (64 - self.width())
+    }
+
+    /// Keep only the bits within the width, e.g. after summing checksums
+    pub(crate) fn truncate(&self, crc: u64) -> u64 {
+        match self.width() {
+            64 => crc,
+            width => crc & ((1 << width) - 1),
+        }
+    }
+
+    pub(crate) fn digest(&self) -> CrcDigest {
+        match self {
+            CrcAlgorithm::Ibm16 => CrcDigest::Ibm16(IBM_16.digest()),
+            CrcAlgorithm::Crc32IsoHdlc => CrcDigest::Crc32IsoHdlc(ISO_HDLC_32.digest()),
+            CrcAlgorithm::Crc64Xz => CrcDigest::Crc64Xz(XZ_64.digest()),
+        }
+    }
+}
+
+/// Incremental checksum for a [`CrcAlgorithm`]
+pub(crate) enum CrcDigest {
+    Ibm16(Digest<'static, u16>),
+    Crc32IsoHdlc(Digest<'static, u32>),
+    Crc64Xz(Digest<'static, u64>),
+}
+
+impl CrcDigest {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            CrcDigest::Ibm16(digest) => digest.update(bytes),
+            CrcDigest::Crc32IsoHdlc(digest) => digest.update(bytes),
+            CrcDigest::Crc64Xz(digest) => digest.update(bytes),
+        }
+    }
+
+    pub fn finalize(self) -> u64 {
+        match self {
+            CrcDigest::Ibm16(digest) => digest.finalize() as u64,
+            CrcDigest::Crc32IsoHdlc(digest) => digest.finalize() as u64,
+            CrcDigest::Crc64Xz(digest) => digest.finalize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{
+        calculate_crc, AppDesyncExt, Crc, DesyncPlugin, FullCrc, TrackDesync, TrackingConfig,
+    };
+
+    #[derive(Component, Serialize)]
+    struct Foo(u32);
+
+    fn build_app(crc_algorithm: CrcAlgorithm) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            crc_algorithm,
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.world.spawn((Foo(1), TrackDesync));
+        app.world.spawn((Foo(2), TrackDesync));
+        app.update();
+        app
+    }
+
+    #[test]
+    fn wide_crcs_keep_the_full_value() {
+        let ibm = build_app(CrcAlgorithm::Ibm16);
+        // the default is unchanged
+        assert_eq!(
+            ibm.world.resource::<FullCrc>().0,
+            ibm.world.resource::<Crc>().0 as u64
+        );
+        assert_eq!(
+            crc::Crc::<u16>::new(&CRC_16_IBM_SDLC).checksum(b"12"),
+            CrcAlgorithm::Ibm16.checksum(b"12") as u16
+        );
+
+        let xz = build_app(CrcAlgorithm::Crc64Xz);
+        let full = xz.world.resource::<FullCrc>().0;
+        assert!(full > u32::MAX as u64);
+        assert_eq!(xz.world.resource::<Crc>().0, full as u16);
+        assert_eq!(calculate_crc(&xz.world).unwrap(), full as u16);
+        assert_eq!(CrcAlgorithm::Crc64Xz.checksum(b"12"), full);
+        assert_eq!(TrackingConfig::from_world(&xz.world).crc, "CRC-64/XZ");
+
+        let iso = build_app(CrcAlgorithm::Crc32IsoHdlc);
+        assert_eq!(
+            iso.world.resource::<FullCrc>().0,
+            CrcAlgorithm::Crc32IsoHdlc.checksum(b"12")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use bevy_ecs::{component::ComponentId, entity::Entity, world::World};
+use std::collections::HashMap;
+
+use crate::{
+    checked,
+    report::{calculate_crc_scoped, EntityReport},
+    CrcScope, DesyncError, EnumerateEntities,
+};
+
+/// The smallest set of `(entity, component)` contributions which explains why `local` and
+/// `remote` hash differently: every tracked component whose hashed bytes differ from its
+/// counterpart, or which has no counterpart. Entities are paired up by `mapper`, which maps local
+/// entities to remote ones.
+///
+/// Entities and components are returned as they are in `local`. Components only the remote has
+/// are returned with the id of the same component in `local`, or left out if `local` never
+/// registered it, and entities only the remote has are returned as `Entity::PLACEHOLDER`.
+///
+/// With a commutative combine strategy removing exactly these contributions makes the CRCs match.
+/// With [`crate::CombineStrategy::Concatenate`] the CRCs can also differ because the entities are
+/// hashed in a different order, which no component is to blame for.
+///
+/// Fails if a tracked value in either world doesn't serialize
+pub fn minimal_blame(
+    local: &World,
+    remote: &World,
+    mapper: &impl EnumerateEntities,
+) -> Result<Vec<(Entity, ComponentId)>, DesyncError> {
+    checked(local, |local| {
+        checked(remote, |remote| blame_unchecked(local, remote, mapper))
+    })?
+}
+
+fn blame_unchecked(
+    local: &World,
+    remote: &World,
+    mapper: &impl EnumerateEntities,
+) -> Vec<(Entity, ComponentId)> {
+    let local_report = calculate_crc_scoped(local, CrcScope::Shared, false);
+    let remote_report = calculate_crc_scoped(remote, CrcScope::Shared, false);
+    let mut remote_entities = remote_report
+        .entities
+        .iter()
+        .map(|e| (e.entity, e))
+        .collect::<HashMap<_, _>>();
+    let mapped = mapper
+        .iter_entities()
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut blame = Vec::new();
+    for local_entity in local_report.entities.iter() {
+        let remote_entity = mapped
+            .get(&local_entity.entity)
+            .and_then(|e| remote_entities.remove(e));
+        let Some(remote_entity) = remote_entity else {
+            blame.extend(
+                local_entity
+                    .components
+                    .iter()
+                    .map(|c| (local_entity.entity, c.id)),
+            );
+            continue;
+        };
+        let local_components = components_by_name(local, local_entity);
+        let remote_components = components_by_name(remote, remote_entity);
+        // in hashing order, so the result is deterministic
+        for component in local_entity.components.iter() {
+            let name = component_name(local, component.id);
+            let remote_hashed = remote_components.get(name).map(|(_, hashed)| *hashed);
+            if remote_hashed != Some(component.hashed.as_slice()) {
+                blame.push((local_entity.entity, component.id));
+            }
+        }
+        for component in remote_entity.components.iter() {
+            let name = component_name(remote, component.id);
+            if local_components.contains_key(name) {
+                continue;
+            }
+            if let Some(id) = local_component_id(local, name) {
+                blame.push((local_entity.entity, id));
+            }
+        }
+    }
+
+    // whatever wasn't paired with a local entity
+    let mut unpaired = remote_entities.into_values().collect::<Vec<_>>();
+    unpaired.sort_by_key(|e| e.entity);
+    for remote_entity in unpaired {
+        for component in remote_entity.components.iter() {
+            if let Some(id) = local_component_id(local, component_name(remote, component.id)) {
+                blame.push((Entity::PLACEHOLDER, id));
+            }
+        }
+    }
+    blame
+}
+
+/// `name -> (id, hashed)` for each component in the report, ids being from `world`
+fn components_by_name<'a>(
+    world: &'a World,
+    entity: &'a EntityReport,
+) -> HashMap<&'a str, (ComponentId, &'a [u8])> {
+    entity
+        .components
+        .iter()
+        .map(|c| (component_name(world, c.id), (c.id, c.hashed.as_slice())))
+        .collect()
+}
+
+fn component_name(world: &World, id: ComponentId) -> &str {
+    world.components().get_info(id).unwrap().name()
+}
+
+fn local_component_id(world: &World, name: &str) -> Option<ComponentId> {
+    world
+        .components()
+        .iter()
+        .find(|info| info.name() == name)
+        .map(|info| info.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{
+        component::Component,
+        entity::{EntityHashMap, EntityMapper},
+    };
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, CombineStrategy, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[derive(Component, Serialize)]
+    struct Armor(u32);
+
+    #[derive(Default)]
+    struct EntityMap(EntityHashMap<Entity>);
+
+    impl EntityMapper for EntityMap {
+        fn map_entity(&mut self, entity: Entity) -> Entity {
+            self.0[&entity]
+        }
+    }
+
+    impl EnumerateEntities for EntityMap {
+        fn iter_entities(&self) -> Vec<(Entity, Entity)> {
+            self.0.iter().map(|(a, b)| (*a, *b)).collect()
+        }
+    }
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            combine: CombineStrategy::Xor,
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app.track_desync::<Armor>();
+        app
+    }
+
+    #[test]
+    fn single_component_divergence() {
+        let mut local = build_app();
+        let mut remote = build_app();
+        let mut map = EntityMap::default();
+        let mut local_entities = Vec::new();
+        // spawned in opposite orders, so only the map lines them up
+        for i in 0..3 {
+            local_entities.push(local.world.spawn((Health(i), Armor(i), TrackDesync)).id());
+        }
+        for i in (0..3).rev() {
+            let remote_entity = remote.world.spawn((Health(i), Armor(i), TrackDesync)).id();
+            map.0.insert(local_entities[i as usize], remote_entity);
+        }
+        assert!(minimal_blame(&local.world, &remote.world, &map)
+            .unwrap()
+            .is_empty());
+
+        local.world.get_mut::<Armor>(local_entities[1]).unwrap().0 = 9;
+        let armor = local.world.component_id::<Armor>().unwrap();
+        assert_eq!(
+            minimal_blame(&local.world, &remote.world, &map).unwrap(),
+            vec![(local_entities[1], armor)]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use bevy_ecs::{
+    archetype::ArchetypeId,
+    component::Tick,
+    entity::Entity,
+    system::Resource,
+    world::{Mut, World},
+};
+use std::collections::HashMap;
+
+use crate::{
+    report::{calculate_crc_scoped, EntityHasher},
+    CrcScope, DesyncError, DesyncPluginData, DesyncStatus, TrackingPredicate,
+};
+
+/// Each archetype's combined contribution to the CRC, so archetypes whose entities haven't
+/// changed aren't serialized again. Only used with a commutative [`crate::CombineStrategy`],
+/// since the cached contributions are combined in whatever order the archetypes are stored.
+///
+/// An archetype is recomputed when its entities change, or when any of their tracked components
+/// have changed since the last CRC. The whole cache is dropped when [`DesyncPluginData`] changes,
+/// e.g. when a component is registered.
+#[derive(Debug, Default, Resource)]
+pub struct ArchetypeCrcCache {
+    archetypes: HashMap<ArchetypeId, CachedArchetype>,
+    /// Tick the cache was last brought up to date
+    last_run: Option<Tick>,
+    recomputed: usize,
+}
+
+#[derive(Debug)]
+struct CachedArchetype {
+    /// Entities in storage order when the contribution was calculated
+    entities: Vec<Entity>,
+    /// At the full width of the [`crate::CrcAlgorithm`]
+    crc: u64,
+    /// Length of the serialized components
+    bytes: usize,
+}
+
+impl ArchetypeCrcCache {
+    /// Number of archetypes which were recomputed for the last CRC
+    pub fn recomputed(&self) -> usize {
+        self.recomputed
+    }
+
+    pub fn clear(&mut self) {
+        self.archetypes.clear();
+        self.last_run = None;
+    }
+}
+
+/// Like [`crate::calculate_crc`], reusing the contributions in [`ArchetypeCrcCache`] for
+/// archetypes which haven't changed. Always matches a full recompute, with any
+/// [`crate::CrcAlgorithm`]. Falls back to a full recompute if the combine strategy isn't
+/// commutative, if descendants are hashed under the plugin's `include_hierarchy` option, or if a
+/// [`TrackingPredicate`] is in use. Fails like [`crate::calculate_crc`]
+pub fn calculate_crc_cached(world: &mut World) -> Result<u16, DesyncError> {
+    let errors = world.resource::<DesyncPluginData>().errors.clone();
+    // left over from something which didn't check
+    errors.take();
+    let crc = calculate_status_with::<ArchetypeCrcCache>(world).0.crc;
+    errors.take().map_or(Ok(crc), Err)
+}
+
+/// A cache of contributions to the CRC, brought up to date by [`calculate_status_with`]
+pub(crate) trait CrcCache: Resource + Default {
+    /// Tick the cache was last brought up to date, `None` if nothing is cached
+    fn last_run(&self) -> Option<Tick>;
+
+    /// Drop everything cached
+    fn invalidate(&mut self);
+
+    /// Hash again whatever changed since `last_run`, returning the status and full width CRC.
+    /// Contributions which failed to serialize mustn't be kept, as they were hashed from partial
+    /// bytes and the error has to be hit again next time
+    fn update(&mut self, world: &World, last_run: Tick, this_run: Tick) -> (DesyncStatus, u64);
+}
+
+/// Calculate the status and full width CRC through the cache `C`, inserting it if it's missing.
+/// Falls back to a full recompute if the combine strategy isn't commutative, as cached
+/// contributions are combined out of order, if descendants are hashed under the plugin's
+/// `include_hierarchy` option, or if a [`TrackingPredicate`] is in use, as the predicate may
+/// depend on anything in the world. The whole cache is dropped when [`DesyncPluginData`] changes,
+/// e.g. when a component is registered. The tick is left at zero
+pub(crate) fn calculate_status_with<C: CrcCache>(world: &mut World) -> (DesyncStatus, u64) {
+    let desync_data = world.resource::<DesyncPluginData>();
+    if !desync_data.combine.is_commutative()
+        || desync_data.includes_hierarchy()
+        || world.contains_resource::<TrackingPredicate>()
+    {
+        let report = calculate_crc_scoped(world, CrcScope::Shared, true);
+        return (DesyncStatus::from_report(&report), report.full_crc);
+    }
+    world.init_resource::<C>();
+    let this_run = world.change_tick();
+    let status = world.resource_scope(|world: &mut World, mut cache: Mut<C>| {
+        let last_run = match cache.last_run() {
+            Some(last_run)
+                if !world
+                    .get_resource_change_ticks::<DesyncPluginData>()
+                    .unwrap()
+                    .is_changed(last_run, this_run) =>
+            {
+                last_run
+            }
+            _ => {
+                cache.invalidate();
+                // nothing is cached, so this is never consulted
+                this_run
+            }
+        };
+        cache.update(world, last_run, this_run)
+    });
+    // anything changed from here on is newer than the cache
+    world.increment_change_tick();
+    status
+}
+
+impl CrcCache for ArchetypeCrcCache {
+    fn last_run(&self) -> Option<Tick> {
+        self.last_run
+    }
+
+    fn invalidate(&mut self) {
+        self.clear();
+    }
+
+    fn update(&mut self, world: &World, last_run: Tick, this_run: Tick) -> (DesyncStatus, u64) {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let (combine, algorithm) = (desync_data.combine, desync_data.crc_algorithm);
+        self.recomputed = 0;
+
+        let mut status = DesyncStatus::default();
+        let mut combined = 0u64;
+        let mut hasher = EntityHasher::default();
+        for archetype in world
+            .archetypes()
+            .iter()
+            .filter(|a| desync_data.tracks_archetype(a, world))
+        {
+            let entities = archetype
+                .entities()
+                .iter()
+                .map(|e| e.id())
+                .collect::<Vec<_>>();
+            let cached = self.archetypes.get(&archetype.id()).filter(|cached| {
+                cached.entities == entities
+                    && !entities.iter().any(|entity| {
+                        let entity = world.entity(*entity);
+                        archetype
+                            .components()
+                            .filter(|c| desync_data.is_tracked(c, CrcScope::Shared))
+                            .any(|c| {
+                                entity
+                                    .get_change_ticks_by_id(c)
+                                    .unwrap()
+                                    .is_changed(last_run, this_run)
+                            })
+                    })
+            });
+            status.entity_count += entities.len() as u32;
+            let (crc, bytes) = match cached {
+                Some(cached) => (cached.crc, cached.bytes),
+                None => {
+                    let mut crc = 0u64;
+                    let mut bytes = 0;
+                    for entity in entities.iter() {
+                        hasher.hash(world, *entity, CrcScope::Shared);
+                        crc = combine.fold(crc, hasher.crc_with(algorithm));
+                        bytes += hasher.byte_count();
+                    }
+                    self.recomputed += 1;
+                    if desync_data.errors.is_set() {
+                        self.archetypes.remove(&archetype.id());
+                        continue;
+                    }
+                    self.archetypes.insert(
+                        archetype.id(),
+                        CachedArchetype {
+                            entities,
+                            crc,
+                            bytes,
+                        },
+                    );
+                    (crc, bytes)
+                }
+            };
+            combined = combine.fold(combined, crc);
+            status.byte_count += bytes as u32;
+        }
+        self.last_run = Some(this_run);
+
+        let global_input = desync_data.serialize_global_input(world);
+        if !global_input.is_empty() {
+            combined = combine.fold(combined, algorithm.checksum(&global_input));
+        }
+        let full_crc = algorithm.truncate(combined);
+        status.crc = full_crc as u16;
+        (status, full_crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{
+        calculate_crc, calculate_crc_detailed, AppDesyncExt, CombineStrategy, Crc, CrcAlgorithm,
+        CrcHistory, DesyncPlugin, FullCrc, TrackDesync,
+    };
+
+    #[derive(Component, Serialize)]
+    struct Foo(u64);
+
+    #[derive(Component, Serialize)]
+    struct Velocity(i32);
+
+    #[derive(Component)]
+    struct Flaky(bool);
+
+    impl Serialize for Flaky {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0 {
+                true => Err(serde::ser::Error::custom("flaked")),
+                false => serializer.serialize_unit(),
+            }
+        }
+    }
+
+    #[test]
+    fn cached_crc_matches_full_recompute() {
+        for (combine, crc_algorithm) in [
+            (CombineStrategy::Xor, CrcAlgorithm::Ibm16),
+            (CombineStrategy::Sum, CrcAlgorithm::Ibm16),
+            (CombineStrategy::Sum, CrcAlgorithm::Crc64Xz),
+        ] {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                combine,
+                crc_algorithm,
+                cache_archetypes: true,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            app.track_desync::<Velocity>();
+
+            // static archetype
+            for i in 0..10 {
+                app.world.spawn((Foo(i), TrackDesync));
+            }
+            // dynamic archetype
+            let moving = (0..3)
+                .map(|i| app.world.spawn((Foo(i), Velocity(1), TrackDesync)).id())
+                .collect::<Vec<_>>();
+
+            app.update();
+            assert_eq!(app.world.resource::<ArchetypeCrcCache>().recomputed(), 2);
+            assert_eq!(
+                app.world.resource::<Crc>().0,
+                calculate_crc(&app.world).unwrap()
+            );
+
+            for tick in 0..5 {
+                for entity in moving.iter() {
+                    if let Some(mut velocity) = app.world.get_mut::<Velocity>(*entity) {
+                        velocity.0 += tick;
+                    }
+                }
+                if tick == 2 {
+                    // move an entity into the static archetype
+                    app.world.entity_mut(moving[0]).remove::<Velocity>();
+                }
+                if tick == 4 {
+                    app.world.despawn(moving[1]);
+                }
+                app.update();
+                assert_eq!(
+                    app.world.resource::<Crc>().0,
+                    calculate_crc(&app.world).unwrap()
+                );
+                assert_eq!(
+                    app.world.resource::<FullCrc>().0,
+                    calculate_crc_detailed(&app.world).unwrap().full_crc
+                );
+                assert_eq!(
+                    *app.world.resource::<DesyncStatus>(),
+                    DesyncStatus {
+                        tick: tick as u64 + 1,
+                        ..DesyncStatus::from_report(&calculate_crc_detailed(&app.world).unwrap())
+                    }
+                );
+                let expected = if tick == 2 { 2 } else { 1 };
+                assert_eq!(
+                    app.world.resource::<ArchetypeCrcCache>().recomputed(),
+                    expected
+                );
+            }
+
+            // registering a component invalidates everything
+            #[derive(Component, Serialize)]
+            struct Health(u8);
+            app.track_desync::<Health>();
+            app.update();
+            assert_eq!(app.world.resource::<ArchetypeCrcCache>().recomputed(), 2);
+            assert_eq!(
+                app.world.resource::<Crc>().0,
+                calculate_crc(&app.world).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn failed_contributions_not_cached() {
+        for plugin in [
+            DesyncPlugin {
+                cache_archetypes: true,
+                ..Default::default()
+            },
+            DesyncPlugin {
+                incremental: true,
+                ..Default::default()
+            },
+        ] {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                combine: CombineStrategy::Xor,
+                ..plugin
+            })
+            .track_desync::<Foo>();
+            app.track_desync::<Flaky>();
+            app.world.spawn((Foo(0), TrackDesync));
+            let flaky = app.world.spawn((Foo(1), Flaky(true), TrackDesync)).id();
+            app.update();
+            app.update();
+            // neither tick was recorded, though nothing changed for the second
+            assert!(app.world.resource::<CrcHistory>().is_empty());
+            assert_eq!(app.world.resource::<Crc>().0, 0);
+
+            app.world.get_mut::<Flaky>(flaky).unwrap().0 = false;
+            app.update();
+            assert_eq!(
+                app.world.resource::<FullCrc>().0,
+                calculate_crc_detailed(&app.world).unwrap().full_crc
+            );
+            assert_eq!(app.world.resource::<CrcHistory>().len(), 1);
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! Canonical JSON used as the hash input for serialized components and resources. Unlike
+//! `serde_json`'s output this doesn't depend on field declaration order or on how a particular
+//! `serde_json` version formats numbers, so golden CRCs stay reproducible across dependency
+//! updates:
+//! * object keys are sorted by their UTF-8 bytes
+//! * no whitespace
+//! * integers are written in decimal, floats with Rust's shortest round-trip formatting (always
+//!   including a `.` or exponent), and non-finite floats as the bare tokens `!nan`, `!inf` and
+//!   `!-inf`, so they don't all hash as `null`, nor as a string some other value could hold
+//! * enums are externally tagged, as with `serde_json`
+use serde::ser::{self, Serialize};
+use std::fmt::{self, Display, Write};
+
+/// Serialize a value as canonical JSON
+pub fn to_canonical_json<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let node = value.serialize(NodeSerializer)?;
+    let mut out = String::new();
+    node.write(&mut out);
+    Ok(out)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalError(String);
+
+impl Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CanonicalError {}
+
+impl ser::Error for CanonicalError {
+    fn custom<T: Display>(msg: T) -> Self {
+        CanonicalError(msg.to_string())
+    }
+}
+
+/// Intermediate tree, so objects can be sorted before writing
+enum Node {
+    Null,
+    Bool(bool),
+    /// Integers and floats, already formatted
+    Number(String),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn write(&self, out: &mut String) {
+        match self {
+            Node::Null => out.push_str("null"),
+            Node::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Node::Number(n) => out.push_str(n),
+            Node::String(s) => write_string(s, out),
+            Node::Array(elements) => {
+                out.push('[');
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    element.write(out);
+                }
+                out.push(']');
+            }
+            Node::Object(entries) => {
+                let mut entries = entries.iter().collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+                out.push('{');
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(key, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    /// Map keys must be strings, so anything else is keyed by its canonical JSON
+    fn into_key(self) -> String {
+        match self {
+            Node::String(s) => s,
+            Node::Number(n) => n,
+            node => {
+                let mut out = String::new();
+                node.write(&mut out);
+                out
+            }
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn float(value: f64, formatted: String) -> Node {
+    if value.is_finite() {
+        Node::Number(formatted)
+    } else {
+        Node::Number(non_finite_token(value).to_string())
+    }
+}
+
+/// Written in place of a non-finite float. Not valid JSON, like [`crate::MISSING_ENTITY_SENTINEL`],
+/// so no string or number serializes the same
+fn non_finite_token(value: f64) -> &'static str {
+    if value.is_nan() {
+        "!nan"
+    } else if value > 0.0 {
+        "!inf"
+    } else {
+        "!-inf"
+    }
+}
+
+fn tagged(variant: &'static str, value: Node) -> Node {
+    Node::Object(vec![(variant.to_string(), value)])
+}
+
+struct NodeSerializer;
+
+type Result<T> = std::result::Result<T, CanonicalError>;
+
+impl ser::Serializer for NodeSerializer {
+    type Ok = Node;
+    type Error = CanonicalError;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = SeqBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = MapBuilder;
+    type SerializeStructVariant = MapBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Node> {
+        Ok(Node::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Node> {
+        Ok(Node::Number(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Node> {
+        Ok(float(v as f64, format!("{v:?}")))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Node> {
+        Ok(float(v, format!("{v:?}")))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Node> {
+        Ok(Node::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Node> {
+        Ok(Node::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Node> {
+        Ok(Node::Array(
+            v.iter().map(|b| Node::Number(b.to_string())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Node> {
+        Ok(Node::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Node> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Node> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder> {
+        Ok(SeqBuilder::new(None, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder> {
+        Ok(SeqBuilder::new(None, len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqBuilder> {
+        Ok(SeqBuilder::new(None, len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqBuilder> {
+        Ok(SeqBuilder::new(Some(variant), len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapBuilder> {
+        Ok(MapBuilder::new(None, len.unwrap_or(0)))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapBuilder> {
+        Ok(MapBuilder::new(None, len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapBuilder> {
+        Ok(MapBuilder::new(Some(variant), len))
+    }
+}
+
+struct SeqBuilder {
+    variant: Option<&'static str>,
+    elements: Vec<Node>,
+}
+
+impl SeqBuilder {
+    fn new(variant: Option<&'static str>, len: usize) -> Self {
+        SeqBuilder {
+            variant,
+            elements: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.elements.push(value.serialize(NodeSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Node> {
+        let array = Node::Array(self.elements);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, array),
+            None => array,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+struct MapBuilder {
+    variant: Option<&'static str>,
+    entries: Vec<(String, Node)>,
+    key: Option<String>,
+}
+
+impl MapBuilder {
+    fn new(variant: Option<&'static str>, len: usize) -> Self {
+        MapBuilder {
+            variant,
+            entries: Vec::with_capacity(len),
+            key: None,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.entries
+            .push((key.to_string(), value.serialize(NodeSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Node> {
+        let object = Node::Object(self.entries);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, object),
+            None => object,
+        })
+    }
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key.serialize(NodeSerializer)?.into_key());
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| CanonicalError("map value serialized before its key".to_string()))?;
+        self.push(&key, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapBuilder {
+    type Ok = Node;
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Ab {
+        a: f32,
+        b: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct Ba {
+        b: Vec<u8>,
+        a: f32,
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Unit,
+        Newtype(i8),
+        Tuple(u8, u8),
+        Struct { y: bool, x: Option<char> },
+    }
+
+    #[test]
+    fn field_order_independent() {
+        let ab = to_canonical_json(&Ab {
+            a: 1.0,
+            b: vec![1, 2],
+        })
+        .unwrap();
+        let ba = to_canonical_json(&Ba {
+            b: vec![1, 2],
+            a: 1.0,
+        })
+        .unwrap();
+        assert_eq!(ab, r#"{"a":1.0,"b":[1,2]}"#);
+        assert_eq!(ab, ba);
+    }
+
+    #[test]
+    fn enums_and_escapes() {
+        let shapes = vec![
+            Shape::Unit,
+            Shape::Newtype(-1),
+            Shape::Tuple(1, 2),
+            Shape::Struct { y: true, x: None },
+        ];
+        assert_eq!(
+            to_canonical_json(&shapes).unwrap(),
+            r#"["Unit",{"Newtype":-1},{"Tuple":[1,2]},{"Struct":{"x":null,"y":true}}]"#
+        );
+        assert_eq!(to_canonical_json("a\"\n\u{1}").unwrap(), r#""a\"\n\u0001""#);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            to_canonical_json(&(1e21f64, 0.1f32, f64::NAN, u128::MAX)).unwrap(),
+            format!(r#"[1e21,0.1,!nan,{}]"#, u128::MAX)
+        );
+        assert_eq!(
+            to_canonical_json(&(f32::INFINITY, f64::NEG_INFINITY)).unwrap(),
+            r#"[!inf,!-inf]"#
+        );
+    }
+
+    #[test]
+    fn map_keys_sorted() {
+        let map = (0..20).map(|i| (i, i)).collect::<HashMap<u8, u8>>();
+        let json = to_canonical_json(&map).unwrap();
+        // sorted as strings
+        assert!(json.starts_with(r#"{"0":0,"1":1,"10":10,"11":11"#));
+        assert_eq!(json, to_canonical_json(&map.clone()).unwrap());
+    }
+}
@@ -0,0 +1,165 @@
+use bevy_ecs::{event::Event, system::Resource, world::World};
+use bevy_utils::tracing::error;
+use serde_json::Value;
+
+use crate::{
+    calculate_crc_detailed, export::report_to_json, CrcHistory, CrcMessage, DesyncReport,
+    DesyncTick, PeerId,
+};
+
+/// The local tracked state captured when a desync against a remote was detected, e.g. for a crash
+/// reporter to attach. The state is captured at detection, which is usually a few ticks after the
+/// tick which desynced, as the remote's CRC takes a while to arrive
+#[derive(Clone, Debug, PartialEq, Event)]
+pub struct DesyncCapture {
+    /// Tick whose CRC didn't match
+    pub tick: u64,
+    /// [`DesyncTick`] when the state was captured
+    pub captured_at: u64,
+    /// Peer the CRC came from, if it was compared with [`crate::compare_peer_crc`]
+    pub peer: Option<PeerId>,
+    /// CRC recorded locally for `tick`
+    pub local_crc: u16,
+    /// CRC the remote sent for `tick`
+    pub remote_crc: u16,
+    /// Every tracked component, with its readable form
+    pub report: DesyncReport,
+    /// The tracked state as [`crate::tracked_state_to_json`] documents it
+    pub json: Value,
+    /// The remote's state, if the networking layer fetched it and handed it to
+    /// [`DesyncCaptures::attach_remote`]
+    pub remote: Option<Value>,
+}
+
+/// Rate limits [`DesyncCapture`]s, added by the plugin's `capture_on_desync` option. Once a desync
+/// has been captured, nothing more is captured for `cooldown` ticks, as a desynced peer usually
+/// mismatches every tick after the first.
+#[derive(Clone, Debug, Resource)]
+pub struct DesyncCaptures {
+    cooldown: u64,
+    latest: Option<DesyncCapture>,
+}
+
+impl DesyncCaptures {
+    pub fn new(cooldown: u64) -> Self {
+        DesyncCaptures {
+            cooldown,
+            latest: None,
+        }
+    }
+
+    /// The most recent capture, which is also sent as an event
+    pub fn latest(&self) -> Option<&DesyncCapture> {
+        self.latest.as_ref()
+    }
+
+    /// Attach the remote's state to the latest capture, e.g. once it has been requested from the
+    /// peer. Returns whether there was a capture to attach it to
+    pub fn attach_remote(&mut self, remote: Value) -> bool {
+        let Some(latest) = &mut self.latest else {
+            return false;
+        };
+        latest.remote = Some(remote);
+        true
+    }
+
+    fn ready(&self, now: u64) -> bool {
+        match &self.latest {
+            None => true,
+            Some(latest) => now >= latest.captured_at.saturating_add(self.cooldown),
+        }
+    }
+}
+
+/// Capture the tracked state for a mismatching remote CRC, unless a desync was captured within the
+/// cooldown
+pub(crate) fn capture_desync(world: &mut World, remote: CrcMessage, peer: Option<PeerId>) {
+    let now = world.resource::<DesyncTick>().0;
+    if !world.resource::<DesyncCaptures>().ready(now) {
+        return;
+    }
+    let Some(local_crc) = world.resource::<CrcHistory>().get(remote.tick) else {
+        return;
+    };
+    let report = match calculate_crc_detailed(world) {
+        Ok(report) => report,
+        Err(error) => {
+            error!(
+                "couldn't capture the desync at tick {}: {error}",
+                remote.tick
+            );
+            return;
+        }
+    };
+    let capture = DesyncCapture {
+        tick: remote.tick,
+        captured_at: now,
+        peer,
+        local_crc,
+        remote_crc: remote.crc,
+        json: report_to_json(world, &report),
+        report,
+        remote: None,
+    };
+    world.resource_mut::<DesyncCaptures>().latest = Some(capture.clone());
+    world.send_event(capture);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{
+        component::Component,
+        event::{Events, ManualEventReader},
+    };
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, CrcMessages, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u32);
+
+    #[test]
+    fn captured_once_at_first_detection() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            message_queue: true,
+            capture_on_desync: Some(100),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.world.spawn((Foo(7), TrackDesync));
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+        app.world
+            .resource_mut::<CrcMessages>()
+            .receive(CrcMessage { tick: 0, crc });
+        app.update();
+        assert!(app.world.resource::<DesyncCaptures>().latest().is_none());
+
+        // the remote mismatches on every tick from here on
+        let mut reader = ManualEventReader::<DesyncCapture>::default();
+        let mut captures = Vec::new();
+        for tick in 1..10 {
+            let crc = app.world.resource::<Crc>().0;
+            app.world
+                .resource_mut::<CrcMessages>()
+                .receive(CrcMessage { tick, crc: !crc });
+            app.update();
+            let events = app.world.resource::<Events<DesyncCapture>>();
+            captures.extend(reader.read(events).cloned());
+        }
+        assert_eq!(captures.len(), 1);
+        let capture = &captures[0];
+        assert_eq!(capture.tick, 1);
+        assert_eq!(capture.captured_at, 2);
+        assert_eq!(capture.remote_crc, !capture.local_crc);
+        assert_eq!(capture.report.entities.len(), 1);
+        assert_eq!(capture.report.entities[0].components[0].serialized, "7");
+        assert_eq!(
+            app.world.resource::<DesyncCaptures>().latest(),
+            Some(capture)
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use bevy_ecs::{
+    component::{ComponentId, Tick},
+    entity::Entity,
+    system::Resource,
+    world::{Mut, World},
+};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{get_tracked_components, is_hashed, unordered_tracked_entities, DesyncPluginData};
+
+/// How many times each tracked component type's serialized value changed over the last few CRCs,
+/// added by the plugin's `churn_window` option. A component changing far more often than the
+/// simulation should change it, e.g. one recomputed from a `HashMap`'s iteration order, is a likely
+/// desync culprit.
+///
+/// Only components whose change ticks say they were written are serialized again, and writes
+/// which leave the serialized value as it was aren't counted.
+#[derive(Clone, Debug, Resource)]
+pub struct ComponentChurn {
+    window: usize,
+    /// Changes by type name for each of the last `window` CRCs, oldest first
+    ticks: VecDeque<HashMap<String, u32>>,
+    /// Hash of each component's serialized value when it was last seen
+    last: HashMap<(Entity, ComponentId), u64>,
+    last_run: Option<Tick>,
+}
+
+impl ComponentChurn {
+    pub fn new(window: usize) -> Self {
+        ComponentChurn {
+            window,
+            ticks: VecDeque::with_capacity(window),
+            last: HashMap::new(),
+            last_run: None,
+        }
+    }
+
+    /// Number of changes to the component type named `name` within the window
+    pub fn count(&self, name: &str) -> u32 {
+        self.ticks.iter().filter_map(|tick| tick.get(name)).sum()
+    }
+
+    /// Number of changes within the window for every component type which changed, most changed
+    /// first, ties by name
+    pub fn counts(&self) -> Vec<(String, u32)> {
+        let mut totals = HashMap::<&str, u32>::new();
+        for (name, count) in self.ticks.iter().flatten() {
+            *totals.entry(name).or_default() += count;
+        }
+        let mut counts = totals
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+}
+
+/// Count the tracked components whose serialized value changed since the last call, called by
+/// `update_crc`
+pub(crate) fn update_churn(world: &mut World) {
+    let this_run = world.change_tick();
+    world.resource_scope(|world, mut churn: Mut<ComponentChurn>| {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let mut changes = HashMap::new();
+        for entity in unordered_tracked_entities(world) {
+            if !is_hashed(entity, world) {
+                continue;
+            }
+            let entity_ref = world.entity(entity);
+            for id in get_tracked_components(entity, world) {
+                let written = match churn.last_run {
+                    Some(last_run) => entity_ref
+                        .get_change_ticks_by_id(id)
+                        .unwrap()
+                        .is_changed(last_run, this_run),
+                    // nothing seen yet
+                    None => true,
+                };
+                if !written {
+                    continue;
+                }
+                let ptr = entity_ref.get_by_id(id).unwrap();
+                let crc = desync_data
+                    .crc_algorithm
+                    .checksum(&desync_data.serialize(world, ptr, &id));
+                match churn.last.insert((entity, id), crc) {
+                    Some(last) if last != crc => {
+                        let name = world.components().get_info(id).unwrap().name();
+                        *changes.entry(name.to_string()).or_default() += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        churn
+            .last
+            .retain(|(entity, _), _| world.get_entity(*entity).is_some());
+        if churn.ticks.len() == churn.window {
+            churn.ticks.pop_front();
+        }
+        if churn.window > 0 {
+            churn.ticks.push_back(changes);
+        }
+        churn.last_run = Some(this_run);
+    });
+    // anything changed from here on is newer than the last run
+    world.increment_change_tick();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, Update};
+    use bevy_ecs::{component::Component, system::Query};
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Flicker(u32);
+
+    #[derive(Component, Serialize)]
+    struct Steady(u32);
+
+    #[derive(Component, Serialize)]
+    struct Rewritten(u32);
+
+    #[test]
+    fn flickering_component_dominates() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            churn_window: Some(8),
+            ..Default::default()
+        })
+        .track_desync::<Flicker>();
+        app.track_desync::<Steady>();
+        app.track_desync::<Rewritten>();
+        app.add_systems(
+            Update,
+            |mut query: Query<(&mut Flicker, &mut Rewritten)>| {
+                for (mut flicker, mut rewritten) in query.iter_mut() {
+                    flicker.0 += 1;
+                    // written every tick, but never to a different value
+                    rewritten.0 = 5;
+                }
+            },
+        );
+        let entity = app
+            .world
+            .spawn((Flicker(0), Steady(0), Rewritten(5), TrackDesync))
+            .id();
+        for _ in 0..4 {
+            app.update();
+        }
+        app.world.get_mut::<Steady>(entity).unwrap().0 = 1;
+        for _ in 0..8 {
+            app.update();
+        }
+
+        let churn = app.world.resource::<ComponentChurn>();
+        let flicker = std::any::type_name::<Flicker>();
+        let steady = std::any::type_name::<Steady>();
+        assert_eq!(churn.count(flicker), 8);
+        assert_eq!(churn.count(steady), 1);
+        assert_eq!(churn.count(std::any::type_name::<Rewritten>()), 0);
+        assert_eq!(
+            churn.counts(),
+            vec![(flicker.to_string(), 8), (steady.to_string(), 1)]
+        );
+    }
+}
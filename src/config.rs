@@ -0,0 +1,290 @@
+use bevy_ecs::world::World;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{CombineStrategy, CrcAlgorithm, DesyncPluginData, HashEncoding};
+
+/// Everything about how an app hashes its world, as one comparable value. Two apps can only be
+/// expected to produce matching CRCs if their configs are equal, so exchanging configs with a peer
+/// on connect rules out a whole class of false positives.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackingConfig {
+    /// Type name of each tracked component, and how it's serialized
+    pub components: BTreeMap<String, String>,
+    /// Type names of the tracked resources
+    pub resources: Vec<String>,
+    /// Type names of the tracked event types
+    pub events: Vec<String>,
+    /// [`crate::DesyncPlugin::entity_sort_name`]
+    pub entity_sort: String,
+    pub combine: String,
+    pub float_options: String,
+    /// [`crate::HashEncoding::name`], for components which aren't registered with their own
+    /// serializer
+    pub hash_backend: String,
+    pub crc: String,
+}
+
+/// A setting which differs between two configs. `None` means the setting isn't present on that
+/// side, e.g. a component only one side tracks
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigDiff {
+    /// e.g. `component/my_game::Health` or `hash_backend`
+    pub key: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+impl TrackingConfig {
+    pub fn from_world(world: &World) -> Self {
+        Self::from_data(world.resource::<DesyncPluginData>(), world)
+    }
+
+    /// The config of `desync_data`, with component names from `world`
+    fn from_data(desync_data: &DesyncPluginData, world: &World) -> Self {
+        let components = desync_data
+            .serialize_fn_registry
+            .iter()
+            .map(|(id, fns)| {
+                let name = world.components().get_info(*id).unwrap().name().to_string();
+                let mut serializer = fns.serializer.to_string();
+                if fns.eq.is_some() {
+                    serializer.push_str("+eq");
+                }
+                if fns.authority_only {
+                    serializer.push_str("+authority-only");
+                }
+                (name, serializer)
+            })
+            .collect();
+        TrackingConfig {
+            components,
+            resources: desync_data
+                .resource_serialize_fn_registry
+                .keys()
+                .map(|name| name.to_string())
+                .collect(),
+            events: desync_data
+                .event_serialize_fn_registry
+                .keys()
+                .map(|name| name.to_string())
+                .collect(),
+            entity_sort: desync_data.entity_sort_name.clone(),
+            combine: format!("{:?}", desync_data.combine),
+            float_options: format!("{:?}", desync_data.float_options),
+            hash_backend: desync_data.hash_encoding.name().to_string(),
+            crc: desync_data.crc_algorithm.name().to_string(),
+        }
+    }
+
+    /// Every setting which differs from `other`, ordered by key
+    pub fn diff(&self, other: &Self) -> Vec<ConfigDiff> {
+        let a = self.entries();
+        let b = other.entries();
+        let mut keys = a.keys().chain(b.keys()).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| a.get(*key) != b.get(*key))
+            .map(|key| ConfigDiff {
+                key: key.clone(),
+                a: a.get(key).cloned(),
+                b: b.get(key).cloned(),
+            })
+            .collect()
+    }
+
+    /// Flatten into `key -> value`
+    fn entries(&self) -> BTreeMap<String, String> {
+        let mut entries = BTreeMap::new();
+        for (name, serializer) in self.components.iter() {
+            entries.insert(format!("component/{name}"), serializer.clone());
+        }
+        for name in self.resources.iter() {
+            entries.insert(format!("resource/{name}"), "tracked".to_string());
+        }
+        for name in self.events.iter() {
+            entries.insert(format!("event/{name}"), "tracked".to_string());
+        }
+        for (key, value) in [
+            ("entity_sort", &self.entity_sort),
+            ("combine", &self.combine),
+            ("float_options", &self.float_options),
+            ("hash_backend", &self.hash_backend),
+            ("crc", &self.crc),
+        ] {
+            entries.insert(key.to_string(), value.clone());
+        }
+        entries
+    }
+}
+
+impl DesyncPluginData {
+    /// The registration contract: the type names of everything tracked and the serializer each
+    /// was registered with, along with the settings which affect hashing. Unlike the registry it
+    /// can be persisted, e.g. next to dumped worlds, and turned back into a registry with
+    /// [`DesyncPluginData::from_contract`]. `world` is the world this registry belongs to
+    pub fn to_contract(&self, world: &World) -> TrackingConfig {
+        TrackingConfig::from_data(self, world)
+    }
+
+    /// Rebuild the registry a contract describes, e.g. in an offline tool. Serializers can't be
+    /// persisted, so they're taken from `registry`, a world where the tool has registered the same
+    /// types the same way, and possibly more. Registrations the contract doesn't list are dropped,
+    /// and the combine strategy, hash encoding and CRC algorithm are taken from the contract.
+    ///
+    /// The entity sort and float options are kept from `registry`, so must already match. If the
+    /// rebuilt registry still doesn't match the contract, e.g. because a type isn't registered or
+    /// is registered with another serializer, the differences are returned instead
+    pub fn from_contract(
+        contract: &TrackingConfig,
+        registry: &World,
+    ) -> Result<Self, Vec<ConfigDiff>> {
+        let mut desync_data = registry.resource::<DesyncPluginData>().clone();
+        desync_data.serialize_fn_registry.retain(|id, _| {
+            let name = registry.components().get_info(*id).unwrap().name();
+            contract.components.contains_key(name)
+        });
+        desync_data
+            .resource_serialize_fn_registry
+            .retain(|name, _| contract.resources.iter().any(|n| n == name));
+        desync_data
+            .event_serialize_fn_registry
+            .retain(|name, _| contract.events.iter().any(|n| n == name));
+        if let Some(combine) = [
+            CombineStrategy::Concatenate,
+            CombineStrategy::Xor,
+            CombineStrategy::Sum,
+        ]
+        .into_iter()
+        .find(|combine| format!("{combine:?}") == contract.combine)
+        {
+            desync_data.combine = combine;
+        }
+        if let Some(encoding) = [HashEncoding::CanonicalJson, HashEncoding::Postcard]
+            .into_iter()
+            .find(|encoding| encoding.name() == contract.hash_backend)
+        {
+            desync_data.hash_encoding = encoding;
+        }
+        if let Some(algorithm) = [
+            CrcAlgorithm::Ibm16,
+            CrcAlgorithm::Crc32IsoHdlc,
+            CrcAlgorithm::Crc64Xz,
+        ]
+        .into_iter()
+        .find(|algorithm| algorithm.name() == contract.crc)
+        {
+            desync_data.crc_algorithm = algorithm;
+        }
+
+        let diff = contract.diff(&desync_data.to_contract(registry));
+        match diff.is_empty() {
+            true => Ok(desync_data),
+            false => Err(diff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{component::Component, system::Resource};
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin};
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[derive(Component, Serialize)]
+    struct Secret(u32);
+
+    #[derive(Resource, Serialize)]
+    struct Score(u32);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Health>();
+        app.track_desync_resource::<Score>();
+        app
+    }
+
+    #[test]
+    fn identical_configs() {
+        let a = TrackingConfig::from_world(&build_app().world);
+        let b = TrackingConfig::from_world(&build_app().world);
+        assert_eq!(a, b);
+        assert!(a.diff(&b).is_empty());
+        assert_eq!(a.crc, crate::CrcAlgorithm::Ibm16.name());
+    }
+
+    #[test]
+    fn diff_configs() {
+        let mut app = build_app();
+        app.track_desync_authority_only::<Secret>();
+        let a = TrackingConfig::from_world(&build_app().world);
+        let mut b = TrackingConfig::from_world(&app.world);
+        b.hash_backend = "postcard".to_string();
+        b.crc = "CRC-32/ISO-HDLC".to_string();
+
+        let diff = a.diff(&b);
+        let keys = diff.iter().map(|d| d.key.as_str()).collect::<Vec<_>>();
+        let secret = format!("component/{}", std::any::type_name::<Secret>());
+        assert_eq!(keys, vec![&secret, "crc", "hash_backend"]);
+        assert_eq!(diff[0].a, None);
+        assert_eq!(diff[0].b.as_deref(), Some("serde+authority-only"));
+        assert_eq!(
+            diff[2].a.as_deref(),
+            Some(crate::HashEncoding::CanonicalJson.name())
+        );
+        assert_eq!(diff[2].b.as_deref(), Some("postcard"));
+    }
+
+    #[test]
+    fn contract_round_trip() {
+        let mut game = App::new();
+        game.add_plugins(DesyncPlugin {
+            combine: crate::CombineStrategy::Xor,
+            hash_encoding: HashEncoding::Postcard,
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        game.track_desync_resource::<Score>();
+        let contract = game
+            .world
+            .resource::<DesyncPluginData>()
+            .to_contract(&game.world);
+        let persisted = serde_json::to_string(&contract).unwrap();
+
+        // the tool registers everything it knows about, with default settings
+        let mut tool = build_app();
+        tool.track_desync::<Secret>();
+        let contract = serde_json::from_str(&persisted).unwrap();
+        let desync_data = DesyncPluginData::from_contract(&contract, &tool.world).unwrap();
+        tool.insert_resource(desync_data);
+        assert_eq!(TrackingConfig::from_world(&tool.world), contract);
+
+        for app in [&mut game, &mut tool] {
+            app.world.spawn((Health(3), Secret(1), crate::TrackDesync));
+            app.world.spawn((Health(4), crate::TrackDesync));
+            app.insert_resource(Score(10));
+        }
+        assert_eq!(
+            crate::calculate_crc(&game.world).unwrap(),
+            crate::calculate_crc(&tool.world).unwrap()
+        );
+
+        // a tool which can't reproduce the contract
+        let mut tool = App::new();
+        tool.add_plugins(DesyncPlugin::default())
+            .track_desync_resource::<Score>();
+        let Err(diff) = DesyncPluginData::from_contract(&contract, &tool.world) else {
+            panic!("Health isn't registered");
+        };
+        let health = format!("component/{}", std::any::type_name::<Health>());
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].key, health);
+    }
+}
@@ -0,0 +1,196 @@
+use bevy_ecs::{
+    component::{ComponentId, Tick},
+    entity::Entity,
+    world::World,
+};
+
+use crate::{checked, get_tracked_components, is_hashed, DesyncError, DesyncPluginData};
+
+/// Marks the point a peer last acknowledged. [`delta_crc`] hashes only what changed since then
+#[derive(Clone, Copy, Debug)]
+pub struct DesyncSnapshot {
+    tick: Tick,
+}
+
+impl DesyncSnapshot {
+    /// Snapshot the world as it is now. Any change made after this is part of the delta
+    pub fn take(world: &mut World) -> Self {
+        let tick = world.change_tick();
+        world.increment_change_tick();
+        DesyncSnapshot { tick }
+    }
+
+    /// The change tick the snapshot was taken at
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+}
+
+/// Hash only the tracked components which changed since `since_snapshot`, along with the
+/// [`crate::DesyncIdentity`] key of the entity each belongs to. Returns the low 16 bits of the
+/// hash, under the plugin's `crc_algorithm` like [`crate::Crc`], and the entities which changed,
+/// in `entity_sort` order.
+///
+/// Removing a component or despawning an entity isn't a change to any component, so neither shows
+/// up in the delta. Fails like [`crate::calculate_crc`]
+pub fn delta_crc(
+    world: &World,
+    since_snapshot: &DesyncSnapshot,
+) -> Result<(u16, Vec<Entity>), DesyncError> {
+    checked(world, |world| {
+        hash_changed(world, since_snapshot.tick, false)
+    })
+}
+
+/// Hash every tracked component of the entities which had any tracked component changed since
+/// `tick`, e.g. a [`DesyncSnapshot::tick`] or a tick stored from [`World::change_tick`], along
+/// with the [`crate::DesyncIdentity`] key of each. Returns the hash, 16 bits as in [`delta_crc`],
+/// and the entities which contributed, in `entity_sort` order. Unlike [`delta_crc`], the
+/// unchanged components of a changed entity are hashed too, so this checks the whole state of
+/// everything which changed. Fails like [`crate::calculate_crc`]
+pub fn calculate_crc_changed_since(
+    world: &World,
+    tick: Tick,
+) -> Result<(u16, Vec<Entity>), DesyncError> {
+    checked(world, |world| hash_changed(world, tick, true))
+}
+
+/// Hash the changed components since `since`, or every tracked component of entities with any
+/// changed with `whole_entities`
+fn hash_changed(world: &World, since: Tick, whole_entities: bool) -> (u16, Vec<Entity>) {
+    let desync_data = world.resource::<DesyncPluginData>();
+    let this_run = world.read_change_tick();
+    let mut digest = desync_data.crc_algorithm.digest();
+    let mut changed = Vec::new();
+    for entity in (desync_data.entity_sort)(world) {
+        if !is_hashed(entity, world) {
+            continue;
+        }
+        let entity_ref = world.entity(entity);
+        let components = get_tracked_components(entity, world);
+        let is_changed = |c: &ComponentId| {
+            let ticks = entity_ref.get_change_ticks_by_id(*c).unwrap();
+            ticks.is_changed(since, this_run)
+        };
+        if whole_entities && !components.iter().any(is_changed) {
+            continue;
+        }
+        let mut entity_changed = false;
+        for c in components.iter().copied() {
+            if !whole_entities && !is_changed(&c) {
+                continue;
+            }
+            if !entity_changed {
+                digest.update(&desync_data.identity.key(entity, world).to_le_bytes());
+                entity_changed = true;
+            }
+            let ptr = world.get_by_id(entity, c).unwrap();
+            digest.update(&desync_data.serialize(world, ptr, &c));
+        }
+        if entity_changed {
+            changed.push(entity);
+        }
+    }
+    (digest.finalize() as u16, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u64);
+
+    #[derive(Component, Serialize)]
+    struct Bar(u64);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        app.track_desync::<Bar>();
+        app
+    }
+
+    #[test]
+    fn delta_contains_changed_entities() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        let mut snapshots = Vec::new();
+        let mut entities = Vec::new();
+        for app in [&mut app_1, &mut app_2] {
+            entities = (0..4)
+                .map(|i| app.world.spawn((Foo(i), Bar(i), TrackDesync)).id())
+                .collect::<Vec<_>>();
+            snapshots.push(DesyncSnapshot::take(&mut app.world));
+        }
+        let (empty, changed) = delta_crc(&app_1.world, &snapshots[0]).unwrap();
+        assert!(changed.is_empty());
+
+        for app in [&mut app_1, &mut app_2] {
+            app.world.get_mut::<Foo>(entities[1]).unwrap().0 = 10;
+            app.world.get_mut::<Bar>(entities[3]).unwrap().0 = 10;
+        }
+        let (crc_1, changed) = delta_crc(&app_1.world, &snapshots[0]).unwrap();
+        assert_eq!(changed, vec![entities[1], entities[3]]);
+        assert_ne!(crc_1, empty);
+        assert_eq!(crc_1, delta_crc(&app_2.world, &snapshots[1]).unwrap().0);
+
+        // one peer changes another component
+        app_2.world.get_mut::<Foo>(entities[3]).unwrap().0 = 10;
+        assert_ne!(crc_1, delta_crc(&app_2.world, &snapshots[1]).unwrap().0);
+
+        // a new snapshot starts an empty delta
+        let snapshot = DesyncSnapshot::take(&mut app_1.world);
+        assert_eq!(
+            delta_crc(&app_1.world, &snapshot).unwrap(),
+            (empty, Vec::new())
+        );
+    }
+
+    #[test]
+    fn changed_since_hashes_whole_entities() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        let mut ticks = Vec::new();
+        let mut entities = Vec::new();
+        for app in [&mut app_1, &mut app_2] {
+            entities = (0..4)
+                .map(|i| app.world.spawn((Foo(i), Bar(i), TrackDesync)).id())
+                .collect::<Vec<_>>();
+        }
+        // differs before the reference tick, and isn't changed after it
+        app_2.world.get_mut::<Foo>(entities[2]).unwrap().0 = 20;
+        for app in [&mut app_1, &mut app_2] {
+            ticks.push(app.world.change_tick());
+            app.world.increment_change_tick();
+            app.world.get_mut::<Foo>(entities[0]).unwrap().0 = 10;
+            app.world.get_mut::<Bar>(entities[2]).unwrap().0 = 10;
+        }
+
+        let (crc_1, changed) = calculate_crc_changed_since(&app_1.world, ticks[0]).unwrap();
+        assert_eq!(changed, vec![entities[0], entities[2]]);
+        let (crc_2, changed) = calculate_crc_changed_since(&app_2.world, ticks[1]).unwrap();
+        assert_eq!(changed, vec![entities[0], entities[2]]);
+        // the unchanged Foo of a changed entity is part of the hash, but not the delta
+        assert_ne!(crc_1, crc_2);
+        let delta = |app: &mut App| {
+            let snapshot = DesyncSnapshot::take(&mut app.world);
+            app.world.get_mut::<Bar>(entities[2]).unwrap().0 = 11;
+            (
+                delta_crc(&app.world, &snapshot).unwrap().0,
+                calculate_crc_changed_since(&app.world, snapshot.tick()).unwrap(),
+            )
+        };
+        let (delta_1, changed_1) = delta(&mut app_1);
+        let (delta_2, changed_2) = delta(&mut app_2);
+        assert_eq!(delta_1, delta_2);
+        assert_ne!(changed_1, changed_2);
+        assert_eq!(changed_1.1, vec![entities[2]]);
+    }
+}
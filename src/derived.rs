@@ -0,0 +1,66 @@
+use bevy_app::App;
+use bevy_ecs::component::Component;
+use bevy_utils::all_tuples;
+use serde::Serialize;
+
+/// A component which knows how to track itself, implemented by `#[derive(TrackDesync)]`. As the
+/// derive requires `Serialize`, a component which can't be hashed fails to compile where it's
+/// declared, rather than wherever it's registered
+pub trait DesyncComponent: Component + Serialize {
+    fn track_desync(app: &mut App);
+}
+
+/// One [`DesyncComponent`], or a tuple of them, tracked together by
+/// [`crate::AppDesyncExt::track_desync_derived`]
+pub trait DesyncComponents {
+    fn track_desync_all(app: &mut App);
+}
+
+impl<T: DesyncComponent> DesyncComponents for T {
+    fn track_desync_all(app: &mut App) {
+        T::track_desync(app);
+    }
+}
+
+macro_rules! impl_desync_components {
+    ($($T:ident),*) => {
+        impl<$($T: DesyncComponents),*> DesyncComponents for ($($T,)*) {
+            #[allow(unused_variables)]
+            fn track_desync_all(app: &mut App) {
+                $($T::track_desync_all(app);)*
+            }
+        }
+    };
+}
+
+all_tuples!(impl_desync_components, 0, 15, T);
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{calculate_crc_detailed, AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize, TrackDesync)]
+    struct Position(i32);
+
+    #[derive(Component, Serialize, TrackDesync)]
+    struct Velocity(i32);
+
+    #[derive(Component, Serialize, TrackDesync)]
+    struct Health(u32);
+
+    #[test]
+    fn derived_components_tracked() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_derived::<(Position, (Velocity,))>();
+        app.track_desync_derived::<Health>();
+        app.world
+            .spawn((Position(1), Velocity(2), Health(3), TrackDesync));
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        assert_eq!(report.entities[0].components.len(), 3);
+    }
+}
@@ -0,0 +1,97 @@
+use bevy_ecs::{reflect::AppTypeRegistry, world::World};
+use bevy_reflect::{Reflect, ReflectSerialize};
+use bevy_utils::tracing::warn;
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::encoding::{encode_value, HashOptions};
+
+/// Concrete types which have already been warned about, so an unregistered type is only reported
+/// once rather than every tick
+#[derive(Default)]
+pub(crate) struct Unregistered(Mutex<HashSet<TypeId>>);
+
+/// Serialize a reflected value through the `ReflectSerialize` registered for its concrete type.
+/// The value is hashed along with the type's path. Values whose type isn't registered, or doesn't
+/// reflect `Serialize`, are skipped: they hash as nothing, so peers holding different unregistered
+/// types can't be told apart
+pub(crate) fn serialize_dyn(
+    value: &dyn Reflect,
+    world: &World,
+    options: &HashOptions,
+    unregistered: &Unregistered,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    let registry = world.resource::<AppTypeRegistry>().read();
+    let type_id = value.as_any().type_id();
+    let Some(reflect_serialize) = registry.get_type_data::<ReflectSerialize>(type_id) else {
+        if unregistered.0.lock().unwrap().insert(type_id) {
+            warn!(
+                "{} isn't registered with #[reflect(Serialize)], it won't be hashed",
+                value.reflect_type_path()
+            );
+        }
+        return Ok(());
+    };
+    // keyed by the type, so concrete types which happen to serialize the same still differ
+    let serializable = reflect_serialize.get_serializable(value);
+    let typed = BTreeMap::from([(value.reflect_type_path(), serializable.borrow())]);
+    encode_value(&typed, options, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    trait Shape: Reflect {}
+
+    #[derive(Reflect, Serialize)]
+    #[reflect(Serialize)]
+    struct Circle {
+        size: u32,
+    }
+
+    #[derive(Reflect, Serialize)]
+    #[reflect(Serialize)]
+    struct Square {
+        size: u32,
+    }
+
+    #[derive(Reflect)]
+    struct Triangle(u32);
+
+    impl Shape for Circle {}
+    impl Shape for Square {}
+    impl Shape for Triangle {}
+
+    #[derive(Component)]
+    struct Body(Box<dyn Shape>);
+
+    fn crc(shape: impl Shape) -> u16 {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .register_type::<Circle>()
+            .register_type::<Square>()
+            .track_desync_dyn::<Body>(|body| body.0.as_reflect());
+        app.world.spawn((Body(Box::new(shape)), TrackDesync));
+        app.update();
+        app.world.resource::<Crc>().0
+    }
+
+    #[test]
+    fn concrete_types_hash_differently() {
+        assert_eq!(crc(Circle { size: 1 }), crc(Circle { size: 1 }));
+        assert_ne!(crc(Circle { size: 1 }), crc(Circle { size: 2 }));
+        assert_ne!(crc(Circle { size: 1 }), crc(Square { size: 1 }));
+        // skipped, so indistinguishable
+        assert_eq!(crc(Triangle(1)), crc(Triangle(2)));
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Serialize;
+
+use crate::{serialize_value, FloatOptions, WithFloatOptions};
+
+/// How tracked components are encoded into the bytes which are hashed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashEncoding {
+    /// [Canonical JSON](crate::to_canonical_json). Readable as is in reports, and map entries are
+    /// sorted so a `HashMap`'s iteration order doesn't matter
+    #[default]
+    CanonicalJson,
+    /// postcard's compact binary format, written straight into the hash buffer. Much cheaper than
+    /// JSON when tracking thousands of entities, but map entries are encoded in iteration order,
+    /// so components holding a `HashMap` hash differently between peers. Reports still show JSON
+    Postcard,
+}
+
+impl HashEncoding {
+    /// Name of the encoding, as in [`crate::TrackingConfig::hash_backend`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashEncoding::CanonicalJson => "canonical-json",
+            HashEncoding::Postcard => "postcard",
+        }
+    }
+}
+
+/// What a registered serializer needs to know about how to encode a component
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HashOptions {
+    pub float_options: FloatOptions,
+    pub encoding: HashEncoding,
+}
+
+/// Append the encoded form of a tracked value to `out`. On failure `out` may be left empty
+pub(crate) fn encode_value<T: Serialize + ?Sized>(
+    value: &T,
+    options: &HashOptions,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    match options.encoding {
+        HashEncoding::CanonicalJson => {
+            out.extend_from_slice(serialize_value(value, &options.float_options)?.as_bytes())
+        }
+        HashEncoding::Postcard => {
+            let buffer = std::mem::take(out);
+            *out = match options.float_options.is_passthrough() {
+                true => postcard::to_extend(value, buffer),
+                false => postcard::to_extend(
+                    &WithFloatOptions::new(value, &options.float_options),
+                    buffer,
+                ),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+
+    use super::*;
+    use crate::{
+        calculate_crc, calculate_crc_detailed, AppDesyncExt, DesyncPlugin, TrackDesync,
+        TrackingConfig,
+    };
+
+    #[derive(Component, Serialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    fn build_app(encoding: HashEncoding) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            hash_encoding: encoding,
+            ..Default::default()
+        })
+        .track_desync::<Position>();
+        app
+    }
+
+    #[test]
+    fn postcard_is_compact_and_detects_desyncs() {
+        let mut json = build_app(HashEncoding::CanonicalJson);
+        let mut app_1 = build_app(HashEncoding::Postcard);
+        let mut app_2 = build_app(HashEncoding::Postcard);
+        for app in [&mut json, &mut app_1, &mut app_2] {
+            app.world.spawn((Position { x: 1.5, y: 2.0 }, TrackDesync));
+        }
+        assert_eq!(
+            calculate_crc(&app_1.world).unwrap(),
+            calculate_crc(&app_2.world).unwrap()
+        );
+        app_2
+            .world
+            .spawn((Position { x: 0.0, y: 0.0 }, TrackDesync));
+        assert_ne!(
+            calculate_crc(&app_1.world).unwrap(),
+            calculate_crc(&app_2.world).unwrap()
+        );
+
+        let json_report = calculate_crc_detailed(&json.world).unwrap();
+        let report = calculate_crc_detailed(&app_1.world).unwrap();
+        // two little endian f32s
+        assert_eq!(report.entities[0].components[0].hashed.len(), 8);
+        assert!(report.byte_count() < json_report.byte_count());
+        // reports stay readable
+        assert_eq!(
+            report.entities[0].components[0].serialized,
+            json_report.entities[0].components[0].serialized
+        );
+        assert_eq!(
+            TrackingConfig::from_world(&app_1.world).hash_backend,
+            "postcard"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use bevy_ecs::entity::Entity;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Why a CRC couldn't be calculated, returned by [`crate::calculate_crc`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DesyncError {
+    /// A tracked component, resource or event failed to serialize
+    Serialize { type_name: String, error: String },
+    /// A tracked entity is missing under [`crate::MissingEntityPolicy::Error`]
+    MissingEntity(Entity),
+    /// The entity mapper resource [`crate::sort_from_entity_map`] sorts by isn't in the world
+    MissingMapper(&'static str),
+}
+
+impl fmt::Display for DesyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DesyncError::Serialize { type_name, error } => {
+                write!(f, "failed to serialize {type_name}: {error}")
+            }
+            DesyncError::MissingEntity(entity) => write!(f, "tracked entity {entity:?} is missing"),
+            DesyncError::MissingMapper(mapper) => write!(f, "entity mapper {mapper} is missing"),
+        }
+    }
+}
+
+impl std::error::Error for DesyncError {}
+
+/// The first error hit while calculating a CRC. Serializers and entity sorts only get read access
+/// to the world, so they record errors here rather than returning them
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ErrorSlot(Arc<Mutex<Option<DesyncError>>>);
+
+impl ErrorSlot {
+    /// Record an error, unless one has already been recorded
+    pub(crate) fn record(&self, error: DesyncError) {
+        self.0.lock().unwrap().get_or_insert(error);
+    }
+
+    /// Whether an error has been recorded since the last `take`
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn take(&self) -> Option<DesyncError> {
+        self.0.lock().unwrap().take()
+    }
+}
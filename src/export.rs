@@ -0,0 +1,146 @@
+use bevy_ecs::world::World;
+use serde_json::{Map, Value};
+
+use crate::{calculate_crc_detailed, DesyncError, DesyncPluginData, DesyncReport};
+
+/// Export the tracked state as `entity_key:component_name:value` records, one per tracked
+/// component, for diffing against dumps from other tools with standard text utilities. Keys come
+/// from the configured [`crate::DesyncIdentity`] and components are named by their type name.
+/// Records are sorted by key, then component name, so the output doesn't depend on
+/// `entity_sort`. Fails like [`crate::calculate_crc`]
+pub fn export_tracked_records(world: &World) -> Result<Vec<String>, DesyncError> {
+    let desync_data = world.resource::<DesyncPluginData>();
+    let report = calculate_crc_detailed(world)?;
+    let mut records = report
+        .entities
+        .iter()
+        .flat_map(|entity| {
+            let key = desync_data.identity.key(entity.entity, world);
+            entity.components.iter().map(move |component| {
+                let name = world.components().get_info(component.id).unwrap().name();
+                (key, name, &component.serialized)
+            })
+        })
+        .collect::<Vec<_>>();
+    records.sort();
+    Ok(records
+        .into_iter()
+        .map(|(key, name, value)| format!("{key}:{name}:{value}"))
+        .collect())
+}
+
+/// The tracked state as a JSON document mapping each entity's key to a map of its tracked
+/// components by type name, for inspecting and version controlling golden states. Both maps are
+/// sorted (JSON keys are strings, so entity keys are ordered as strings), and keys come from the
+/// configured [`crate::DesyncIdentity`], so the document doesn't depend on spawn order or
+/// `entity_sort`. Components whose readable form isn't JSON are included as strings.
+///
+/// Entities sharing a key are merged, which only happens if the identity isn't unique. Fails like
+/// [`crate::calculate_crc`]
+pub fn tracked_state_to_json(world: &World) -> Result<Value, DesyncError> {
+    Ok(report_to_json(world, &calculate_crc_detailed(world)?))
+}
+
+/// [`tracked_state_to_json`] of an already calculated report
+pub(crate) fn report_to_json(world: &World, report: &DesyncReport) -> Value {
+    let desync_data = world.resource::<DesyncPluginData>();
+    let mut entities = Map::new();
+    for entity in report.entities.iter() {
+        let key = desync_data.identity.key(entity.entity, world).to_string();
+        let Value::Object(components) = entities
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()))
+        else {
+            unreachable!()
+        };
+        for component in entity.components.iter() {
+            let name = world.components().get_info(component.id).unwrap().name();
+            let value = serde_json::from_str(&component.serialized)
+                .unwrap_or_else(|_| Value::String(component.serialized.clone()));
+            components.insert(name.to_string(), value);
+        }
+    }
+    Value::Object(entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct NetId(u64);
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[test]
+    fn export_records() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            identity: Arc::new(|entity, world: &World| world.get::<NetId>(entity).unwrap().0),
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app.world.spawn((NetId(7), Health(10), TrackDesync));
+        app.world.spawn((NetId(3), Health(20), TrackDesync));
+
+        let name = std::any::type_name::<Health>();
+        assert_eq!(
+            export_tracked_records(&app.world).unwrap(),
+            vec![format!("3:{name}:20"), format!("7:{name}:10")]
+        );
+    }
+
+    #[derive(Component, Serialize)]
+    struct Position {
+        x: i32,
+        y: i32,
+    }
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            identity: Arc::new(|entity, world: &World| world.get::<NetId>(entity).unwrap().0),
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app.track_desync::<Position>();
+        app
+    }
+
+    #[test]
+    fn json_document() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1
+            .world
+            .spawn((NetId(7), Health(10), Position { x: 1, y: 2 }, TrackDesync));
+        app_1.world.spawn((NetId(3), Health(20), TrackDesync));
+        // same state, spawned in the opposite order
+        app_2.world.spawn((NetId(3), Health(20), TrackDesync));
+        app_2
+            .world
+            .spawn((NetId(7), Position { x: 1, y: 2 }, Health(10), TrackDesync));
+
+        let health = std::any::type_name::<Health>();
+        let position = std::any::type_name::<Position>();
+        let json = tracked_state_to_json(&app_1.world).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "3": { health: 20 },
+                "7": { health: 10, position: { "x": 1, "y": 2 } },
+            })
+        );
+        assert_eq!(
+            serde_json::to_string_pretty(&json).unwrap(),
+            serde_json::to_string_pretty(&tracked_state_to_json(&app_2.world).unwrap()).unwrap()
+        );
+    }
+}
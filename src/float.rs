@@ -0,0 +1,541 @@
+use serde::ser::{
+    Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+
+/// Controls how floats inside tracked components are written before hashing. Applies to floats
+/// nested anywhere in a component, including map keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FloatOptions {
+    /// JSON has no representation for NaN or the infinities, so `serde_json` writes them all as
+    /// `null`. When set, they are written as the strings `"NaN"`, `"+Inf"` and `"-Inf"` instead.
+    /// The canonical JSON that's hashed already tells them apart, with bare tokens no string can
+    /// produce, so there this only swaps those for strings a string field could also hold. It's
+    /// meant for [`crate::HashEncoding::Postcard`], where every NaN bit pattern is then written as
+    /// the same sentinel.
+    pub sentinel_non_finite: bool,
+    /// Write finite floats as strings rounded to this many decimal places, so tiny differences
+    /// past the last place don't change the hash. Values which round to zero are written as
+    /// positive zero. Large magnitudes keep every integer digit, so very large floats can still
+    /// differ in their low integer digits.
+    pub float_precision: Option<usize>,
+    /// Write finite floats as the fixed-point integer `round(value * scale)`, matching how
+    /// fixed-point netcode represents them, so floats which convert to the same fixed-point value
+    /// hash identically. Values out of `i64` range saturate. Takes precedence over
+    /// `float_precision`
+    pub fixed_point_scale: Option<f64>,
+    /// Rewrite floats which are the same value with different bits before any other option is
+    /// applied, see [`FloatCanonicalization`]
+    pub canonicalization: FloatCanonicalization,
+}
+
+/// Whether `-0.0` and the many NaN bit patterns are written as one canonical value.
+///
+/// This only matters if clients are already deterministic at the float level: it stops a client
+/// which computed `-0.0` where another computed `0.0`, or a NaN with a different payload, from
+/// reporting a desync, but does nothing for results which actually differ, e.g. from a different
+/// order of operations or a different libm.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatCanonicalization {
+    /// Floats are written as they are, so `-0.0` and `0.0` hash differently, as do NaNs with
+    /// different bits under [`crate::HashEncoding::Postcard`]
+    #[default]
+    Off,
+    /// `-0.0` is written as `0.0`, and every NaN as the standard quiet NaN
+    Canonical,
+}
+
+impl FloatOptions {
+    /// Whether these options leave every float untouched
+    pub fn is_passthrough(&self) -> bool {
+        *self == FloatOptions::default()
+    }
+}
+
+/// Wraps a value so that serializing it applies [`FloatOptions`] to every float it contains
+pub struct WithFloatOptions<'a, T: ?Sized> {
+    pub value: &'a T,
+    pub options: &'a FloatOptions,
+}
+
+impl<'a, T: ?Sized> WithFloatOptions<'a, T> {
+    pub fn new(value: &'a T, options: &'a FloatOptions) -> Self {
+        WithFloatOptions { value, options }
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for WithFloatOptions<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(FloatSerializer {
+            inner: serializer,
+            options: self.options,
+        })
+    }
+}
+
+/// The string a non-finite float is written as under `sentinel_non_finite`
+pub(crate) fn non_finite_sentinel(value: f64) -> &'static str {
+    if value.is_nan() {
+        "NaN"
+    } else if value > 0.0 {
+        "+Inf"
+    } else {
+        "-Inf"
+    }
+}
+
+/// Serializer which forwards everything to `inner`, rewriting floats on the way
+struct FloatSerializer<'a, S> {
+    inner: S,
+    options: &'a FloatOptions,
+}
+
+impl<S: Serializer> FloatSerializer<'_, S> {
+    fn canonical_f32(&self, value: f32) -> f32 {
+        match self.options.canonicalization {
+            FloatCanonicalization::Off => value,
+            _ if value.is_nan() => f32::NAN,
+            // true for -0.0 as well
+            _ if value == 0.0 => 0.0,
+            _ => value,
+        }
+    }
+
+    fn canonical_f64(&self, value: f64) -> f64 {
+        match self.options.canonicalization {
+            FloatCanonicalization::Off => value,
+            _ if value.is_nan() => f64::NAN,
+            _ if value == 0.0 => 0.0,
+            _ => value,
+        }
+    }
+
+    /// Returns the replacement for a non-finite float, if it should be replaced
+    fn sentinel(&self, value: f64) -> Option<&'static str> {
+        if !self.options.sentinel_non_finite || value.is_finite() {
+            return None;
+        }
+        Some(non_finite_sentinel(value))
+    }
+
+    /// Returns the fixed-point form of a finite float, if a scale is set
+    fn fixed_point(&self, value: f64) -> Option<i64> {
+        let scale = self
+            .options
+            .fixed_point_scale
+            .filter(|_| value.is_finite())?;
+        // `as` saturates, and rounding -0.4 gives -0.0, which converts to 0
+        Some((value * scale).round() as i64)
+    }
+
+    /// Returns the rounded form of a finite float, if a precision is set
+    fn rounded(&self, value: impl std::fmt::Display, is_finite: bool) -> Option<String> {
+        let precision = self.options.float_precision.filter(|_| is_finite)?;
+        let rounded = format!("{value:.precision$}");
+        match rounded.strip_prefix('-') {
+            // -0.000 and 0.000 are the same value
+            Some(zero) if zero.bytes().all(|b| b == b'0' || b == b'.') => Some(zero.to_string()),
+            _ => Some(rounded),
+        }
+    }
+}
+
+/// Forwards the elements of a compound type, wrapping each one so floats are still rewritten
+struct Compound<'a, C> {
+    inner: C,
+    options: &'a FloatOptions,
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+                self.inner.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+macro_rules! forward_compound {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $assoc:ident;)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<Self::$assoc, Self::Error> {
+                Ok(Compound {
+                    inner: self.inner.$method($($arg),*)?,
+                    options: self.options,
+                })
+            }
+        )*
+    };
+}
+
+impl<'a, S: Serializer> Serializer for FloatSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'a, S::SerializeStructVariant>;
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        let v = self.canonical_f32(v);
+        if let Some(fixed) = self.fixed_point(v as f64) {
+            return self.inner.serialize_i64(fixed);
+        }
+        if let Some(rounded) = self.rounded(v, v.is_finite()) {
+            return self.inner.serialize_str(&rounded);
+        }
+        match self.sentinel(v as f64) {
+            Some(sentinel) => self.inner.serialize_str(sentinel),
+            None => self.inner.serialize_f32(v),
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        let v = self.canonical_f64(v);
+        if let Some(fixed) = self.fixed_point(v) {
+            return self.inner.serialize_i64(fixed);
+        }
+        if let Some(rounded) = self.rounded(v, v.is_finite()) {
+            return self.inner.serialize_str(&rounded);
+        }
+        match self.sentinel(v) {
+            Some(sentinel) => self.inner.serialize_str(sentinel),
+            None => self.inner.serialize_f64(v),
+        }
+    }
+
+    forward! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_i128(v: i128);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_u128(v: u128);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.inner
+            .serialize_some(&WithFloatOptions::new(value, self.options))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.inner
+            .serialize_newtype_struct(name, &WithFloatOptions::new(value, self.options))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.inner.serialize_newtype_variant(
+            name,
+            variant_index,
+            variant,
+            &WithFloatOptions::new(value, self.options),
+        )
+    }
+
+    forward_compound! {
+        serialize_seq(len: Option<usize>) -> SerializeSeq;
+        serialize_tuple(len: usize) -> SerializeTuple;
+        serialize_tuple_struct(name: &'static str, len: usize) -> SerializeTupleStruct;
+        serialize_tuple_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeTupleVariant;
+        serialize_map(len: Option<usize>) -> SerializeMap;
+        serialize_struct(name: &'static str, len: usize) -> SerializeStruct;
+        serialize_struct_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeStructVariant;
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<C: SerializeSeq> SerializeSeq for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleVariant> SerializeTupleVariant for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeMap> SerializeMap for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        let key = WithFloatOptions::new(key, self.options);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStructVariant> SerializeStructVariant for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = WithFloatOptions::new(value, self.options);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Floats {
+        a: f32,
+        list: Vec<f64>,
+        nested: Option<(f32, u8)>,
+    }
+
+    fn to_json<T: Serialize>(value: &T) -> String {
+        let options = FloatOptions {
+            sentinel_non_finite: true,
+            ..Default::default()
+        };
+        serde_json::to_string(&WithFloatOptions::new(value, &options)).unwrap()
+    }
+
+    #[test]
+    fn non_finite_sentinels() {
+        let floats = Floats {
+            a: f32::NAN,
+            list: vec![f64::INFINITY, f64::NEG_INFINITY, 1.5],
+            nested: Some((f32::from_bits(f32::NAN.to_bits() + 1), 2)),
+        };
+        assert_eq!(
+            to_json(&floats),
+            r#"{"a":"NaN","list":["+Inf","-Inf",1.5],"nested":["NaN",2]}"#
+        );
+    }
+
+    #[test]
+    fn passthrough_matches_serde_json() {
+        let floats = Floats {
+            a: 0.25,
+            list: vec![-1.0],
+            nested: None,
+        };
+        let options = FloatOptions::default();
+        assert_eq!(
+            serde_json::to_string(&WithFloatOptions::new(&floats, &options)).unwrap(),
+            serde_json::to_string(&floats).unwrap()
+        );
+    }
+
+    #[test]
+    fn fixed_precision() {
+        let to_json = |value: f64| {
+            let options = FloatOptions {
+                float_precision: Some(6),
+                ..Default::default()
+            };
+            serde_json::to_string(&WithFloatOptions::new(&value, &options)).unwrap()
+        };
+        assert_eq!(to_json(1.0000001), to_json(1.0000004));
+        assert_ne!(to_json(1.00001), to_json(1.00002));
+        assert_eq!(to_json(1.5), r#""1.500000""#);
+        // tiny values either side of zero are both zero
+        assert_eq!(to_json(-1e-12), to_json(1e-12));
+        assert_eq!(to_json(-1e-12), r#""0.000000""#);
+        // every integer digit is kept
+        assert_eq!(to_json(1e20), r#""100000000000000000000.000000""#);
+        // non-finite floats aren't rounded
+        assert_eq!(to_json(f64::NAN), "null");
+    }
+
+    #[test]
+    fn fixed_point() {
+        let to_json = |value: f32| {
+            let options = FloatOptions {
+                fixed_point_scale: Some(65536.0),
+                ..Default::default()
+            };
+            serde_json::to_string(&WithFloatOptions::new(&value, &options)).unwrap()
+        };
+        // 16.16 fixed point
+        assert_eq!(to_json(1.5), "98304");
+        assert_eq!(to_json(1.5), to_json(1.500001));
+        assert_ne!(to_json(1.5), to_json(1.5001));
+        assert_eq!(to_json(-1e-9), "0");
+        assert_eq!(to_json(f32::MAX), i64::MAX.to_string());
+        assert_eq!(to_json(f32::NAN), "null");
+    }
+
+    #[test]
+    fn canonical_zero_and_nan() {
+        let options = FloatOptions {
+            canonicalization: FloatCanonicalization::Canonical,
+            ..Default::default()
+        };
+        let signalling = f64::from_bits(f64::NAN.to_bits() + 1);
+        let floats = |zero: f32, nan: f64| Floats {
+            a: zero,
+            list: vec![nan, 1.5],
+            nested: Some((zero, 2)),
+        };
+        let json = |value: &Floats, options| {
+            serde_json::to_string(&WithFloatOptions::new(value, options)).unwrap()
+        };
+        let postcard = |value: &Floats, options| {
+            postcard::to_extend(&WithFloatOptions::new(value, options), Vec::new()).unwrap()
+        };
+        let a = floats(0.0, f64::NAN);
+        let b = floats(-0.0, signalling);
+        let off = FloatOptions::default();
+        assert_ne!(json(&a, &off), json(&b, &off));
+        assert_ne!(postcard(&a, &off), postcard(&b, &off));
+        assert_eq!(
+            json(&b, &options),
+            r#"{"a":0.0,"list":[null,1.5],"nested":[0.0,2]}"#
+        );
+        assert_eq!(postcard(&a, &options), postcard(&b, &options));
+        // other values are untouched
+        assert_eq!(
+            json(&floats(-1.0, 1.0), &options),
+            json(&floats(-1.0, 1.0), &off)
+        );
+    }
+
+    struct FloatKey(f32);
+
+    impl Serialize for FloatKey {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(&self.0, &0u8)?;
+            map.end()
+        }
+    }
+
+    #[test]
+    fn non_finite_map_keys() {
+        // serde_json refuses non-finite map keys, but sentinels are strings
+        assert!(serde_json::to_string(&FloatKey(f32::NAN)).is_err());
+        assert_eq!(to_json(&FloatKey(f32::NAN)), r#"{"NaN":0}"#);
+    }
+}
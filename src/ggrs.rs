@@ -0,0 +1,117 @@
+//! bevy_ggrs integration, under the `ggrs` feature. [`GgrsChecksumPlugin`] keeps the
+//! [`RollbackChecksums`] confirmed frame in step with the session's, and works the checksum of
+//! each frame into the one bevy_ggrs saves with it, so ggrs's own desync detection compares the
+//! tracked state between peers.
+
+use bevy_app::{App, Plugin};
+use bevy_ecs::prelude::*;
+use bevy_ggrs::{ChecksumPart, ConfirmedFrameCount, RollbackFrameCount, SaveWorld, SaveWorldSet};
+
+use crate::RollbackChecksums;
+
+/// Hands the [`RollbackChecksums`] of each frame to bevy_ggrs as it's saved. Add it alongside
+/// `GgrsPlugin` and a [`crate::DesyncPlugin`] set up to hash each frame in the rollback schedule:
+/// ```rust,ignore
+/// app.add_plugins(GgrsPlugin::<Config>::default())
+///     .add_plugins(DesyncPlugin {
+///         schedule: Some(GgrsSchedule.intern()),
+///         tick_source: Some(Arc::new(|world| world.resource::<RollbackFrameCount>().0 as u64)),
+///         rollback_checksums: Some(32),
+///         ..Default::default()
+///     })
+///     .add_plugins(GgrsChecksumPlugin)
+///     // hash the frame once the game has simulated it
+///     .configure_sets(GgrsSchedule, DesyncSet::CalculateCrc.after(GameplaySet));
+/// ```
+/// Nothing is added to the saved checksum unless the `rollback_checksums` option is set
+pub struct GgrsChecksumPlugin;
+
+impl Plugin for GgrsChecksumPlugin {
+    fn build(&self, app: &mut App) {
+        app.world
+            .spawn((DesyncChecksumPart, ChecksumPart::default()));
+        app.add_systems(SaveWorld, save_checksum.in_set(SaveWorldSet::Checksum));
+    }
+}
+
+/// The entity whose [`ChecksumPart`] is the checksum of the tracked state
+#[derive(Component)]
+struct DesyncChecksumPart;
+
+fn save_checksum(
+    frame: Res<RollbackFrameCount>,
+    confirmed: Res<ConfirmedFrameCount>,
+    checksums: Option<ResMut<RollbackChecksums>>,
+    mut part: Query<&mut ChecksumPart, With<DesyncChecksumPart>>,
+) {
+    let Some(mut checksums) = checksums else {
+        return;
+    };
+    checksums.set_confirmed_frame((*confirmed).into());
+    // the frame is saved before it's first simulated too, when there's nothing to hand over yet
+    let checksum = checksums.simulated(frame.0).map_or(0, |c| c.checksum);
+    part.single_mut().0 = checksum;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, FullCrc, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Position(i32);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<RollbackFrameCount>()
+            .init_resource::<ConfirmedFrameCount>()
+            .add_plugins(DesyncPlugin {
+                tick_source: Some(Arc::new(|world| {
+                    world.resource::<RollbackFrameCount>().0 as u64
+                })),
+                rollback_checksums: Some(8),
+                ..Default::default()
+            })
+            .add_plugins(GgrsChecksumPlugin)
+            .track_desync::<Position>();
+        app.world.spawn((Position(1), TrackDesync));
+        app
+    }
+
+    fn saved_part(app: &mut App) -> u128 {
+        app.world.run_schedule(SaveWorld);
+        app.world
+            .query_filtered::<&ChecksumPart, With<DesyncChecksumPart>>()
+            .single(&app.world)
+            .0
+    }
+
+    #[test]
+    fn saves_checksum_of_frame() {
+        let mut app = build_app();
+        // saved before the frame is simulated
+        assert_eq!(saved_part(&mut app), 0);
+
+        app.world.resource_mut::<RollbackFrameCount>().0 = 1;
+        app.update();
+        let full_crc = app.world.resource::<FullCrc>().0;
+        assert_eq!(saved_part(&mut app), full_crc as u128);
+        assert_eq!(
+            app.world.resource::<RollbackChecksums>().confirmed_frame(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn nothing_saved_without_rollback_checksums() {
+        let mut app = build_app();
+        app.world.remove_resource::<RollbackChecksums>();
+        app.world.resource_mut::<RollbackFrameCount>().0 = 1;
+        app.update();
+        assert_eq!(saved_part(&mut app), 0);
+    }
+}
@@ -0,0 +1,252 @@
+use bevy_ecs::system::Resource;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::CrcMessage;
+
+/// Write `(tick, crc)` pairs, e.g. from [`crate::CrcHistory::iter`], as a golden sequence for
+/// [`GoldenComparison`]: one [`CrcMessage::encode`] after another
+pub fn write_golden<'a>(
+    mut out: impl Write,
+    crcs: impl IntoIterator<Item = &'a (u64, u16)>,
+) -> io::Result<()> {
+    for (tick, crc) in crcs {
+        out.write_all(
+            &CrcMessage {
+                tick: *tick,
+                crc: *crc,
+            }
+            .encode(),
+        )?;
+    }
+    out.flush()
+}
+
+/// The first tick a live run deviated from the golden sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GoldenDeviation {
+    pub tick: u64,
+    /// CRC from the golden sequence
+    pub expected: u16,
+    /// CRC of the live run
+    pub actual: u16,
+}
+
+#[derive(Debug, Default)]
+struct GoldenState {
+    checked: usize,
+    deviation: Option<GoldenDeviation>,
+}
+
+/// Compares the live CRCs against a golden sequence written by [`write_golden`] on a background
+/// thread, so loading a long recording and comparing against it stays off the simulation thread.
+/// Insert it, and `update_crc` sends it each tick's CRC. On the first tick which deviates, a report
+/// is written and nothing more is compared, as every later tick is expected to differ too.
+///
+/// Ticks missing from the golden sequence aren't checked.
+#[derive(Debug, Resource)]
+pub struct GoldenComparison {
+    sender: Option<Sender<CrcMessage>>,
+    state: Arc<Mutex<GoldenState>>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl GoldenComparison {
+    /// Compare against the golden sequence read from `golden`, writing the report to whatever
+    /// `report` opens. `report` is only called if the run deviates
+    pub fn spawn<W: Write>(
+        golden: impl Read + Send + 'static,
+        report: impl FnOnce() -> io::Result<W> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let state = Arc::new(Mutex::new(GoldenState::default()));
+        let thread_state = state.clone();
+        let handle = thread::spawn(move || compare(golden, receiver, &thread_state, report));
+        GoldenComparison {
+            sender: Some(sender),
+            state,
+            handle: Some(handle),
+        }
+    }
+
+    /// Compare against the golden file at `golden`, writing the report to a file at `report`
+    pub fn from_files(golden: impl AsRef<Path>, report: impl Into<PathBuf>) -> io::Result<Self> {
+        let golden = BufReader::new(File::open(golden)?);
+        let report = report.into();
+        Ok(Self::spawn(golden, move || {
+            File::create(report).map(BufWriter::new)
+        }))
+    }
+
+    /// The first tick which deviated, if the background thread has found one yet
+    pub fn deviation(&self) -> Option<GoldenDeviation> {
+        self.state.lock().unwrap().deviation
+    }
+
+    /// Number of ticks which have been compared so far
+    pub fn checked(&self) -> usize {
+        self.state.lock().unwrap().checked
+    }
+
+    /// Stop comparing once every CRC sent so far has been compared, and the report written if
+    /// there was a deviation
+    pub fn finish(mut self) -> io::Result<Option<GoldenDeviation>> {
+        self.sender = None;
+        match self.handle.take().unwrap().join() {
+            Ok(result) => result?,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+        Ok(self.deviation())
+    }
+
+    /// Queue a tick's CRC for comparison. Once the thread has stopped, nothing is sent
+    pub(crate) fn send(&self, tick: u64, crc: u16) {
+        if let Some(sender) = &self.sender {
+            // the thread stops after the first deviation
+            let _ = sender.send(CrcMessage { tick, crc });
+        }
+    }
+}
+
+fn compare<W: Write>(
+    mut golden: impl Read,
+    receiver: Receiver<CrcMessage>,
+    state: &Mutex<GoldenState>,
+    report: impl FnOnce() -> io::Result<W>,
+) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    golden.read_to_end(&mut bytes)?;
+    if bytes.len() % CrcMessage::SIZE != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "golden sequence isn't a whole number of CRC messages",
+        ));
+    }
+    let expected = bytes
+        .chunks(CrcMessage::SIZE)
+        .map(|chunk| CrcMessage::decode(chunk).unwrap())
+        .map(|message| (message.tick, message.crc))
+        .collect::<HashMap<_, _>>();
+
+    for message in receiver {
+        let Some(expected) = expected.get(&message.tick).copied() else {
+            continue;
+        };
+        let checked = {
+            let mut state = state.lock().unwrap();
+            state.checked += 1;
+            state.checked
+        };
+        if expected == message.crc {
+            continue;
+        }
+        let deviation = GoldenDeviation {
+            tick: message.tick,
+            expected,
+            actual: message.crc,
+        };
+        let mut out = report()?;
+        writeln!(
+            out,
+            "deviated from the golden sequence at tick {}: expected CRC {:#06x}, got {:#06x}",
+            deviation.tick, deviation.expected, deviation.actual
+        )?;
+        writeln!(out, "{} ticks matched before it", checked - 1)?;
+        out.flush()?;
+        state.lock().unwrap().deviation = Some(deviation);
+        break;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{AppDesyncExt, CrcHistory, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Counter(u32);
+
+    fn play(inputs: &[u32], comparison: Option<GoldenComparison>) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Counter>();
+        if let Some(comparison) = comparison {
+            app.insert_resource(comparison);
+        }
+        let entity = app.world.spawn((Counter(0), TrackDesync)).id();
+        for input in inputs {
+            app.update();
+            app.world.get_mut::<Counter>(entity).unwrap().0 += input;
+        }
+        app
+    }
+
+    #[test]
+    fn background_comparison_reports_deviation() {
+        let inputs = [1, 2, 3, 4, 5, 6];
+        let mut golden = Vec::new();
+        let recording = play(&inputs, None);
+        write_golden(&mut golden, recording.world.resource::<CrcHistory>().iter()).unwrap();
+
+        let report = Arc::new(Mutex::new(Vec::new()));
+        let sink = report.clone();
+        let comparison =
+            GoldenComparison::spawn(Cursor::new(golden.clone()), move || Ok(SharedBuffer(sink)));
+        let mut matching = play(&inputs, Some(comparison));
+        let comparison = matching
+            .world
+            .remove_resource::<GoldenComparison>()
+            .unwrap();
+        assert_eq!(comparison.finish().unwrap(), None);
+        assert!(report.lock().unwrap().is_empty());
+
+        let mut perturbed = inputs;
+        perturbed[2] = 0;
+        let sink = report.clone();
+        let comparison =
+            GoldenComparison::spawn(Cursor::new(golden), move || Ok(SharedBuffer(sink)));
+        let mut replay = play(&perturbed, Some(comparison));
+        let comparison = replay.world.remove_resource::<GoldenComparison>().unwrap();
+        // the CRC of tick 3 is taken after the input of frame 2 is applied
+        let deviation = comparison.finish().unwrap().unwrap();
+        assert_eq!(deviation.tick, 3);
+        assert_eq!(
+            recording.world.resource::<CrcHistory>().get(3),
+            Some(deviation.expected)
+        );
+        assert_eq!(
+            replay.world.resource::<CrcHistory>().get(3),
+            Some(deviation.actual)
+        );
+        let report = String::from_utf8(report.lock().unwrap().clone()).unwrap();
+        assert!(report.contains("at tick 3"));
+        assert!(report.contains("3 ticks matched"));
+    }
+
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
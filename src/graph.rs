@@ -0,0 +1,106 @@
+use bevy_ecs::world::World;
+
+use crate::{CrcScope, DesyncPluginData};
+
+/// Hash the structure of the world rather than its contents: the set of archetypes with
+/// tracked entities, each identified by the type names of its tracked components. Archetypes are
+/// included even if they're currently empty, so two worlds which reached the same state through
+/// different sequences of inserts and removals can have different graph CRCs.
+///
+/// This is independent of [`crate::calculate_crc`], and is meant to be compared separately for
+/// strict determinism checks. Archetypes are never removed from a world, so the graph CRC only
+/// changes when a new combination of tracked components is seen. Like [`crate::Crc`], it's the low
+/// 16 bits of the plugin's `crc_algorithm`.
+pub fn calculate_archetype_graph_crc(world: &World) -> u16 {
+    let desync_data = world.resource::<DesyncPluginData>();
+    // component ids are assigned in registration order, so use type names as the stable key
+    let mut keys = world
+        .archetypes()
+        .iter()
+        .filter(|a| desync_data.tracks_archetype(a, world))
+        .map(|a| {
+            let mut names = a
+                .components()
+                .filter(|c| desync_data.is_tracked(c, CrcScope::Shared))
+                .map(|c| world.components().get_info(c).unwrap().name())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        })
+        .collect::<Vec<_>>();
+    keys.sort();
+    // archetypes differing only in untracked components have the same key
+    keys.dedup();
+
+    let mut digest = desync_data.crc_algorithm.digest();
+    for names in keys {
+        for name in names {
+            // type names never contain NUL, so this terminates the name unambiguously
+            digest.update(name.as_bytes());
+            digest.update(&[0]);
+        }
+        digest.update(&[1]);
+    }
+    digest.finalize() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{calculate_crc, AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct A(u8);
+
+    #[derive(Component, Serialize)]
+    struct B(u8);
+
+    #[derive(Component)]
+    struct Untracked;
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default()).track_desync::<A>();
+        app.track_desync::<B>();
+        app.world.spawn((A(0), TrackDesync));
+        app
+    }
+
+    #[test]
+    fn extra_empty_archetype() {
+        let app_1 = build_app();
+        let mut app_2 = build_app();
+        assert_eq!(
+            calculate_archetype_graph_crc(&app_1.world),
+            calculate_archetype_graph_crc(&app_2.world)
+        );
+
+        // leaves an empty (A, B) archetype behind
+        let entity = app_2.world.spawn((A(1), B(1), TrackDesync)).id();
+        app_2.world.despawn(entity);
+        assert_eq!(
+            calculate_crc(&app_1.world).unwrap(),
+            calculate_crc(&app_2.world).unwrap()
+        );
+        assert_ne!(
+            calculate_archetype_graph_crc(&app_1.world),
+            calculate_archetype_graph_crc(&app_2.world)
+        );
+    }
+
+    #[test]
+    fn untracked_components_ignored() {
+        let app_1 = build_app();
+        let mut app_2 = build_app();
+        let entity = app_2.world.spawn((A(1), Untracked, TrackDesync)).id();
+        app_2.world.despawn(entity);
+        assert_eq!(
+            calculate_archetype_graph_crc(&app_1.world),
+            calculate_archetype_graph_crc(&app_2.world)
+        );
+    }
+}
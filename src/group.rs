@@ -0,0 +1,135 @@
+use bevy_ecs::{system::Resource, world::World};
+use std::collections::BTreeMap;
+
+use crate::{
+    algorithm::CrcDigest, report::calculate_crc_scoped, CombineStrategy, CrcScope, DesyncPluginData,
+};
+
+/// Name of a group of tracked components, see [`crate::AppDesyncExt::track_desync_in`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GroupId(pub String);
+
+impl From<&str> for GroupId {
+    fn from(name: &str) -> Self {
+        GroupId(name.to_string())
+    }
+}
+
+/// CRC of each group of components registered with [`crate::AppDesyncExt::track_desync_in`],
+/// updated alongside [`crate::Crc`], so a desync can be narrowed down to the system which owns
+/// the group. A group's CRC covers only its own components, hashed in the same entity order and
+/// combined the same way as the full CRC. Grouped components are still part of the full CRC.
+/// Groups are kept in name order, so they can be sent or logged in the same order on every peer
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct GroupCrcs(pub BTreeMap<GroupId, u16>);
+
+impl GroupCrcs {
+    pub fn get(&self, group: &str) -> Option<u16> {
+        self.0.get(&GroupId::from(group)).copied()
+    }
+}
+
+/// Running checksum of one group
+enum GroupDigest {
+    Concatenate(CrcDigest),
+    Folded(u64),
+}
+
+pub(crate) fn update_group_crcs(world: &mut World) {
+    let report = calculate_crc_scoped(world, CrcScope::Shared, false);
+    let desync_data = world.resource::<DesyncPluginData>();
+    let (combine, algorithm) = (desync_data.combine, desync_data.crc_algorithm);
+    // every group gets a CRC, even while none of its components are in the world
+    let mut digests = desync_data
+        .component_groups
+        .values()
+        .map(|group| {
+            let digest = match combine {
+                CombineStrategy::Concatenate => GroupDigest::Concatenate(algorithm.digest()),
+                CombineStrategy::Xor | CombineStrategy::Sum => GroupDigest::Folded(0),
+            };
+            (group, digest)
+        })
+        .collect::<BTreeMap<_, _>>();
+    for entity_report in report.entities.iter() {
+        let mut entity_digests = BTreeMap::new();
+        for component in entity_report.components.iter() {
+            let Some(group) = desync_data.component_groups.get(&component.id) else {
+                continue;
+            };
+            match digests.get_mut(group).unwrap() {
+                GroupDigest::Concatenate(digest) => digest.update(&component.hashed),
+                GroupDigest::Folded(_) => entity_digests
+                    .entry(group)
+                    .or_insert_with(|| algorithm.digest())
+                    .update(&component.hashed),
+            }
+        }
+        for (group, digest) in entity_digests {
+            if let GroupDigest::Folded(combined) = digests.get_mut(group).unwrap() {
+                *combined = combine.fold(*combined, digest.finalize());
+            }
+        }
+    }
+    let crcs = digests
+        .into_iter()
+        .map(|(group, digest)| {
+            let crc = match digest {
+                GroupDigest::Concatenate(digest) => digest.finalize(),
+                GroupDigest::Folded(combined) => algorithm.truncate(combined),
+            };
+            (group.clone(), crc as u16)
+        })
+        .collect();
+    world.resource_mut::<GroupCrcs>().0 = crcs;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Position(i32);
+
+    #[derive(Component, Serialize)]
+    struct Velocity(i32);
+
+    #[derive(Component, Serialize)]
+    struct Gold(u32);
+
+    fn group_crcs(combine: CombineStrategy, gold: u32) -> (GroupCrcs, u16) {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            combine,
+            ..Default::default()
+        })
+        .track_desync_in::<Position>("physics");
+        app.track_desync_in::<Velocity>("physics");
+        app.track_desync_in::<Gold>("inventory");
+        app.world
+            .spawn((Position(1), Velocity(2), Gold(gold), TrackDesync));
+        app.world.spawn((Position(3), TrackDesync));
+        app.update();
+        (
+            app.world.resource::<GroupCrcs>().clone(),
+            app.world.resource::<Crc>().0,
+        )
+    }
+
+    #[test]
+    fn desync_isolated_to_group() {
+        for combine in [CombineStrategy::Concatenate, CombineStrategy::Xor] {
+            let (crcs_1, crc_1) = group_crcs(combine, 10);
+            let (crcs_2, crc_2) = group_crcs(combine, 20);
+            assert_ne!(crc_1, crc_2);
+            assert_eq!(crcs_1.get("physics"), crcs_2.get("physics"));
+            assert_ne!(crcs_1.get("inventory"), crcs_2.get("inventory"));
+            assert_eq!(crcs_1.0.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use bevy_ecs::{
+    entity::{Entity, EntityHashSet},
+    world::World,
+};
+use bevy_hierarchy::Children;
+use serde::{Serialize, Serializer};
+
+use crate::{mapping::EntityLookup, DesyncPluginData};
+
+/// Whether the order of an entity's [`bevy_hierarchy::Children`] is part of the hashed state,
+/// see [`crate::AppDesyncExt::track_desync_children`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChildOrder {
+    /// Children are hashed in order, so the same children in a different order are a desync.
+    /// For games where the order means something, e.g. draw order
+    #[default]
+    Ordered,
+    /// Children are sorted by their mapped entity first, so only which children there are matters
+    Sorted,
+}
+
+/// Children mapped through an [`EntityLookup`]
+pub(crate) struct MappedChildren<'a> {
+    pub(crate) children: &'a [Entity],
+    pub(crate) lookup: &'a EntityLookup,
+    pub(crate) order: ChildOrder,
+}
+
+impl Serialize for MappedChildren<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.lookup.serialize_entities(
+            self.children.iter().copied(),
+            self.order == ChildOrder::Sorted,
+            serializer,
+        )
+    }
+}
+
+/// `roots`, each followed by its descendants depth first, for the plugin's `include_hierarchy`
+/// option. Descendants without tracked components are left out, but their own descendants aren't.
+/// A descendant which is one of `roots` is left in its place among them, with its descendants
+pub(crate) fn with_descendants(world: &World, roots: Vec<Entity>) -> Vec<Entity> {
+    let registry = &world.resource::<DesyncPluginData>().serialize_fn_registry;
+    let has_tracked = |entity: Entity| {
+        world.get_entity(entity).is_some_and(|entity| {
+            entity
+                .archetype()
+                .components()
+                .any(|c| registry.contains_key(&c))
+        })
+    };
+    let children = |entity: Entity| {
+        world
+            .get::<Children>(entity)
+            .into_iter()
+            .flat_map(|children| children.iter().copied())
+            .rev()
+    };
+    let root_set = roots.iter().copied().collect::<EntityHashSet>();
+    let mut entities = Vec::with_capacity(roots.len());
+    let mut stack = Vec::new();
+    for root in roots {
+        entities.push(root);
+        stack.extend(children(root));
+        while let Some(entity) = stack.pop() {
+            if root_set.contains(&entity) {
+                continue;
+            }
+            if has_tracked(entity) {
+                entities.push(entity);
+            }
+            stack.extend(children(entity));
+        }
+    }
+    entities
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{
+        component::Component,
+        entity::{EntityHashMap, EntityMapper},
+        system::Resource,
+    };
+    use bevy_hierarchy::BuildWorldChildren;
+
+    use super::*;
+    use crate::{
+        calculate_crc, calculate_crc_detailed, sort_from_entity_map, AppDesyncExt, DesyncPlugin,
+        EnumerateEntities, TrackDesync,
+    };
+    use std::sync::Arc;
+
+    #[derive(Clone, Default, Resource)]
+    struct EntityMap(EntityHashMap<Entity>);
+
+    impl EntityMapper for EntityMap {
+        fn map_entity(&mut self, entity: Entity) -> Entity {
+            self.0[&entity]
+        }
+    }
+
+    impl EnumerateEntities for EntityMap {
+        fn iter_entities(&self) -> Vec<(Entity, Entity)> {
+            self.0.iter().map(|(a, b)| (*a, *b)).collect()
+        }
+    }
+
+    /// CRCs of two worlds holding a parent with the same two children, pushed in opposite orders
+    fn crcs(order: ChildOrder) -> (u16, u16) {
+        let mut apps = [0, 1].map(|i| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                entity_sort: Arc::new(Box::new(move |world| {
+                    sort_from_entity_map::<EntityMap>(world, i == 0)
+                })),
+                ..Default::default()
+            })
+            .track_desync_children::<EntityMap>(i == 0, order);
+            app
+        });
+        let [a, b] = [0, 1].map(|i| {
+            let world = &mut apps[i].world;
+            let children = [world.spawn_empty().id(), world.spawn_empty().id()];
+            let parent = world.spawn(TrackDesync).id();
+            let pushed = match i {
+                0 => children,
+                _ => [children[1], children[0]],
+            };
+            world.entity_mut(parent).push_children(&pushed);
+            (parent, children)
+        });
+        let mut map = EntityHashMap::default();
+        map.insert(a.0, b.0);
+        map.insert(a.1[0], b.1[0]);
+        map.insert(a.1[1], b.1[1]);
+        for app in apps.iter_mut() {
+            app.insert_resource(EntityMap(map.clone()));
+        }
+        (
+            calculate_crc(&apps[0].world).unwrap(),
+            calculate_crc(&apps[1].world).unwrap(),
+        )
+    }
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[test]
+    fn descendants_hashed_once() {
+        let build = |include_hierarchy| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                include_hierarchy,
+                ..Default::default()
+            })
+            .track_desync::<Health>();
+            let world = &mut app.world;
+            let grandchild = world.spawn(Health(3)).id();
+            let empty = world.spawn_empty().push_children(&[grandchild]).id();
+            let child = world.spawn(Health(2)).id();
+            let tracked_child = world.spawn((Health(4), TrackDesync)).id();
+            let root = world
+                .spawn((Health(1), TrackDesync))
+                .push_children(&[child, empty, tracked_child])
+                .id();
+            (app, [root, child, grandchild, tracked_child])
+        };
+
+        let (app, [root, child, grandchild, tracked_child]) = build(false);
+        let entities = |app: &App| {
+            calculate_crc_detailed(&app.world)
+                .unwrap()
+                .entities
+                .iter()
+                .map(|e| e.entity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entities(&app), vec![tracked_child, root]);
+
+        let (mut app, _) = build(true);
+        // the tracked child is hashed as a root, not again under its parent
+        assert_eq!(entities(&app), vec![tracked_child, root, child, grandchild]);
+        let crc = calculate_crc(&app.world).unwrap();
+        app.world.get_mut::<Health>(grandchild).unwrap().0 = 5;
+        assert_ne!(calculate_crc(&app.world).unwrap(), crc);
+    }
+
+    #[test]
+    fn children_order_as_configured() {
+        let (a, b) = crcs(ChildOrder::Sorted);
+        assert_eq!(a, b);
+        let (a, b) = crcs(ChildOrder::Ordered);
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,211 @@
+use bevy_ecs::system::Resource;
+use std::collections::VecDeque;
+
+use crate::CrcAlgorithm;
+
+/// Tick counter used to tag CRCs. `update_crc` records the CRC against the current value, then
+/// advances it by one. Overwrite it to line the CRCs up with your own tick count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub struct DesyncTick(pub u64);
+
+/// Ring buffer of the most recent `(tick, crc)` pairs, holding [`crate::Crc`] rather than the
+/// full width [`crate::FullCrc`]
+#[derive(Clone, Debug, Default, Resource)]
+pub struct CrcHistory {
+    entries: VecDeque<(u64, u16)>,
+    capacity: usize,
+}
+
+impl CrcHistory {
+    pub fn new(capacity: usize) -> Self {
+        CrcHistory {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record a CRC, dropping the oldest entry if the history is full
+    pub fn push(&mut self, tick: u64, crc: u16) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((tick, crc));
+    }
+
+    /// The CRC recorded for `tick`, if it's still in the history
+    pub fn get(&self, tick: u64) -> Option<u16> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(t, _)| *t == tick)
+            .map(|(_, crc)| *crc)
+    }
+
+    /// Recorded `(tick, crc)` pairs, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &(u64, u16)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// How a peer's CRCs line up with the local [`CrcHistory`], see [`compare_against`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    /// Every tick both sides have a CRC for matches
+    InSync,
+    /// The CRCs differ, first at `first_bad_tick`. Later ticks will usually differ too, so this is
+    /// the tick to look at when working out how the divergence began
+    Diverged { first_bad_tick: u64 },
+    /// None of the peer's ticks are in the history, e.g. because they're too old or haven't been
+    /// reached yet
+    NoOverlap,
+}
+
+/// Compare a peer's `(tick, crc)` pairs against the local history. Only ticks both sides have a CRC
+/// for are compared, and `remote` doesn't need to be sorted
+pub fn compare_against(history: &CrcHistory, remote: &[(u64, u16)]) -> Comparison {
+    let mut overlap = false;
+    let mut first_bad_tick = None::<u64>;
+    for &(tick, crc) in remote {
+        let Some(local) = history.get(tick) else {
+            continue;
+        };
+        overlap = true;
+        if local != crc && first_bad_tick.is_none_or(|first| tick < first) {
+            first_bad_tick = Some(tick);
+        }
+    }
+    match (overlap, first_bad_tick) {
+        (_, Some(first_bad_tick)) => Comparison::Diverged { first_bad_tick },
+        (true, None) => Comparison::InSync,
+        (false, None) => Comparison::NoOverlap,
+    }
+}
+
+/// CRC over the CRCs of the last `window` ticks. Unlike [`crate::Crc`], a divergence which
+/// corrects itself on the next tick is still visible here until it leaves the window. Folds
+/// 16 bit CRCs into a 16 bit CRC, whichever [`crate::CrcAlgorithm`] is configured
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RollingCrc {
+    window: VecDeque<u16>,
+    size: usize,
+    crc: u16,
+}
+
+impl RollingCrc {
+    pub fn new(size: usize) -> Self {
+        RollingCrc {
+            window: VecDeque::with_capacity(size),
+            size,
+            crc: 0,
+        }
+    }
+
+    /// Fold a tick's CRC into the window, dropping the oldest if the window is full
+    pub fn push(&mut self, crc: u16) {
+        if self.size == 0 {
+            return;
+        }
+        if self.window.len() == self.size {
+            self.window.pop_front();
+        }
+        self.window.push_back(crc);
+
+        let mut digest = CrcAlgorithm::Ibm16.digest();
+        for crc in self.window.iter() {
+            digest.update(&crc.to_le_bytes());
+        }
+        self.crc = digest.finalize() as u16;
+    }
+
+    /// The CRC of the current window
+    pub fn crc(&self) -> u16 {
+        self.crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_drops_oldest() {
+        let mut history = CrcHistory::new(2);
+        history.push(0, 10);
+        history.push(1, 11);
+        history.push(2, 12);
+        assert_eq!(history.get(0), None);
+        assert_eq!(history.get(1), Some(11));
+        assert_eq!(history.get(2), Some(12));
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn compare_against_finds_first_bad_tick() {
+        let mut history = CrcHistory::new(4);
+        for (tick, crc) in [(10, 1), (11, 2), (12, 3), (13, 4)] {
+            history.push(tick, crc);
+        }
+        let cases: &[(&[(u64, u16)], Comparison)] = &[
+            (&[], Comparison::NoOverlap),
+            (&[(3, 1), (20, 1)], Comparison::NoOverlap),
+            (&[(11, 2), (12, 3)], Comparison::InSync),
+            // ticks outside the history are ignored
+            (&[(9, 0), (13, 4), (14, 0)], Comparison::InSync),
+            (
+                &[(11, 2), (12, 0), (13, 0)],
+                Comparison::Diverged { first_bad_tick: 12 },
+            ),
+            // out of order
+            (
+                &[(13, 0), (10, 1), (11, 0)],
+                Comparison::Diverged { first_bad_tick: 11 },
+            ),
+            (
+                &[(9, 0), (10, 9)],
+                Comparison::Diverged { first_bad_tick: 10 },
+            ),
+        ];
+        for (remote, expected) in cases {
+            assert_eq!(compare_against(&history, remote), *expected, "{remote:?}");
+        }
+    }
+
+    #[test]
+    fn rolling_crc_remembers_mismatch() {
+        let mut rolling_1 = RollingCrc::new(3);
+        let mut rolling_2 = RollingCrc::new(3);
+        for crc in [1, 2, 3] {
+            rolling_1.push(crc);
+            rolling_2.push(crc);
+        }
+        assert_eq!(rolling_1.crc(), rolling_2.crc());
+
+        // a single tick mismatch
+        rolling_1.push(4);
+        rolling_2.push(5);
+        assert_ne!(rolling_1.crc(), rolling_2.crc());
+
+        // recovered, but the mismatch is still in the window
+        for crc in [6, 7] {
+            rolling_1.push(crc);
+            rolling_2.push(crc);
+            assert_ne!(rolling_1.crc(), rolling_2.crc());
+        }
+
+        // mismatch has left the window
+        rolling_1.push(8);
+        rolling_2.push(8);
+        assert_eq!(rolling_1.crc(), rolling_2.crc());
+    }
+}
@@ -0,0 +1,215 @@
+use bevy_ecs::{component::Component, entity::Entity, world::World};
+use std::marker::PhantomData;
+
+use crate::unordered_tracked_entities;
+
+/// Maps an entity to a stable key, which should be the same for the "same" entity on every peer.
+/// Used wherever entities need to be identified outside of the world they live in, e.g. when
+/// exporting tracked state or sending per-entity hashes to a peer.
+///
+/// Implemented for any `Fn(Entity, &World) -> u64`, so a closure reading a network id component
+/// can be used directly.
+pub trait DesyncIdentity: Send + Sync + 'static {
+    fn key(&self, entity: Entity, world: &World) -> u64;
+}
+
+impl<F: Fn(Entity, &World) -> u64 + Send + Sync + 'static> DesyncIdentity for F {
+    fn key(&self, entity: Entity, world: &World) -> u64 {
+        self(entity, world)
+    }
+}
+
+/// Default identity, using `Entity::to_bits`. Only stable if both peers spawn entities
+/// identically.
+pub struct EntityBitsIdentity;
+
+impl DesyncIdentity for EntityBitsIdentity {
+    fn key(&self, entity: Entity, _world: &World) -> u64 {
+        entity.to_bits()
+    }
+}
+
+/// Implemented by a component which carries its entity's stable key, e.g. a network id. Designate
+/// the keying component with [`crate::AppDesyncExt::track_desync_key`].
+pub trait DesyncKey {
+    fn desync_key(&self) -> u64;
+}
+
+/// Identity read from the entity's `K` component. Entities without one fall back to
+/// `Entity::to_bits`
+pub struct ComponentKeyIdentity<K>(PhantomData<fn() -> K>);
+
+impl<K> Default for ComponentKeyIdentity<K> {
+    fn default() -> Self {
+        ComponentKeyIdentity(PhantomData)
+    }
+}
+
+impl<K: Component + DesyncKey> DesyncIdentity for ComponentKeyIdentity<K> {
+    fn key(&self, entity: Entity, world: &World) -> u64 {
+        match world.get::<K>(entity) {
+            Some(key) => key.desync_key(),
+            None => entity.to_bits(),
+        }
+    }
+}
+
+/// Sort tracked entities by the key of their `K` component. Entities without one are ordered after
+/// every keyed entity, by `Entity`
+///
+/// The order only depends on the keys, not on where entities are stored, so inserting and
+/// removing components (which moves entities between archetypes, and reorders the tables they
+/// leave) doesn't change the CRC of a world which ends up in the same state.
+pub fn sort_by_desync_key<K: Component + DesyncKey>(world: &World) -> Vec<Entity> {
+    let mut entities = unordered_tracked_entities(world)
+        .into_iter()
+        .map(|e| (world.get::<K>(e).map(|k| k.desync_key()), e))
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|(key, e)| (key.is_none(), *key, *e));
+    entities.into_iter().map(|(_, e)| e).collect()
+}
+
+/// What [`sort_by_component_with`] does with tracked entities which don't have the key component
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnkeyedEntities {
+    /// Order them after every keyed entity, by `Entity`
+    #[default]
+    Last,
+    /// Leave them out of the CRC
+    Exclude,
+}
+
+/// Sort tracked entities by the value of their `K` component, e.g. a network id which is already
+/// the same on every peer. Entities sharing a value are ordered by `Entity`, and entities without
+/// `K` are ordered after every keyed entity.
+///
+/// Usage:
+/// ```rust,ignore
+/// app.add_plugins(
+/// DesyncPlugin {
+///     entity_sort: Arc::new(Box::new(sort_by_component::<NetId>)),
+///     ..Default::default()
+/// })
+/// ```
+pub fn sort_by_component<K: Component + Ord>(world: &World) -> Vec<Entity> {
+    sort_by_component_with::<K>(world, UnkeyedEntities::Last)
+}
+
+/// [`sort_by_component`], choosing what happens to entities without `K`
+pub fn sort_by_component_with<K: Component + Ord>(
+    world: &World,
+    unkeyed: UnkeyedEntities,
+) -> Vec<Entity> {
+    let mut entities = unordered_tracked_entities(world)
+        .into_iter()
+        .map(|e| (world.get::<K>(e), e))
+        .filter(|(key, _)| key.is_some() || unkeyed == UnkeyedEntities::Last)
+        .collect::<Vec<_>>();
+    entities.sort_by(|(a, a_entity), (b, b_entity)| {
+        (a.is_none(), a, a_entity).cmp(&(b.is_none(), b, b_entity))
+    });
+    entities.into_iter().map(|(_, e)| e).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, DesyncPluginData, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u64);
+
+    #[derive(Component)]
+    struct NetKey(u64);
+
+    impl DesyncKey for NetKey {
+        fn desync_key(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn sort_by_component_key() {
+        let mut app_1 = App::new();
+        let mut app_2 = App::new();
+        for app in [&mut app_1, &mut app_2] {
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync::<Foo>();
+            app.track_desync_key::<NetKey>();
+        }
+        for i in 0..3 {
+            app_1.world.spawn((Foo(i), NetKey(i), TrackDesync));
+        }
+        for i in (0..3).rev() {
+            app_2.world.spawn((Foo(i), NetKey(i), TrackDesync));
+        }
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        let entity = app_2.world.spawn(NetKey(7)).id();
+        let desync_data = app_2.world.resource::<DesyncPluginData>();
+        assert_eq!(desync_data.identity.key(entity, &app_2.world), 7);
+    }
+
+    #[derive(Component, PartialEq, Eq, PartialOrd, Ord)]
+    struct NetId(u32);
+
+    fn sorted_crc(ids: impl Iterator<Item = u32>, unkeyed: UnkeyedEntities, extra: u64) -> u16 {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            entity_sort: Arc::new(Box::new(move |w| {
+                sort_by_component_with::<NetId>(w, unkeyed)
+            })),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        for id in ids {
+            app.world.spawn((Foo(id as u64), NetId(id), TrackDesync));
+        }
+        app.world.spawn((Foo(extra), TrackDesync));
+        app.update();
+        app.world.resource::<Crc>().0
+    }
+
+    #[test]
+    fn sort_by_component_value() {
+        let last = |ids: Vec<u32>, extra| sorted_crc(ids.into_iter(), UnkeyedEntities::Last, extra);
+        assert_eq!(last(vec![3, 1, 2], 0), last(vec![2, 3, 1], 0));
+        assert_ne!(last(vec![3, 1, 2], 0), last(vec![3, 1, 2], 1));
+
+        let exclude = |extra| sorted_crc([5, 4].into_iter(), UnkeyedEntities::Exclude, extra);
+        assert_eq!(exclude(0), exclude(1));
+    }
+
+    #[derive(Component)]
+    struct Stunned;
+
+    #[test]
+    fn stable_across_archetype_moves() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        app.track_desync_key::<NetKey>();
+        let entities = (0..4)
+            .map(|i| app.world.spawn((Foo(i), NetKey(i), TrackDesync)).id())
+            .collect::<Vec<_>>();
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+
+        // moving out and back swap-removes from the table, so the entity comes back last
+        app.world.entity_mut(entities[0]).insert(Stunned);
+        app.world.entity_mut(entities[1]).insert(Stunned);
+        app.world.entity_mut(entities[0]).remove::<Stunned>();
+        app.world.entity_mut(entities[1]).remove::<Stunned>();
+        let row = app.world.entity(entities[0]).location().archetype_row;
+        assert_ne!(row.index(), 0);
+
+        app.update();
+        assert_eq!(app.world.resource::<Crc>().0, crc);
+    }
+}
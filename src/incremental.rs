@@ -0,0 +1,272 @@
+use bevy_ecs::{
+    archetype::ArchetypeId, component::Tick, entity::Entity, system::Resource, world::World,
+};
+use bevy_utils::tracing::{enabled, Level};
+use std::collections::HashMap;
+
+use crate::{cache::CrcCache, report::EntityHasher, CrcScope, DesyncPluginData, DesyncStatus};
+
+/// Each tracked entity's contribution to the CRC, kept folded into a running value so that only
+/// entities which changed are serialized again, added by the plugin's `incremental` option. Only
+/// used with a commutative [`crate::CombineStrategy`], which lets a contribution be taken back out
+/// of the running value: XOR it in again, or subtract it.
+///
+/// An entity is hashed again when any of its tracked components has changed since the last CRC, or
+/// when it has moved archetype, e.g. because a component was inserted or removed. Entities which
+/// were despawned or stopped being tracked are taken out. The whole cache is dropped when
+/// [`DesyncPluginData`] changes, e.g. when a component is registered. Falls back to a full
+/// recompute like [`crate::calculate_crc_cached`].
+#[derive(Debug, Default, Resource)]
+pub struct EntityCrcCache {
+    entities: HashMap<Entity, CachedEntity>,
+    /// Every cached contribution folded together
+    combined: u64,
+    /// Length of every cached entity's serialized components
+    bytes: usize,
+    /// Tick the cache was last brought up to date
+    last_run: Option<Tick>,
+    /// Incremented each time the cache is updated, to find entities which weren't seen
+    pass: u32,
+    recomputed: usize,
+}
+
+#[derive(Debug)]
+struct CachedEntity {
+    archetype: ArchetypeId,
+    crc: u64,
+    bytes: usize,
+    /// The last pass the entity was tracked in
+    pass: u32,
+}
+
+impl EntityCrcCache {
+    /// Number of entities which were hashed again for the last CRC
+    pub fn recomputed(&self) -> usize {
+        self.recomputed
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+        self.combined = 0;
+        self.bytes = 0;
+        self.last_run = None;
+    }
+}
+
+impl CrcCache for EntityCrcCache {
+    fn last_run(&self) -> Option<Tick> {
+        self.last_run
+    }
+
+    fn invalidate(&mut self) {
+        self.clear();
+    }
+
+    fn update(&mut self, world: &World, last_run: Tick, this_run: Tick) -> (DesyncStatus, u64) {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let combine = desync_data.combine;
+        let algorithm = desync_data.crc_algorithm;
+        self.recomputed = 0;
+        self.pass = self.pass.wrapping_add(1);
+        let pass = self.pass;
+
+        let mut hasher = EntityHasher::default();
+        for archetype in world
+            .archetypes()
+            .iter()
+            .filter(|a| desync_data.tracks_archetype(a, world))
+        {
+            let tracked = archetype
+                .components()
+                .filter(|c| desync_data.is_tracked(c, CrcScope::Shared))
+                .collect::<Vec<_>>();
+            for entity in archetype.entities().iter().map(|e| e.id()) {
+                if let Some(cached) = self.entities.get_mut(&entity) {
+                    let entity_ref = world.entity(entity);
+                    let changed = cached.archetype != archetype.id()
+                        || tracked.iter().any(|c| {
+                            entity_ref
+                                .get_change_ticks_by_id(*c)
+                                .unwrap()
+                                .is_changed(last_run, this_run)
+                        });
+                    cached.pass = pass;
+                    if !changed {
+                        continue;
+                    }
+                    self.combined = combine.unfold(self.combined, cached.crc);
+                    self.bytes -= cached.bytes;
+                }
+                hasher.hash(world, entity, CrcScope::Shared);
+                if desync_data.errors.is_set() {
+                    // hashed from partial bytes, and the error has to be hit again next time
+                    self.entities.remove(&entity);
+                    continue;
+                }
+                if enabled!(Level::TRACE) {
+                    hasher.trace(world, entity);
+                }
+                let crc = hasher.crc_with(algorithm);
+                let bytes = hasher.byte_count();
+                self.combined = combine.fold(self.combined, crc);
+                self.bytes += bytes;
+                self.entities.insert(
+                    entity,
+                    CachedEntity {
+                        archetype: archetype.id(),
+                        crc,
+                        bytes,
+                        pass,
+                    },
+                );
+                self.recomputed += 1;
+            }
+        }
+
+        // despawned, or no longer tracked
+        let (combined, bytes) = (&mut self.combined, &mut self.bytes);
+        self.entities.retain(|_, cached| {
+            if cached.pass == pass {
+                return true;
+            }
+            *combined = combine.unfold(*combined, cached.crc);
+            *bytes -= cached.bytes;
+            false
+        });
+        self.last_run = Some(this_run);
+
+        let mut combined = self.combined;
+        let global_input = desync_data.serialize_global_input(world);
+        if !global_input.is_empty() {
+            combined = combine.fold(combined, algorithm.checksum(&global_input));
+        }
+        let full_crc = algorithm.truncate(combined);
+        let status = DesyncStatus {
+            crc: full_crc as u16,
+            tick: 0,
+            entity_count: self.entities.len() as u32,
+            byte_count: self.bytes as u32,
+        };
+        (status, full_crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{
+        calculate_crc_detailed, AppDesyncExt, CombineStrategy, CrcAlgorithm, DesyncPlugin, FullCrc,
+        TrackDesync,
+    };
+
+    #[derive(Component, Serialize)]
+    struct Foo(u64);
+
+    #[derive(Component, Serialize)]
+    struct Velocity(i32);
+
+    #[derive(Component)]
+    struct Untracked;
+
+    /// xorshift, so the test is reproducible without a dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn mutate(app: &mut App, rng: &mut Rng) {
+        let entities = app
+            .world
+            .iter_entities()
+            .map(|e| e.id())
+            .collect::<Vec<_>>();
+        for _ in 0..rng.below(6) {
+            let entity = match entities.is_empty() {
+                true => None,
+                false => Some(entities[rng.below(entities.len() as u64) as usize]),
+            };
+            let Some(mut entity) = entity.and_then(|e| app.world.get_entity_mut(e)) else {
+                app.world.spawn((Foo(rng.below(4)), TrackDesync));
+                continue;
+            };
+            match rng.below(8) {
+                0 => {
+                    entity.despawn();
+                }
+                1 => {
+                    entity.insert(Velocity(rng.below(3) as i32));
+                }
+                2 => {
+                    entity.remove::<Velocity>();
+                }
+                3 => {
+                    entity.remove::<TrackDesync>();
+                }
+                4 => {
+                    entity.insert(TrackDesync);
+                }
+                5 => {
+                    entity.insert(Untracked);
+                }
+                _ => {
+                    if let Some(mut foo) = entity.get_mut::<Foo>() {
+                        foo.0 = rng.below(4);
+                    }
+                }
+            }
+        }
+        // spawns reuse despawned indices with a new generation
+        if rng.below(3) == 0 {
+            app.world
+                .spawn((Foo(rng.below(4)), Velocity(1), TrackDesync));
+        }
+    }
+
+    #[test]
+    fn incremental_matches_full_recompute() {
+        for (combine, crc_algorithm) in [
+            (CombineStrategy::Xor, CrcAlgorithm::Ibm16),
+            (CombineStrategy::Sum, CrcAlgorithm::Ibm16),
+            (CombineStrategy::Sum, CrcAlgorithm::Crc64Xz),
+        ] {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                combine,
+                crc_algorithm,
+                incremental: true,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            app.track_desync::<Velocity>();
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+            for _ in 0..200 {
+                mutate(&mut app, &mut rng);
+                app.update();
+                let report = calculate_crc_detailed(&app.world).unwrap();
+                assert_eq!(app.world.resource::<FullCrc>().0, report.full_crc);
+                let status = *app.world.resource::<DesyncStatus>();
+                assert_eq!(
+                    status,
+                    DesyncStatus {
+                        tick: status.tick,
+                        ..DesyncStatus::from_report(&report)
+                    }
+                );
+            }
+
+            // nothing to hash again
+            app.update();
+            assert_eq!(app.world.resource::<EntityCrcCache>().recomputed(), 0);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use bevy_ecs::{component::Component, entity::Entity, query::With, system::Resource, world::World};
+use std::collections::HashSet;
+
+use crate::{report::calculate_crc_scoped, CrcScope};
+
+/// Hash of a tracked entity's tracked components, as in [`crate::EntityReport::crc`]. Inserted
+/// and kept up to date by `update_crc` when [`crate::DesyncPlugin::entity_hashes`] is set, so
+/// debug tools can show which entity diverges from a peer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Component)]
+pub struct DesyncHash(pub u64);
+
+/// Marks that `update_crc` should write [`DesyncHash`]
+#[derive(Debug, Default, Resource)]
+pub(crate) struct EntityHashes;
+
+/// Insert or update [`DesyncHash`] on every hashed entity, and remove it from entities which are
+/// no longer hashed. Unchanged hashes aren't written, so `Changed<DesyncHash>` only matches
+/// entities whose hash actually changed
+pub(crate) fn update_entity_hashes(world: &mut World) {
+    let report = calculate_crc_scoped(world, CrcScope::Shared, false);
+    let mut hashed = HashSet::with_capacity(report.entities.len());
+    for entity_report in report.entities.iter() {
+        let hash = DesyncHash(entity_report.crc());
+        hashed.insert(entity_report.entity);
+        let mut entity = world.entity_mut(entity_report.entity);
+        if entity.get::<DesyncHash>() != Some(&hash) {
+            entity.insert(hash);
+        }
+    }
+    let stale = world
+        .query_filtered::<Entity, With<DesyncHash>>()
+        .iter(world)
+        .filter(|e| !hashed.contains(e))
+        .collect::<Vec<_>>();
+    for entity in stale {
+        world.entity_mut(entity).remove::<DesyncHash>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{calculate_crc_detailed, AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[test]
+    fn hashes_match_report() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            entity_hashes: true,
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        let entities = (0..3)
+            .map(|i| app.world.spawn((Health(i), TrackDesync)).id())
+            .collect::<Vec<_>>();
+        let untracked = app.world.spawn(Health(0)).id();
+        app.update();
+
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        assert_eq!(report.entities.len(), entities.len());
+        for entity_report in report.entities.iter() {
+            assert_eq!(
+                app.world.get::<DesyncHash>(entity_report.entity),
+                Some(&DesyncHash(entity_report.crc()))
+            );
+        }
+        assert_ne!(
+            app.world.get::<DesyncHash>(entities[0]),
+            app.world.get::<DesyncHash>(entities[1])
+        );
+        assert_eq!(app.world.get::<DesyncHash>(untracked), None);
+
+        // no longer tracked
+        app.world.entity_mut(entities[0]).remove::<TrackDesync>();
+        app.update();
+        assert_eq!(app.world.get::<DesyncHash>(entities[0]), None);
+    }
+}
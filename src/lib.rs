@@ -1,30 +1,312 @@
-use bevy_app::{App, First, Plugin};
+use bevy_app::{App, AppExit, First, Last, Plugin};
 use bevy_ecs::{
+    archetype::Archetype,
     component::{Component, ComponentId},
     entity::{Entity, EntityMapper},
+    event::{Event, EventUpdates, Events},
     ptr::Ptr,
-    system::Resource,
+    query::QueryFilter,
+    reflect::AppTypeRegistry,
+    schedule::{
+        common_conditions::{not, on_event, resource_exists},
+        Condition, InternedScheduleLabel, IntoSystemConfigs, SystemSet,
+    },
+    system::{Res, Resource},
     world::World,
 };
-use serde::Serialize;
-use std::collections::HashMap;
+use bevy_reflect::{serde::TypedReflectSerializer, FromReflect, GetTypeRegistration, Reflect};
+use bevy_utils::tracing::{debug, error};
+use serde::{de::DeserializeOwned, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
+// lets the derive's generated paths resolve within this crate too
+extern crate self as bevy_mod_desync;
+
+mod algorithm;
+mod blame;
+mod cache;
+mod canonical;
+mod capture;
+mod churn;
+mod config;
+mod delta;
+mod derived;
+mod dynamic;
+mod encoding;
+mod error;
+mod export;
+mod float;
+#[cfg(feature = "ggrs")]
+mod ggrs;
+mod golden;
+mod graph;
+mod group;
+#[cfg(feature = "hierarchy")]
+mod hierarchy;
+mod history;
+mod identity;
+mod incremental;
+mod inspect;
+mod limit;
+mod mapping;
+mod merkle;
+mod migration;
+mod net;
+mod ops;
+#[cfg(any(test, feature = "test-utils"))]
+mod order;
+mod peer;
+mod recorder;
+mod reflected;
+mod replay;
+mod report;
+mod rollback;
+mod snapshot;
+mod spatial;
+mod tolerance;
+#[cfg(feature = "transform")]
+mod transform;
+mod weighted;
+
+pub use algorithm::CrcAlgorithm;
+#[cfg(feature = "derive")]
+pub use bevy_mod_desync_derive::TrackDesync;
+pub use blame::minimal_blame;
+use cache::calculate_status_with;
+pub use cache::{calculate_crc_cached, ArchetypeCrcCache};
+pub use canonical::{to_canonical_json, CanonicalError};
+pub use capture::{DesyncCapture, DesyncCaptures};
+use churn::update_churn;
+pub use churn::ComponentChurn;
+pub use config::{ConfigDiff, TrackingConfig};
+pub use delta::{calculate_crc_changed_since, delta_crc, DesyncSnapshot};
+pub use derived::{DesyncComponent, DesyncComponents};
+use dynamic::{serialize_dyn, Unregistered};
+pub use encoding::HashEncoding;
+use encoding::{encode_value, HashOptions};
+pub use error::DesyncError;
+use error::ErrorSlot;
+pub use export::{export_tracked_records, tracked_state_to_json};
+pub use float::{FloatCanonicalization, FloatOptions, WithFloatOptions};
+#[cfg(feature = "ggrs")]
+pub use ggrs::GgrsChecksumPlugin;
+pub use golden::{write_golden, GoldenComparison, GoldenDeviation};
+pub use graph::calculate_archetype_graph_crc;
+use group::update_group_crcs;
+pub use group::{GroupCrcs, GroupId};
+#[cfg(feature = "hierarchy")]
+pub use hierarchy::ChildOrder;
+#[cfg(feature = "hierarchy")]
+use hierarchy::MappedChildren;
+pub use history::{compare_against, Comparison, CrcHistory, DesyncTick, RollingCrc};
+pub use identity::{
+    sort_by_component, sort_by_component_with, sort_by_desync_key, ComponentKeyIdentity,
+    DesyncIdentity, DesyncKey, EntityBitsIdentity, UnkeyedEntities,
+};
+pub use incremental::EntityCrcCache;
+pub use inspect::DesyncHash;
+use inspect::{update_entity_hashes, EntityHashes};
+pub use limit::ComponentLimitExceeded;
+use limit::{check_component_limit, ComponentLimit};
+use mapping::{EntityLookup, LookupCache, MappedValue};
+pub use merkle::MerkleCrc;
+pub use migration::DualCrc;
+pub use net::{
+    flush_desync_messages, per_entity_message, CrcMessage, CrcMessages, DesyncDetected,
+    DesyncFlush, DesyncStatus,
+};
+use net::{flush_on_exit, queue_crc_message};
+pub use ops::{ComponentOp, ComponentOpKind, ComponentOpLog, TrackedOpsExt};
+#[cfg(any(test, feature = "test-utils"))]
+pub use order::assert_order_independent;
+pub use peer::{compare_peer_crc, NeedsResync, PeerDesyncState, PeerId};
+use recorder::{record_tick, Recording};
+pub use recorder::{DesyncRecorder, OpenSegmentFn};
+use reflected::check_reflect_serializable;
+pub use reflected::ReflectTrackError;
+use replay::validate_replay;
+pub use replay::{ReplayDivergence, ReplayValidator};
+pub use report::{
+    calculate_crc_detailed, diff_worlds, json_field_diff, AuditEntry, ComponentDiff,
+    ComponentReport, CrcAudit, DesyncEntry, DesyncReport, DiffSide, EntityReport,
+};
+use report::{calculate_crc_scoped, calculate_status, collect_input};
+use rollback::record_rollback_checksum;
+pub use rollback::{RollbackChecksum, RollbackChecksums, RollbackFrame};
+pub use snapshot::{
+    assert_save_load_stable, check_save_load_stable, SaveLoadError, TrackedSnapshot,
+};
+pub use spatial::{sort_by_spatial_grid, GridPosition};
+use tolerance::apply_tolerances;
+pub use tolerance::FloatTolerances;
+#[cfg(feature = "transform")]
+pub use transform::{canonical_trs, CanonicalTrs, Trs};
+pub use weighted::{CriticalDesync, WeightedCrc};
+
+/// Used by the derive's generated code
+#[doc(hidden)]
+pub mod __macro {
+    pub use bevy_app::App;
+}
+
+/// Function used to order the entities which are hashed
+pub type EntitySortFn = Arc<Box<dyn Fn(&World) -> Vec<Entity> + Send + Sync>>;
+
+/// Function deciding whether an entity is included
+pub type EntityPredicateFn = Arc<dyn Fn(Entity, &World) -> bool + Send + Sync>;
+
+/// Function deciding whether the entities of an archetype are tracked
+pub type ArchetypeFilterFn = Arc<dyn Fn(&Archetype, &World) -> bool + Send + Sync>;
+
+/// Function returning the tick a CRC is recorded against
+pub type TickSourceFn = Arc<dyn Fn(&World) -> u64 + Send + Sync>;
+
+/// Function called with each tick and its CRC, see [`DesyncPlugin::on_crc`]
+pub type CrcCallbackFn = Arc<dyn Fn(u64, u16) + Send + Sync>;
+
 /// Bevy Plugin to detect desyncs
 pub struct DesyncPlugin {
     /// Whether to add the update_crc system. Set to false if you want to add this yourself to
     /// control execution
     pub add_system: bool,
+    /// Add `update_crc` to this schedule instead of `First`, e.g. `FixedPostUpdate.intern()` so
+    /// CRCs are recorded once per fixed step after simulation rather than once per frame.
+    /// [`DesyncTick`] then counts fixed steps. Tracked events are hashed on every step until
+    /// they're swapped out at the start of the next frame
+    pub schedule: Option<InternedScheduleLabel>,
     /// Function for sorting entities before hashing. A default implementation which will likely
-    /// trigger false positives is provided.
-    pub entity_sort: Arc<Box<dyn Fn(&World) -> Vec<Entity> + Send + Sync>>,
+    /// trigger false positives is provided. Every entity it returns is hashed, so it's also
+    /// responsible for leaving out entities which aren't tracked, as the provided sorts do by
+    /// starting from [`unordered_tracked_entities`]
+    pub entity_sort: EntitySortFn,
+    /// Name of `entity_sort`, so [`TrackingConfig`]s of apps sorting differently can be told apart
+    pub entity_sort_name: &'static str,
+    /// Number of past CRCs kept in [`CrcHistory`]
+    pub history_len: usize,
+    /// Number of ticks folded into [`RollingCrc`]
+    pub rolling_window: usize,
+    /// How per-entity data is combined into the CRC
+    pub combine: CombineStrategy,
+    /// How floats in tracked components are serialized
+    pub float_options: FloatOptions,
+    /// How tracked components are encoded for hashing
+    pub hash_encoding: HashEncoding,
+    /// Record every serialized component in [`CrcAudit`] each tick. This is expensive, so only
+    /// enable it while debugging
+    pub audit: bool,
+    /// Stable key for entities when they're identified outside the world, see [`DesyncIdentity`]
+    pub identity: Arc<dyn DesyncIdentity>,
+    /// Whether this app is the authority. Only the authority maintains [`LocalCrc`]
+    pub role: DesyncRole,
+    /// Cache each archetype's contribution to the CRC between ticks, see [`ArchetypeCrcCache`].
+    /// Only has an effect with a commutative `combine` strategy
+    pub cache_archetypes: bool,
+    /// Keep each entity's contribution to the CRC between ticks, only hashing entities which
+    /// changed again, see [`EntityCrcCache`]. Only has an effect with a commutative `combine`
+    /// strategy. Takes precedence over `cache_archetypes`
+    pub incremental: bool,
+    /// What to do when an entity only exists on one side of an entity map, see
+    /// [`MissingEntityPolicy`]
+    pub missing_entities: MissingEntityPolicy,
+    /// Warn, and send [`ComponentLimitExceeded`], when a tracked entity has more than this many
+    /// tracked components
+    pub component_limit: Option<usize>,
+    /// Hash the order tracked components were inserted and removed in each tick, see
+    /// [`ComponentOpLog`]
+    pub hash_component_ops: bool,
+    /// Prefix each entity's hashed components with a hash of their sorted type names, so an entity
+    /// tracking a different set of components always changes the CRC, even if the extra component
+    /// serializes to nothing or the rest happen to line up
+    pub schema_prefix: bool,
+    /// Keep a [`DesyncHash`] on every tracked entity. Each tick this hashes every entity again
+    /// and writes components, so only enable it for debugging
+    pub entity_hashes: bool,
+    /// Start with `update_crc` enabled. Tracking can be set up dormant and enabled later with
+    /// [`DesyncWorldExt::set_desync_enabled`]
+    pub enabled: bool,
+    /// Buffer outgoing and received CRCs in [`CrcMessages`], flushing them on `AppExit`
+    pub message_queue: bool,
+    /// Track mismatch streaks per peer in [`PeerDesyncState`], sending [`NeedsResync`] when a
+    /// streak reaches this many consecutive ticks, see [`compare_peer_crc`]
+    pub resync_threshold: Option<u32>,
+    /// Capture the tracked state in a [`DesyncCapture`] when a remote CRC first mismatches, then
+    /// wait this many ticks before capturing again, see [`DesyncCaptures`]
+    pub capture_on_desync: Option<u64>,
+    /// Count how often each tracked component type changes over this many CRCs, see
+    /// [`ComponentChurn`]
+    pub churn_window: Option<usize>,
+    /// Tag CRCs with the tick this returns, e.g. read from the networking layer's frame counter,
+    /// rather than counting calls to `update_crc` in [`DesyncTick`]. [`DesyncTick`] then follows
+    /// the returned tick
+    pub tick_source: Option<TickSourceFn>,
+    /// Checksum the world is hashed with, see [`CrcAlgorithm`]
+    pub crc_algorithm: CrcAlgorithm,
+    /// Called by `update_crc` with every CRC it records, and the tick it's recorded against in
+    /// [`CrcHistory`], e.g. to send it in an existing replication message or count it in a metric.
+    /// Not called for a tick whose CRC failed
+    pub on_crc: Option<CrcCallbackFn>,
+    /// Keep the checksums of this many frames for rollback netcode, see [`RollbackChecksums`]
+    pub rollback_checksums: Option<usize>,
+    /// Write the tracked state behind every CRC to a log, see [`DesyncRecorder`]
+    pub recorder: Option<DesyncRecorder>,
+    /// Which entities are tracked, see [`TrackingMode`]
+    pub tracking_mode: TrackingMode,
+    /// Also hash the tracked components of tracked entities' descendants, which needn't be marked
+    /// themselves. Each entity is followed by its descendants, depth first in
+    /// [`bevy_hierarchy::Children`] order. A descendant which is tracked itself is hashed in its
+    /// own place instead, along with its descendants. The CRC caches are bypassed while this is set
+    #[cfg(feature = "hierarchy")]
+    pub include_hierarchy: bool,
+}
+
+/// Which entities are tracked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrackingMode {
+    /// Entities marked with [`TrackDesync`]
+    #[default]
+    OptIn,
+    /// Every entity with at least one tracked component, unless it's marked with [`IgnoreDesync`].
+    /// Replaced by [`AppDesyncExt::track_desync_filter`] like the marker is
+    OptOut,
 }
 
 impl Default for DesyncPlugin {
     fn default() -> Self {
         DesyncPlugin {
             add_system: true,
+            schedule: None,
             entity_sort: Arc::new(Box::new(sort_entities_ids)),
+            entity_sort_name: "sort_entities_ids",
+            history_len: 64,
+            rolling_window: 8,
+            combine: CombineStrategy::default(),
+            float_options: FloatOptions::default(),
+            hash_encoding: HashEncoding::default(),
+            audit: false,
+            identity: Arc::new(EntityBitsIdentity),
+            role: DesyncRole::default(),
+            cache_archetypes: false,
+            incremental: false,
+            missing_entities: MissingEntityPolicy::default(),
+            component_limit: None,
+            hash_component_ops: false,
+            schema_prefix: false,
+            entity_hashes: false,
+            enabled: true,
+            message_queue: false,
+            resync_threshold: None,
+            capture_on_desync: None,
+            churn_window: None,
+            tick_source: None,
+            crc_algorithm: CrcAlgorithm::default(),
+            on_crc: None,
+            rollback_checksums: None,
+            recorder: None,
+            tracking_mode: TrackingMode::default(),
+            #[cfg(feature = "hierarchy")]
+            include_hierarchy: false,
         }
     }
 }
@@ -33,41 +315,504 @@ impl Plugin for DesyncPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(DesyncPluginData {
             entity_sort: self.entity_sort.clone(),
+            entity_sort_name: self.entity_sort_name.to_string(),
+            combine: self.combine,
+            float_options: self.float_options,
+            hash_encoding: self.hash_encoding,
+            identity: self.identity.clone(),
+            role: self.role,
+            missing_entities: self.missing_entities,
+            schema_prefix: self.schema_prefix,
+            tick_source: self.tick_source.clone(),
+            crc_algorithm: self.crc_algorithm,
+            on_crc: self.on_crc.clone(),
+            enabled: self.enabled,
+            #[cfg(feature = "hierarchy")]
+            include_hierarchy: self.include_hierarchy,
             ..Default::default()
         })
-        .init_resource::<Crc>();
+        .init_resource::<Crc>()
+        .init_resource::<FullCrc>()
+        .init_resource::<DesyncTick>()
+        .init_resource::<DesyncStatus>()
+        .add_event::<DesyncDetected>()
+        .insert_resource(CrcHistory::new(self.history_len))
+        .insert_resource(RollingCrc::new(self.rolling_window));
         app.world.init_component::<TrackDesync>();
+        if self.tracking_mode == TrackingMode::OptOut {
+            let ignore = app.world.init_component::<IgnoreDesync>();
+            app.world
+                .resource_mut::<DesyncPluginData>()
+                .tracked_archetypes = Arc::new(move |archetype, world| {
+                let registry = &world.resource::<DesyncPluginData>().serialize_fn_registry;
+                !archetype.contains(ignore)
+                    && archetype.components().any(|c| registry.contains_key(&c))
+            });
+        }
+        if self.audit {
+            app.init_resource::<CrcAudit>();
+        }
+        if self.role == DesyncRole::Authority {
+            app.init_resource::<LocalCrc>();
+        }
+        if self.cache_archetypes {
+            app.init_resource::<ArchetypeCrcCache>();
+        }
+        if self.incremental {
+            app.init_resource::<EntityCrcCache>();
+        }
+        if self.hash_component_ops {
+            app.init_resource::<ComponentOpLog>();
+        }
+        if self.entity_hashes {
+            app.init_resource::<EntityHashes>();
+        }
+        if self.message_queue {
+            app.init_resource::<CrcMessages>()
+                .add_systems(Last, flush_on_exit.run_if(on_event::<AppExit>()));
+        }
+        if let Some(window) = self.churn_window {
+            app.insert_resource(ComponentChurn::new(window));
+        }
+        if let Some(threshold) = self.resync_threshold {
+            app.add_event::<NeedsResync>()
+                .insert_resource(PeerDesyncState::new(threshold));
+        }
+        if let Some(cooldown) = self.capture_on_desync {
+            app.add_event::<DesyncCapture>()
+                .insert_resource(DesyncCaptures::new(cooldown));
+        }
+        if let Some(capacity) = self.rollback_checksums {
+            app.insert_resource(RollbackChecksums::new(capacity));
+        }
+        if let Some(recorder) = &self.recorder {
+            app.insert_resource(Recording::new(recorder.clone()));
+        }
+        if let Some(limit) = self.component_limit {
+            app.add_event::<ComponentLimitExceeded>()
+                .insert_resource(ComponentLimit::new(limit));
+        }
 
         if self.add_system {
-            app.add_systems(First, update_crc);
+            match self.schedule {
+                // hash events before they're swapped out of the current buffer
+                None => app.add_systems(
+                    First,
+                    (
+                        record_init_crc
+                            .run_if(not(resource_exists::<InitCrc>).and_then(tracks_anything)),
+                        update_crc,
+                    )
+                        .chain()
+                        .in_set(DesyncSet::CalculateCrc)
+                        .before(EventUpdates),
+                ),
+                Some(schedule) => app
+                    .add_systems(
+                        First,
+                        record_init_crc
+                            .run_if(not(resource_exists::<InitCrc>).and_then(tracks_anything))
+                            .in_set(DesyncSet::CalculateCrc),
+                    )
+                    .add_systems(schedule, update_crc.in_set(DesyncSet::CalculateCrc)),
+            };
+        }
+    }
+}
+
+/// System sets the plugin's systems run in, for ordering them against the rest of the app, e.g.
+/// `app.configure_sets(First, DesyncSet::CalculateCrc.after(ApplyNetworkState))`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum DesyncSet {
+    /// `update_crc`, in `First` or the plugin's `schedule`, and recording the [`InitCrc`] in
+    /// `First`. Only populated when `add_system` is set
+    CalculateCrc,
+}
+
+/// How the serialized entities are combined into the final CRC
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CombineStrategy {
+    /// Concatenate every entity's serialized components in `entity_sort` order and hash the result.
+    /// Sensitive to entity order
+    #[default]
+    Concatenate,
+    /// Hash each entity separately and XOR the hashes together. Independent of entity order, so
+    /// `entity_sort` is never called. Note that two identical entities cancel each other out
+    Xor,
+    /// Hash each entity separately and add the hashes together (wrapping). Independent of entity
+    /// order, so `entity_sort` is never called
+    Sum,
+}
+
+impl CombineStrategy {
+    /// Whether the result is independent of the order entities are combined in
+    pub fn is_commutative(&self) -> bool {
+        !matches!(self, CombineStrategy::Concatenate)
+    }
+
+    /// Combine one hash into the running value of a commutative strategy
+    pub(crate) fn fold(&self, combined: u64, crc: u64) -> u64 {
+        match self {
+            CombineStrategy::Concatenate => unreachable!("concatenation isn't a fold"),
+            CombineStrategy::Xor => combined ^ crc,
+            CombineStrategy::Sum => combined.wrapping_add(crc),
+        }
+    }
+
+    /// Take a hash folded in with [`CombineStrategy::fold`] back out of the running value
+    pub(crate) fn unfold(&self, combined: u64, crc: u64) -> u64 {
+        match self {
+            CombineStrategy::Concatenate => unreachable!("concatenation isn't a fold"),
+            CombineStrategy::Xor => combined ^ crc,
+            CombineStrategy::Sum => combined.wrapping_sub(crc),
         }
     }
 }
 
-/// CRC Resource - contains the hash of the ECS world at the start of the tick
+/// CRC Resource - contains the hash of the ECS world at the start of the tick. Only the low 16
+/// bits of [`FullCrc`], whichever [`CrcAlgorithm`] is configured
 #[derive(Debug, Default, PartialEq, Resource)]
 pub struct Crc(pub u16);
 
-#[derive(Resource)]
+/// CRC the next `update_crc` must produce, or it panics, e.g. to fail a determinism regression
+/// test the moment the state diverges from a recorded golden value. Update or remove it between
+/// ticks; nothing is checked while it isn't in the world
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub struct StrictCrc(pub u16);
+
+/// [`Crc`] at the full width of [`DesyncPlugin::crc_algorithm`], which [`Crc`] holds the low 16
+/// bits of
+#[derive(Debug, Default, PartialEq, Resource)]
+pub struct FullCrc(pub u64);
+
+/// Like [`Crc`], but also including components registered with
+/// [`AppDesyncExt::track_desync_authority_only`]. Only maintained by the authority, for checks
+/// which don't involve clients
+#[derive(Debug, Default, PartialEq, Resource)]
+pub struct LocalCrc(pub u16);
+
+/// CRC of the world once the startup schedules have run, before any gameplay. Differing initial
+/// state, e.g. from an unseeded rng or load order in startup systems, shows up here at frame zero.
+/// Inserted once by the first update, regardless of [`DesyncPluginData::enabled`]
+#[derive(Debug, PartialEq, Resource)]
+pub struct InitCrc(pub u16);
+
+/// Which side of a client/server setup this app is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DesyncRole {
+    /// Holds state clients don't have, hashed into [`LocalCrc`]
+    Authority,
+    #[default]
+    Client,
+}
+
+/// What to do with an entity which is in the entity map on one peer but has nothing to map to on
+/// the other, or which `entity_sort` returned but is no longer in the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingEntityPolicy {
+    /// Leave the entity out, as if it didn't exist
+    #[default]
+    Skip,
+    /// Hash a placeholder where the entity would be, so the CRC reliably differs from a peer which
+    /// does have it
+    Sentinel,
+    /// Fail the CRC with [`DesyncError::MissingEntity`]
+    Error,
+}
+
+impl MissingEntityPolicy {
+    /// Apply the policy to a missing entity, returning the entity if it should be hashed as a
+    /// placeholder. Under `Error` the error is recorded and the entity is left out
+    pub(crate) fn missing(&self, entity: Entity, errors: &ErrorSlot) -> Option<Entity> {
+        match self {
+            MissingEntityPolicy::Skip => None,
+            MissingEntityPolicy::Sentinel => Some(entity),
+            MissingEntityPolicy::Error => {
+                errors.record(DesyncError::MissingEntity(entity));
+                None
+            }
+        }
+    }
+}
+
+/// Hashed in place of a missing entity under [`MissingEntityPolicy::Sentinel`]. Not valid JSON, so
+/// no serialized component can collide with it
+pub(crate) const MISSING_ENTITY_SENTINEL: &str = "!missing";
+
+/// Which components are hashed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum CrcScope {
+    /// Components every peer has, compared between peers
+    Shared,
+    /// Also include authority-only components
+    Local,
+}
+
+#[derive(Clone, Resource)]
 pub struct DesyncPluginData {
-    serialize_fn_registry: HashMap<ComponentId, unsafe fn(Ptr) -> String>,
-    pub entity_sort: Arc<Box<dyn Fn(&World) -> Vec<Entity> + Send + Sync>>,
+    /// Only looked up while hashing, never iterated, so its order can't reach the CRC. Components
+    /// are hashed in type name order
+    serialize_fn_registry: HashMap<ComponentId, ComponentFns>,
+    /// Tracked resources by type name, which is the order they're hashed in
+    resource_serialize_fn_registry: BTreeMap<&'static str, ResourceFns>,
+    /// Tracked event types by type name, which is the order they're hashed in
+    event_serialize_fn_registry: BTreeMap<&'static str, ResourceFns>,
+    /// Group of each component registered with [`AppDesyncExt::track_desync_in`]. Only looked up
+    /// while hashing, like `serialize_fn_registry`
+    component_groups: HashMap<ComponentId, GroupId>,
+    pub entity_sort: EntitySortFn,
+    pub entity_sort_name: String,
+    pub combine: CombineStrategy,
+    pub float_options: FloatOptions,
+    pub hash_encoding: HashEncoding,
+    pub identity: Arc<dyn DesyncIdentity>,
+    pub role: DesyncRole,
+    pub missing_entities: MissingEntityPolicy,
+    pub schema_prefix: bool,
+    pub tick_source: Option<TickSourceFn>,
+    pub crc_algorithm: CrcAlgorithm,
+    pub on_crc: Option<CrcCallbackFn>,
+    /// Which archetypes hold tracked entities. [`TrackDesync`], or the plugin's [`TrackingMode`],
+    /// unless replaced with [`AppDesyncExt::track_desync_filter`]
+    pub tracked_archetypes: ArchetypeFilterFn,
+    /// Whether `update_crc` does anything, see [`DesyncWorldExt::set_desync_enabled`]
+    pub enabled: bool,
+    #[cfg(feature = "hierarchy")]
+    include_hierarchy: bool,
+    /// The first error hit by the CRC being calculated
+    errors: ErrorSlot,
+}
+
+/// Type erased equality used when diffing two worlds. Both pointers must be of the registered
+/// component type
+type EqFn = Arc<dyn Fn(Ptr, Ptr) -> bool + Send + Sync>;
+
+/// Type erased serializer appending the bytes which are hashed to the buffer. The pointer must be
+/// of the registered component type, from the given world
+type SerializeFn =
+    Arc<dyn Fn(Ptr, &World, &HashOptions, &mut Vec<u8>) -> Result<(), String> + Send + Sync>;
+
+/// Type erased serializer producing the form shown in reports. The pointer must be of the
+/// registered component type
+type ReadableFn = Arc<dyn Fn(Ptr) -> String + Send + Sync>;
+
+/// Type erased functions for a component registered with [`AppDesyncExt::track_desync`]
+#[derive(Clone)]
+struct ComponentFns {
+    serialize: SerializeFn,
+    /// Names how `serialize` was registered, for [`TrackingConfig`]
+    serializer: &'static str,
+    /// Human readable form for reports. Only needed if the hashed bytes aren't readable
+    /// themselves, which they are (as JSON) unless a custom hash serializer is registered
+    readable: Option<ReadableFn>,
+    /// Custom equality for diff reporting. This never affects the CRC
+    eq: Option<EqFn>,
+    /// Only hashed into [`LocalCrc`]
+    authority_only: bool,
+    /// Registered with [`AppDesyncExt::track_desync_saveable`]
+    snapshot: Option<SnapshotFns>,
+}
+
+impl ComponentFns {
+    /// Hashed with `serialize`, named `serializer`, with nothing else set
+    fn new(
+        serializer: &'static str,
+        serialize: impl Fn(Ptr, &World, &HashOptions, &mut Vec<u8>) -> Result<(), String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        ComponentFns {
+            serialize: Arc::new(serialize),
+            serializer,
+            readable: None,
+            eq: None,
+            authority_only: false,
+            snapshot: None,
+        }
+    }
+
+    /// Hashed with `T`'s `Serialize` impl
+    fn serde<T: Component + Serialize>(serializer: &'static str) -> Self {
+        ComponentFns::new(serializer, |ptr, _, options, out| unsafe {
+            // SAFETY: caller guarantees the pointer is of type T
+            untyped_serialize::<T>(ptr, options, out)
+        })
+    }
+}
+
+/// Type erased functions for saving and loading a component in a [`TrackedSnapshot`]
+#[derive(Clone)]
+struct SnapshotFns {
+    /// Register the component in another world
+    init: fn(&mut World) -> ComponentId,
+    /// Serialize the component as JSON. The pointer must be of the registered component type
+    save: unsafe fn(Ptr) -> Result<String, String>,
+    /// Deserialize the component and insert it on the entity
+    load: fn(&mut World, Entity, &str) -> Result<(), String>,
+}
+
+/// Type erased functions for a resource registered with [`AppDesyncExt::track_desync_resource`],
+/// or an event type registered with [`AppDesyncExt::track_desync_events`]
+#[derive(Clone)]
+struct ResourceFns {
+    /// Type name, which the registry is keyed by so hashing doesn't depend on registration order
+    name: &'static str,
+    /// Returns `None` if the resource isn't in the world
+    serialize: fn(&World, &FloatOptions) -> Option<Result<String, String>>,
 }
 
 impl Default for DesyncPluginData {
     fn default() -> Self {
         DesyncPluginData {
             serialize_fn_registry: HashMap::default(),
+            resource_serialize_fn_registry: BTreeMap::new(),
+            event_serialize_fn_registry: BTreeMap::new(),
+            component_groups: HashMap::default(),
             entity_sort: Arc::new(Box::new(sort_entities_ids)),
+            entity_sort_name: "sort_entities_ids".to_string(),
+            combine: CombineStrategy::default(),
+            float_options: FloatOptions::default(),
+            hash_encoding: HashEncoding::default(),
+            identity: Arc::new(EntityBitsIdentity),
+            role: DesyncRole::default(),
+            missing_entities: MissingEntityPolicy::default(),
+            schema_prefix: false,
+            tick_source: None,
+            crc_algorithm: CrcAlgorithm::default(),
+            on_crc: None,
+            tracked_archetypes: Arc::new(|archetype, world| {
+                world
+                    .component_id::<TrackDesync>()
+                    .is_some_and(|id| archetype.contains(id))
+            }),
+            enabled: true,
+            #[cfg(feature = "hierarchy")]
+            include_hierarchy: false,
+            errors: ErrorSlot::default(),
         }
     }
 }
 
 impl DesyncPluginData {
-    fn serialize(&self, ptr: Ptr, id: &ComponentId) -> String {
-        unsafe {
-            // SAFETY: components match
-            self.serialize_fn_registry[id](ptr)
+    /// Whether any component, resource or event type has been registered
+    pub(crate) fn tracks_anything(&self) -> bool {
+        !self.serialize_fn_registry.is_empty()
+            || !self.resource_serialize_fn_registry.is_empty()
+            || !self.event_serialize_fn_registry.is_empty()
+    }
+
+    /// Whether descendants of tracked entities are hashed, see [`DesyncPlugin::include_hierarchy`]
+    pub(crate) fn includes_hierarchy(&self) -> bool {
+        #[cfg(feature = "hierarchy")]
+        return self.include_hierarchy;
+        #[cfg(not(feature = "hierarchy"))]
+        false
+    }
+
+    /// Whether the entities of `archetype` are tracked
+    pub(crate) fn tracks_archetype(&self, archetype: &Archetype, world: &World) -> bool {
+        (self.tracked_archetypes)(archetype, world)
+    }
+
+    fn serialize(&self, world: &World, ptr: Ptr, id: &ComponentId) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.serialize_into(world, ptr, id, &mut out);
+        out
+    }
+
+    /// Append the hashed form of a component to `out`
+    fn serialize_into(&self, world: &World, ptr: Ptr, id: &ComponentId, out: &mut Vec<u8>) {
+        let options = HashOptions {
+            float_options: self.float_options,
+            encoding: self.hash_encoding,
+        };
+        // components match
+        if let Err(error) = (self.serialize_fn_registry[id].serialize)(ptr, world, &options, out) {
+            self.record_serialize_error(world.components().get_name(*id), error);
+        }
+    }
+
+    fn record_serialize_error(&self, type_name: Option<&str>, error: String) {
+        self.errors.record(DesyncError::Serialize {
+            type_name: type_name.unwrap_or_default().to_string(),
+            error,
+        });
+    }
+
+    /// Serialize a component for reports rather than hashing
+    fn serialize_readable(&self, world: &World, ptr: Ptr, id: &ComponentId) -> String {
+        match &self.serialize_fn_registry[id].readable {
+            // components match
+            Some(readable) => readable(ptr),
+            None => {
+                // binary encodings aren't readable, so read it as JSON instead
+                let options = HashOptions {
+                    float_options: self.float_options,
+                    encoding: HashEncoding::CanonicalJson,
+                };
+                let mut out = Vec::new();
+                let serialize = &self.serialize_fn_registry[id].serialize;
+                if let Err(error) = serialize(ptr, world, &options, &mut out) {
+                    self.record_serialize_error(world.components().get_name(*id), error);
+                }
+                String::from_utf8_lossy(&out).into_owned()
+            }
+        }
+    }
+
+    /// Serialize every tracked resource present in the world, in type name order
+    fn serialize_resources<'a>(&'a self, world: &'a World) -> impl Iterator<Item = String> + 'a {
+        self.resource_serialize_fn_registry
+            .values()
+            .filter_map(|fns| self.serialize_global(fns, world))
+    }
+
+    /// Serialize the current events of every tracked event type, in type name order
+    fn serialize_events(&self, world: &World) -> String {
+        self.event_serialize_fn_registry
+            .values()
+            .filter_map(|fns| self.serialize_global(fns, world))
+            .collect()
+    }
+
+    fn serialize_global(&self, fns: &ResourceFns, world: &World) -> Option<String> {
+        match (fns.serialize)(world, &self.float_options)? {
+            Ok(serialized) => Some(serialized),
+            Err(error) => {
+                self.record_serialize_error(Some(fns.name), error);
+                None
+            }
+        }
+    }
+
+    /// Everything hashed after the entities: the tracked resources, then what happened this tick,
+    /// i.e. the current tracked events and the [`ComponentOpLog`] if there is one
+    pub(crate) fn serialize_global_input(&self, world: &World) -> Vec<u8> {
+        let mut input = self.serialize_resources(world).collect::<String>();
+        input.push_str(&self.serialize_events(world));
+        let mut input = input.into_bytes();
+        if let Some(log) = world.get_resource::<ComponentOpLog>() {
+            input.extend(log.to_bytes());
+        }
+        input
+    }
+
+    fn is_tracked(&self, id: &ComponentId, scope: CrcScope) -> bool {
+        match self.serialize_fn_registry.get(id) {
+            Some(fns) => scope == CrcScope::Local || !fns.authority_only,
+            None => false,
+        }
+    }
+
+    /// Compare two values of a tracked component, falling back to comparing their serialized
+    /// form if no custom equality was registered
+    fn values_eq(&self, a: (&World, Ptr), b: (&World, Ptr), id: &ComponentId) -> bool {
+        let fns = &self.serialize_fn_registry[id];
+        match &fns.eq {
+            Some(eq) => eq(a.1, b.1),
+            // components match
+            None => self.serialize(a.0, a.1, id) == self.serialize(b.0, b.1, id),
         }
     }
 }
@@ -76,6 +821,43 @@ impl DesyncPluginData {
 #[derive(Component)]
 pub struct TrackDesync;
 
+/// Component to leave an entity out of desync tracking under [`TrackingMode::OptOut`]
+#[derive(Component)]
+pub struct IgnoreDesync;
+
+/// Resource to dynamically restrict which tracked entities contribute to the CRC, e.g. only those
+/// near a contested objective. Consulted in addition to the [`TrackDesync`] marker and component
+/// registrations, so interest can shift each tick without adding or removing markers.
+#[derive(Clone, Resource)]
+pub struct TrackingPredicate(pub EntityPredicateFn);
+
+impl TrackingPredicate {
+    pub fn new(predicate: impl Fn(Entity, &World) -> bool + Send + Sync + 'static) -> Self {
+        TrackingPredicate(Arc::new(predicate))
+    }
+}
+
+/// Whether entities in `archetype` are tracked, by [`TrackDesync`] or the registered
+/// [`AppDesyncExt::track_desync_filter`]
+pub(crate) fn is_tracked_entity(archetype: &Archetype, world: &World) -> bool {
+    world
+        .resource::<DesyncPluginData>()
+        .tracks_archetype(archetype, world)
+}
+
+/// Whether an entity returned by `entity_sort` should be hashed. Entities which aren't in the world
+/// are never hashed, see [`MissingEntityPolicy`]. Whether the entity is tracked isn't checked
+/// again, as that's up to `entity_sort`
+pub(crate) fn is_hashed(entity: Entity, world: &World) -> bool {
+    if world.get_entity(entity).is_none() {
+        return false;
+    }
+    match world.get_resource::<TrackingPredicate>() {
+        Some(predicate) => (predicate.0)(entity, world),
+        None => true,
+    }
+}
+
 // to track an entity we need:
 // * component marked with app.track_desync()
 // * entity marked with TrackDesync
@@ -87,63 +869,662 @@ pub struct TrackDesync;
 
 pub trait AppDesyncExt {
     fn track_desync<T: Component + Serialize>(&mut self);
+    /// Track a component, using a custom comparator when reporting which components differ
+    /// between two worlds. The CRC is still calculated from the serialized component, so this
+    /// only refines what [`diff_worlds`] reports as divergent.
+    fn track_desync_with_eq<T: Component + Serialize>(&mut self, eq: fn(&T, &T) -> bool);
+    /// Track a component which only the authority has. It's excluded from [`Crc`], so clients
+    /// can still compare against the authority, but included in the authority's [`LocalCrc`]
+    fn track_desync_authority_only<T: Component + Serialize>(&mut self);
+    /// Track a component as part of a named group, e.g. "physics", whose CRC is kept in
+    /// [`GroupCrcs`] to tell which system desynced. An entity's components can be in different
+    /// groups, but each component is only in the group it was last registered to
+    fn track_desync_in<T: Component + Serialize>(&mut self, group: &str);
+    /// Track components implementing [`DesyncComponent`], usually with `#[derive(TrackDesync)]`.
+    /// Register several at once with a tuple, e.g. `app.track_desync_derived::<(Position,
+    /// Velocity)>()`, so the list can be kept next to the components
+    fn track_desync_derived<T: DesyncComponents>(&mut self);
+    /// Track a resource. Resources are hashed into the CRC after the entities, in order of their
+    /// type names, so peers registering resources in a different order still match. Resources
+    /// are skipped while they aren't in the world. [`calculate_resource_crc`] hashes them alone
+    fn track_desync_resource<R: Resource + Serialize>(&mut self);
+    /// Track an event type. The events sent since the last event update are hashed in the order
+    /// they were sent, so peers which processed different inputs diverge even if their state
+    /// hasn't yet
+    fn track_desync_events<E: Event + Serialize>(&mut self);
+    /// Designate `K` as the component providing each entity's stable key. Entities are sorted by
+    /// the key with [`sort_by_desync_key`], and it's used as the [`DesyncIdentity`]
+    fn track_desync_key<K: Component + DesyncKey>(&mut self);
+    /// Track a component, snapping floats to a per-field tolerance before hashing so small drift
+    /// doesn't register as a desync, apart from values right on a rounding boundary, which drift
+    /// across it however small. Fields are found by name using reflection, and a field's tolerance
+    /// applies to every float nested inside it, e.g. `("translation", 1e-4)` covers all three
+    /// axes. A tolerance of zero keeps a field exact.
+    fn track_desync_tolerances<T: Component + Serialize + FromReflect>(
+        &mut self,
+        tolerances: FloatTolerances,
+    );
+    /// Track a component with separate serializers for hashing and for diagnostics. `hash`
+    /// produces the bytes fed into the CRC, so it can be a fast raw encoding, and isn't affected
+    /// by the plugin's float options. `readable` is only called when building a
+    /// [`DesyncReport`] or one of the dumps derived from it.
+    fn track_desync_with_serializers<T: Component>(
+        &mut self,
+        hash: fn(&T) -> Vec<u8>,
+        readable: fn(&T) -> String,
+    );
+    /// Track a component through a function returning its hashed form, for components which don't
+    /// implement `Serialize` or should only hash some of their fields. The string is hashed as is,
+    /// so it isn't affected by the plugin's float options, and is also what reports show
+    fn track_desync_with<T: Component>(&mut self, f: fn(&T) -> String);
+    /// Track only whether a component is on an entity, e.g. a `Stunned` marker, without
+    /// serializing it. A token naming the type is hashed while it's present, so `T` doesn't need
+    /// to implement `Serialize`, and different markers don't hash the same
+    fn track_presence<T: Component>(&mut self);
+    /// Track a component which can also be saved to and loaded from a [`TrackedSnapshot`], see
+    /// [`check_save_load_stable`]
+    fn track_desync_saveable<T: Component + Serialize + DeserializeOwned>(&mut self);
+    /// Track a component which refers to other entities, hashing it through reflection with every
+    /// `Entity`, `Option<Entity>` and collection of entities mapped the same way
+    /// [`sort_from_entity_map`] maps the entities themselves, with the same `from_self`.
+    /// Referenced entities which aren't in the map are handled by the [`MissingEntityPolicy`]
+    fn track_desync_mapped<T: Component + Reflect, Mapper: EnumerateEntities + Resource>(
+        &mut self,
+        from_self: bool,
+    );
+    /// Track a component holding a trait object, e.g. `Box<dyn Shape>`. `f` returns the boxed
+    /// value, which is hashed along with its concrete type through the `ReflectSerialize`
+    /// registered for that type in the [`bevy_ecs::reflect::AppTypeRegistry`]. Values whose type
+    /// isn't registered with `#[reflect(Serialize)]` are skipped with a warning
+    fn track_desync_dyn<T: Component>(&mut self, f: fn(&T) -> &dyn Reflect);
+    /// Track a component which only implements `Reflect`, hashing it through the
+    /// [`bevy_ecs::reflect::AppTypeRegistry`]. `T` is registered, but the types of its fields must
+    /// already be, and every value nested in it must either reflect `Serialize` or be made of
+    /// fields which do. That's checked here rather than when hashing, so nothing is tracked if it
+    /// isn't the case. Fields skipped with `#[reflect(skip_serializing)]` aren't hashed
+    fn track_desync_reflect<T: Component + Reflect + GetTypeRegistration>(
+        &mut self,
+    ) -> Result<(), ReflectTrackError>;
+    /// Track a component whose hashed form depends on the rest of the world. `f` returns what's
+    /// hashed in place of the component. Changes to the context alone aren't seen by
+    /// [`ArchetypeCrcCache`], [`EntityCrcCache`] or [`delta_crc`], which only look at component
+    /// change ticks
+    fn track_desync_ctx<T: Component, S: Serialize + 'static>(&mut self, f: fn(&T, &World) -> S);
+    /// Track a component relative to a resource both peers share, e.g. positions relative to a
+    /// world origin, so peers with different absolute values but the same relative state match.
+    /// `f` returns what's hashed in place of the component. While `R` isn't in the world the
+    /// component is hashed as `null`
+    fn track_desync_relative<T: Component, R: Resource, S: Serialize + 'static>(
+        &mut self,
+        f: fn(&T, &R) -> S,
+    );
+    /// Track a component, passing its serialized form through `transform` before it's hashed.
+    /// A last-mile normalization for cases no other serializer covers, e.g. stripping a volatile
+    /// substring. Every peer must register the same transform
+    fn track_desync_transformed<T: Component + Serialize>(
+        &mut self,
+        transform: fn(String) -> String,
+    );
+    /// Track a transform by its translation, rotation and scale, see [`canonical_trs`]. Matrix
+    /// and quaternion representations can differ bit-wise for the same orientation, so this
+    /// avoids false desyncs a plain `Serialize` would report
+    #[cfg(feature = "transform")]
+    fn track_desync_trs<T: Trs>(&mut self, quantum: f32);
+    /// Track [`bevy_hierarchy::Children`], mapping each child the way
+    /// [`AppDesyncExt::track_desync_mapped`] maps entities. `order` decides whether the same
+    /// children in a different order are a desync
+    #[cfg(feature = "hierarchy")]
+    fn track_desync_children<Mapper: EnumerateEntities + Resource>(
+        &mut self,
+        from_self: bool,
+        order: ChildOrder,
+    );
+    /// Define tracked entities by a query filter, e.g. `(With<Replicated>, Without<Predicted>)`,
+    /// instead of the [`TrackDesync`] marker. Entities matching `F` are tracked whether or not
+    /// they're marked. Only archetypal filters are supported, so `Changed` and `Added` panic
+    fn track_desync_filter<F: QueryFilter + 'static>(&mut self);
+    /// See [`DesyncWorldExt::set_desync_enabled`]
+    fn set_desync_enabled(&mut self, enabled: bool);
+    /// See [`DesyncWorldExt::untrack_desync`]
+    fn untrack_desync<T: Component>(&mut self);
+}
+
+pub trait DesyncWorldExt {
+    /// Pause or resume `update_crc`. While disabled the CRC, history and tick are left as they
+    /// were, and registrations are kept so tracking resumes exactly where it left off
+    fn set_desync_enabled(&mut self, enabled: bool);
+    fn desync_enabled(&self) -> bool;
+    /// Stop hashing a component registered with any of the `track_desync` functions, e.g. a
+    /// debug-only component during a phase where it's noisy. Does nothing if it isn't tracked
+    fn untrack_desync<T: Component>(&mut self);
+}
+
+impl DesyncWorldExt for World {
+    fn set_desync_enabled(&mut self, enabled: bool) {
+        self.resource_mut::<DesyncPluginData>().enabled = enabled;
+    }
+
+    fn desync_enabled(&self) -> bool {
+        self.resource::<DesyncPluginData>().enabled
+    }
+
+    fn untrack_desync<T: Component>(&mut self) {
+        if let Some(id) = self.component_id::<T>() {
+            self.resource_mut::<DesyncPluginData>()
+                .serialize_fn_registry
+                .remove(&id);
+        }
+    }
 }
 
 impl AppDesyncExt for App {
     fn track_desync<T: Component + Serialize>(&mut self) {
+        register_component::<T>(self, None, false);
+    }
+
+    fn track_desync_with_eq<T: Component + Serialize>(&mut self, eq: fn(&T, &T) -> bool) {
+        register_component::<T>(
+            self,
+            Some(Arc::new(move |a, b| unsafe {
+                // SAFETY: caller guarantees both pointers are of type T
+                eq(a.deref::<T>(), b.deref::<T>())
+            })),
+            false,
+        );
+    }
+
+    fn track_desync_authority_only<T: Component + Serialize>(&mut self) {
+        register_component::<T>(self, None, true);
+    }
+
+    fn track_desync_derived<T: DesyncComponents>(&mut self) {
+        T::track_desync_all(self);
+    }
+
+    fn track_desync_in<T: Component + Serialize>(&mut self, group: &str) {
+        register_component::<T>(self, None, false);
         let component_id = self.world.init_component::<T>();
         let mut desync_data = self.world.resource_mut::<DesyncPluginData>();
         desync_data
-            .serialize_fn_registry
-            .insert(component_id, untyped_serialize::<T>);
+            .component_groups
+            .insert(component_id, GroupId::from(group));
+        self.world.init_resource::<GroupCrcs>();
+    }
+
+    fn track_desync_resource<R: Resource + Serialize>(&mut self) {
+        let name = std::any::type_name::<R>();
+        self.world
+            .resource_mut::<DesyncPluginData>()
+            .resource_serialize_fn_registry
+            .insert(
+                name,
+                ResourceFns {
+                    name,
+                    serialize: serialize_resource::<R>,
+                },
+            );
+    }
+
+    fn track_desync_events<E: Event + Serialize>(&mut self) {
+        self.add_event::<E>();
+        let name = std::any::type_name::<E>();
+        self.world
+            .resource_mut::<DesyncPluginData>()
+            .event_serialize_fn_registry
+            .insert(
+                name,
+                ResourceFns {
+                    name,
+                    serialize: serialize_events::<E>,
+                },
+            );
+    }
+
+    fn track_desync_key<K: Component + DesyncKey>(&mut self) {
+        let mut desync_data = self.world.resource_mut::<DesyncPluginData>();
+        desync_data.entity_sort = Arc::new(Box::new(sort_by_desync_key::<K>));
+        desync_data.entity_sort_name =
+            format!("sort_by_desync_key::<{}>", std::any::type_name::<K>());
+        desync_data.identity = Arc::new(ComponentKeyIdentity::<K>::default());
+    }
+
+    fn track_desync_tolerances<T: Component + Serialize + FromReflect>(
+        &mut self,
+        tolerances: FloatTolerances,
+    ) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("tolerances", move |ptr, _, options, out| unsafe {
+                // SAFETY: caller guarantees the pointer is of type T
+                serialize_with_tolerances::<T>(ptr, options, &tolerances, out)
+            }),
+        );
+    }
+
+    fn track_desync_with_serializers<T: Component>(
+        &mut self,
+        hash: fn(&T) -> Vec<u8>,
+        readable: fn(&T) -> String,
+    ) {
+        register_fns::<T>(
+            self,
+            ComponentFns {
+                readable: Some(Arc::new(move |ptr| unsafe {
+                    // SAFETY: caller guarantees the pointer is of type T
+                    readable(ptr.deref::<T>())
+                })),
+                ..ComponentFns::new("custom", move |ptr, _, _, out| unsafe {
+                    // SAFETY: caller guarantees the pointer is of type T
+                    out.extend(hash(ptr.deref::<T>()));
+                    Ok(())
+                })
+            },
+        );
+    }
+
+    fn track_desync_with<T: Component>(&mut self, f: fn(&T) -> String) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("with", move |ptr, _, _, out| unsafe {
+                // SAFETY: caller guarantees the pointer is of type T
+                out.extend_from_slice(f(ptr.deref::<T>()).as_bytes());
+                Ok(())
+            }),
+        );
+    }
+
+    fn track_presence<T: Component>(&mut self) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("presence", |_, _, options, out| {
+                encode_value(std::any::type_name::<T>(), options, out)
+            }),
+        );
+    }
+
+    fn track_desync_saveable<T: Component + Serialize + DeserializeOwned>(&mut self) {
+        register_fns::<T>(
+            self,
+            ComponentFns {
+                snapshot: Some(SnapshotFns {
+                    init: |world| world.init_component::<T>(),
+                    save: save_component::<T>,
+                    load: load_component::<T>,
+                }),
+                ..ComponentFns::serde::<T>("saveable")
+            },
+        );
+    }
+
+    fn track_desync_mapped<T: Component + Reflect, Mapper: EnumerateEntities + Resource>(
+        &mut self,
+        from_self: bool,
+    ) {
+        let cache = LookupCache::default();
+        register_fns::<T>(
+            self,
+            ComponentFns::new("mapped", move |ptr, world, options, out| {
+                let lookup = EntityLookup::new::<Mapper>(world, from_self, &cache);
+                let value = MappedValue {
+                    // SAFETY: caller guarantees the pointer is of type T
+                    value: unsafe { ptr.deref::<T>() },
+                    lookup: &lookup,
+                };
+                encode_value(&value, options, out)
+            }),
+        );
+    }
+
+    fn track_desync_dyn<T: Component>(&mut self, f: fn(&T) -> &dyn Reflect) {
+        let unregistered = Arc::new(Unregistered::default());
+        register_fns::<T>(
+            self,
+            ComponentFns::new("dyn", move |ptr, world, options, out| {
+                // SAFETY: caller guarantees the pointer is of type T
+                let value = f(unsafe { ptr.deref::<T>() });
+                serialize_dyn(value, world, options, &unregistered, out)
+            }),
+        );
+    }
+
+    fn track_desync_reflect<T: Component + Reflect + GetTypeRegistration>(
+        &mut self,
+    ) -> Result<(), ReflectTrackError> {
+        self.register_type::<T>();
+        {
+            let registry = self.world.resource::<AppTypeRegistry>().read();
+            check_reflect_serializable(&registry, TypeId::of::<T>())?;
+        }
+        register_fns::<T>(
+            self,
+            ComponentFns::new("reflect", |ptr, world, options, out| {
+                let registry = world.resource::<AppTypeRegistry>().read();
+                // SAFETY: caller guarantees the pointer is of type T
+                let value = unsafe { ptr.deref::<T>() };
+                encode_value(&TypedReflectSerializer::new(value, &registry), options, out)
+            }),
+        );
+        Ok(())
+    }
+
+    fn track_desync_ctx<T: Component, S: Serialize + 'static>(&mut self, f: fn(&T, &World) -> S) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("ctx", move |ptr, world, options, out| {
+                // SAFETY: caller guarantees the pointer is of type T
+                let value = f(unsafe { ptr.deref::<T>() }, world);
+                encode_value(&value, options, out)
+            }),
+        );
+    }
+
+    fn track_desync_relative<T: Component, R: Resource, S: Serialize + 'static>(
+        &mut self,
+        f: fn(&T, &R) -> S,
+    ) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("relative", move |ptr, world, options, out| {
+                // SAFETY: caller guarantees the pointer is of type T
+                let component = unsafe { ptr.deref::<T>() };
+                let value = world.get_resource::<R>().map(|r| f(component, r));
+                encode_value(&value, options, out)
+            }),
+        );
+    }
+
+    fn track_desync_transformed<T: Component + Serialize>(
+        &mut self,
+        transform: fn(String) -> String,
+    ) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("transformed", move |ptr, _, options, out| {
+                // SAFETY: caller guarantees the pointer is of type T
+                let serialized =
+                    serialize_value(unsafe { ptr.deref::<T>() }, &options.float_options)?;
+                out.extend_from_slice(transform(serialized).as_bytes());
+                Ok(())
+            }),
+        );
+    }
+
+    #[cfg(feature = "transform")]
+    fn track_desync_trs<T: Trs>(&mut self, quantum: f32) {
+        register_fns::<T>(
+            self,
+            ComponentFns::new("trs", move |ptr, _, options, out| {
+                // SAFETY: caller guarantees the pointer is of type T
+                let value = canonical_trs(unsafe { ptr.deref::<T>() }, quantum);
+                encode_value(&value, options, out)
+            }),
+        );
+    }
+
+    #[cfg(feature = "hierarchy")]
+    fn track_desync_children<Mapper: EnumerateEntities + Resource>(
+        &mut self,
+        from_self: bool,
+        order: ChildOrder,
+    ) {
+        let cache = LookupCache::default();
+        register_fns::<bevy_hierarchy::Children>(
+            self,
+            ComponentFns::new(
+                match order {
+                    ChildOrder::Ordered => "children",
+                    ChildOrder::Sorted => "sorted-children",
+                },
+                move |ptr, world, options, out| {
+                    let lookup = EntityLookup::new::<Mapper>(world, from_self, &cache);
+                    let value = MappedChildren {
+                        // SAFETY: caller guarantees the pointer is of type Children
+                        children: unsafe { ptr.deref::<bevy_hierarchy::Children>() },
+                        lookup: &lookup,
+                        order,
+                    };
+                    encode_value(&value, options, out)
+                },
+            ),
+        );
+    }
+
+    fn track_desync_filter<F: QueryFilter + 'static>(&mut self) {
+        assert!(
+            F::IS_ARCHETYPAL,
+            "tracked entities can only be filtered by archetype"
+        );
+        let state = F::init_state(&mut self.world);
+        self.world
+            .resource_mut::<DesyncPluginData>()
+            .tracked_archetypes = Arc::new(move |archetype, _| {
+            F::matches_component_set(&state, &|id| archetype.contains(id))
+        });
+    }
+
+    fn set_desync_enabled(&mut self, enabled: bool) {
+        self.world.set_desync_enabled(enabled);
     }
+
+    fn untrack_desync<T: Component>(&mut self) {
+        self.world.untrack_desync::<T>();
+    }
+}
+
+fn register_component<T: Component + Serialize>(
+    app: &mut App,
+    eq: Option<EqFn>,
+    authority_only: bool,
+) {
+    register_fns::<T>(
+        app,
+        ComponentFns {
+            eq,
+            authority_only,
+            ..ComponentFns::serde::<T>("serde")
+        },
+    );
+}
+
+fn register_fns<T: Component>(app: &mut App, fns: ComponentFns) {
+    let component_id = app.world.init_component::<T>();
+    let mut desync_data = app.world.resource_mut::<DesyncPluginData>();
+    desync_data.serialize_fn_registry.insert(component_id, fns);
 }
 
 /// SAFETY: Ptr must be of type T
-unsafe fn untyped_serialize<T: Component + Serialize>(ptr: Ptr) -> String {
+unsafe fn untyped_serialize<T: Component + Serialize>(
+    ptr: Ptr,
+    options: &HashOptions,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
     let se = ptr.deref::<T>();
-    serde_json::to_string(se).unwrap()
+    encode_value(se, options, out)
 }
 
-fn get_tracked_components(entity: Entity, world: &World) -> Vec<ComponentId> {
-    let entity = world.get_entity(entity).unwrap();
-    let archetype = entity.archetype();
-    let desync_data = world.resource::<DesyncPluginData>();
-    let mut components = archetype
-        .components()
-        .filter(|c| desync_data.serialize_fn_registry.contains_key(c))
-        .collect::<Vec<_>>();
-    // TODO: component IDs aren't stable, think of a better way to sort
-    components.sort();
+/// SAFETY: Ptr must be of type T
+unsafe fn save_component<T: Component + Serialize>(ptr: Ptr) -> Result<String, String> {
+    serde_json::to_string(ptr.deref::<T>()).map_err(|e| e.to_string())
+}
+
+fn load_component<T: Component + DeserializeOwned>(
+    world: &mut World,
+    entity: Entity,
+    json: &str,
+) -> Result<(), String> {
+    let component = serde_json::from_str::<T>(json).map_err(|e| e.to_string())?;
+    world.entity_mut(entity).insert(component);
+    Ok(())
+}
+
+/// SAFETY: Ptr must be of type T
+unsafe fn serialize_with_tolerances<T: Component + Serialize + FromReflect>(
+    ptr: Ptr,
+    options: &HashOptions,
+    tolerances: &FloatTolerances,
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    // FromReflect makes an owned copy to quantize, without requiring Clone
+    let mut value =
+        T::from_reflect(ptr.deref::<T>()).ok_or("FromReflect couldn't copy the value")?;
+    apply_tolerances(&mut value, tolerances);
+    encode_value(&value, options, out)
+}
+
+fn serialize_resource<R: Resource + Serialize>(
+    world: &World,
+    options: &FloatOptions,
+) -> Option<Result<String, String>> {
+    world
+        .get_resource::<R>()
+        .map(|resource| serialize_value(resource, options))
+}
+
+fn serialize_events<E: Event + Serialize>(
+    world: &World,
+    options: &FloatOptions,
+) -> Option<Result<String, String>> {
+    world.get_resource::<Events<E>>().map(|events| {
+        events
+            .iter_current_update_events()
+            .map(|event| serialize_value(event, options))
+            .collect()
+    })
+}
+
+/// Serialize a tracked value as [canonical JSON](to_canonical_json), which is what gets hashed
+fn serialize_value<T: Serialize + ?Sized>(
+    value: &T,
+    options: &FloatOptions,
+) -> Result<String, String> {
+    if options.is_passthrough() {
+        to_canonical_json(value)
+    } else {
+        to_canonical_json(&WithFloatOptions::new(value, options))
+    }
+    .map_err(|e| e.to_string())
+}
+
+pub(crate) fn get_tracked_components(entity: Entity, world: &World) -> Vec<ComponentId> {
+    get_tracked_components_in(entity, world, CrcScope::Shared)
+}
+
+/// Tracked components of an entity, sorted by type name, so the order doesn't depend on the order
+/// components were registered in. Empty if the entity no longer exists
+pub(crate) fn get_tracked_components_in(
+    entity: Entity,
+    world: &World,
+    scope: CrcScope,
+) -> Vec<ComponentId> {
+    let mut components = Vec::new();
+    tracked_components_into(entity, world, scope, &mut components);
     components
 }
 
+/// [`get_tracked_components_in`], replacing the contents of `components` to reuse its allocation
+pub(crate) fn tracked_components_into(
+    entity: Entity,
+    world: &World,
+    scope: CrcScope,
+    components: &mut Vec<ComponentId>,
+) {
+    components.clear();
+    let Some(entity) = world.get_entity(entity) else {
+        return;
+    };
+    let desync_data = world.resource::<DesyncPluginData>();
+    components.extend(
+        entity
+            .archetype()
+            .components()
+            .filter(|c| desync_data.is_tracked(c, scope)),
+    );
+    components.sort_by_key(|c| world.components().get_name(*c));
+}
+
 /// This method of calculating the CRC sorts archetypes, entities and components by their IDs. This
 /// may lead to false positives if the two worlds have different orders for those IDs.
+///
+/// Entities are sorted by their full bits, generation and index, so entities which reused an index
+/// always sort the same way within a world. This is only stable across machines if they spawned
+/// and despawned the same entities in the same order: an index is handed out again after a
+/// despawn, with its generation bumped, so a client which spawned one extra bullet, or despawned
+/// entities in another order, gives the same entity a different index or generation.
 pub fn sort_entities_ids(world: &World) -> Vec<Entity> {
-    let track_desync_component_id = world.component_id::<TrackDesync>().unwrap();
+    sort_entities_by(world, EntityOrder::Bits)
+}
+
+/// How [`sort_entities_by`] orders entities within an archetype
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntityOrder {
+    /// By [`Entity::to_bits`], which compares generation first, then index
+    #[default]
+    Bits,
+    /// Index only. Ties keep the archetype's storage order
+    Index,
+    /// Index first, then generation
+    IndexGeneration,
+}
+
+impl EntityOrder {
+    pub fn sort(&self, entities: &mut [Entity]) {
+        match self {
+            EntityOrder::Bits => entities.sort_unstable_by_key(|e| e.to_bits()),
+            EntityOrder::Index => entities.sort_by_key(|e| e.index()),
+            EntityOrder::IndexGeneration => entities.sort_by_key(|e| (e.index(), e.generation())),
+        }
+    }
+}
+
+/// Like [`sort_entities_ids`], with a configurable ordering for entities within an archetype
+///
+/// Usage:
+/// ```rust,ignore
+/// app.add_plugins(
+/// DesyncPlugin {
+///     entity_sort: Arc::new(Box::new(|w| sort_entities_by(w, EntityOrder::IndexGeneration))),
+///     ..Default::default()
+/// })
+/// ```
+pub fn sort_entities_by(world: &World, order: EntityOrder) -> Vec<Entity> {
     let mut archetypes = world
         .archetypes()
         .iter()
-        // archetypes with the track_desync component
-        .filter(|a| a.contains(track_desync_component_id))
+        // tracked archetypes, by default those with the TrackDesync component
+        .filter(|a| is_tracked_entity(a, world))
         .collect::<Vec<_>>();
     // TODO: archetype IDs aren't stable, think of a better way to sort
-    archetypes.sort_by(|a, b| a.id().cmp(&b.id()));
+    archetypes.sort_by_key(|a| a.id());
 
     archetypes
         .iter()
         .flat_map(|archetype| {
-            let mut entities = archetype.entities().iter().collect::<Vec<_>>();
+            let mut entities = archetype
+                .entities()
+                .iter()
+                .map(|e| e.id())
+                .collect::<Vec<_>>();
             // TODO: entity IDs aren't stable, think of a better way to sort
-            entities.sort_by(|a, b| a.id().cmp(&b.id()));
-            entities.iter().map(|e| e.id()).collect::<Vec<_>>()
+            order.sort(&mut entities);
+            entities
         })
         .collect()
 }
 
+/// Entities marked with [`TrackDesync`], in whatever order is cheapest to iterate. Used in place of
+/// `entity_sort` when the combine strategy is commutative
+pub fn unordered_tracked_entities(world: &World) -> Vec<Entity> {
+    world
+        .archetypes()
+        .iter()
+        .filter(|a| is_tracked_entity(a, world))
+        .flat_map(|archetype| archetype.entities().iter().map(|e| e.id()))
+        .collect()
+}
+
 pub trait EnumerateEntities: EntityMapper {
-    /// Get all the entities mapped by this mapper
+    /// Get all the entities mapped by this mapper, as `(from, to)` pairs where `to` is what
+    /// `map_entity(from)` returns
     fn iter_entities(&self) -> Vec<(Entity, Entity)>;
 }
 
@@ -158,68 +1539,275 @@ pub trait EnumerateEntities: EntityMapper {
 /// })
 /// ```
 ///
-/// EntityMapper requires clone because this function requires read only access to the world
-pub fn sort_from_entity_map<Mapper: EnumerateEntities + Resource + Clone>(
+/// The mapper is only read through [`EnumerateEntities::iter_entities`], so it's never cloned or
+/// mutated, and `map_entity` is never called.
+///
+/// Entities which only one side has are handled by the plugin's [`MissingEntityPolicy`]. With
+/// `from_self`, a tracked entity which isn't in the map is dropped under `Skip` and hashed as usual
+/// otherwise. Without, an entity which is mapped to something not in the world (or not tracked)
+/// is dropped under `Skip` and hashed as a placeholder under `Sentinel`.
+///
+/// If the mapper isn't in the world no entities are returned, and the CRC fails with
+/// [`DesyncError::MissingMapper`] rather than panicking.
+pub fn sort_from_entity_map<Mapper: EnumerateEntities + Resource>(
     world: &World,
     from_self: bool,
 ) -> Vec<Entity> {
-    let mut entity_map = world.get_resource::<Mapper>().unwrap().clone();
-    let entities = world
-        .iter_entities()
-        .filter(|entity| entity.contains::<TrackDesync>())
-        .map(|e| e.id());
+    let desync_data = world.resource::<DesyncPluginData>();
+    let (policy, errors) = (desync_data.missing_entities, &desync_data.errors);
+    let Some(entity_map) = world.get_resource::<Mapper>() else {
+        errors.record(DesyncError::MissingMapper(std::any::type_name::<Mapper>()));
+        return Vec::new();
+    };
+    let mut mapped = entity_map.iter_entities();
     if from_self {
-        let mut entities = entities.collect::<Vec<_>>();
-        entities.sort_by(|a, b| a.cmp(&b));
+        let sources = mapped.iter().map(|(from, _)| *from).collect::<HashSet<_>>();
+        let mut entities = world
+            .iter_entities()
+            .filter(|entity| is_tracked_entity(entity.archetype(), world))
+            .map(|e| e.id())
+            .filter(|e| sources.contains(e) || policy.missing(*e, errors).is_some())
+            .collect::<Vec<_>>();
+        entities.sort();
         entities
     } else {
+        // reported as itself, but hashed as the placeholder, which is never in the world, so an
+        // entity which is there but untracked is hashed as the sentinel too
+        let missing = |to| policy.missing(to, errors).map(|_| Entity::PLACEHOLDER);
         // invert entity mapper
-        let mut entities = entity_map.iter_entities();
-        entities.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-        entities
+        mapped.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        mapped
             .iter()
-            .map(|e| entity_map.map_entity(e.0))
+            .filter_map(|&(_, to)| {
+                // the map can hold stale entries for entities despawned this tick
+                match world.get_entity(to) {
+                    Some(e) if is_tracked_entity(e.archetype(), world) => Some(to),
+                    Some(_) => missing(to),
+                    None => {
+                        debug!("{to:?} in the entity map doesn't exist");
+                        missing(to)
+                    }
+                }
+            })
             .collect()
     }
 }
 
-pub fn calculate_crc(world: &World) -> u16 {
-    let mut crc_input = String::new();
-    let desync_data = world.resource::<DesyncPluginData>();
-    let entities = (desync_data.entity_sort)(world);
-    for entity in entities.iter() {
-        let components = get_tracked_components(*entity, world);
-        // check has tracking
-        if !world.get_entity(*entity).unwrap().contains::<TrackDesync>() {
-            continue;
-        }
-        for c in components.iter() {
-            let ptr = world.get_by_id(*entity, *c).unwrap();
-            crc_input.push_str(&desync_data.serialize(ptr, c));
-        }
-    }
+/// Calculate the CRC of the tracked state. Fails if a tracked value doesn't serialize, if the
+/// entity sort's mapper is missing, or if a tracked entity is missing under
+/// [`MissingEntityPolicy::Error`]. Each entity and component hashed, with the bytes that went into
+/// the hash, is logged at the trace level, e.g. with `RUST_LOG=bevy_mod_desync=trace`
+pub fn calculate_crc(world: &World) -> Result<u16, DesyncError> {
+    calculate_crc_filtered(world, |_, _| true)
+}
 
-    let crc_algo = crc::Crc::<u16>::new(&crc::CRC_16_IBM_SDLC);
-    crc_algo.checksum(crc_input.as_bytes())
+/// Calculate the CRC of only the tracked entities `predicate` accepts, e.g. those near one zone of
+/// a large world, to narrow down where a desync is. The predicate is applied to the entities
+/// `entity_sort` returns, before anything is hashed. Tracked resources and events are still hashed
+pub fn calculate_crc_filtered(
+    world: &World,
+    predicate: impl Fn(Entity, &World) -> bool,
+) -> Result<u16, DesyncError> {
+    checked(world, |world| {
+        let combine = world.resource::<DesyncPluginData>().combine;
+        calculate_status(world, CrcScope::Shared, combine, &predicate)
+            .0
+            .crc
+    })
 }
 
-pub fn update_crc(world: &mut World) {
-    let crc = calculate_crc(world);
-    let mut crc_res = world.resource_mut::<Crc>();
-    *crc_res = Crc(crc);
+/// The bytes [`calculate_crc`] checksums, in the order they're hashed: each tracked entity's
+/// components in `entity_sort` order, then the tracked resources and events. For checking the CRC
+/// against another implementation, or feeding the tracked state into another hash or signature.
+/// With [`CombineStrategy::Concatenate`], `crc_algorithm.checksum(&input)` is the [`FullCrc`].
+/// Other strategies checksum each entity's bytes separately and fold those together
+pub fn collect_crc_input(world: &World) -> Result<Vec<u8>, DesyncError> {
+    checked(world, |world| {
+        let mut input = Vec::new();
+        collect_input(world, &mut input);
+        input
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use bevy_ecs::entity::EntityHashMap;
+/// Calculate the CRC, panicking where [`calculate_crc`] would return an error
+pub fn calculate_crc_unchecked(world: &World) -> u16 {
+    calculate_crc(world).unwrap_or_else(|error| panic!("{error}"))
+}
 
-    use super::*;
+/// Run `f`, returning the first error it recorded instead of its result
+fn checked<T>(world: &World, f: impl FnOnce(&World) -> T) -> Result<T, DesyncError> {
+    let errors = world.resource::<DesyncPluginData>().errors.clone();
+    // left over from something which didn't check
+    errors.take();
+    let result = f(world);
+    match errors.take() {
+        Some(error) => Err(error),
+        None => Ok(result),
+    }
+}
 
-    #[derive(Component, Serialize)]
-    struct Foo(u64);
+/// Calculate the CRC including authority-only components, see [`LocalCrc`]. Fails like
+/// [`calculate_crc`]
+pub fn calculate_local_crc(world: &World) -> Result<u16, DesyncError> {
+    checked(world, |world| {
+        let combine = world.resource::<DesyncPluginData>().combine;
+        calculate_status(world, CrcScope::Local, combine, &|_, _| true)
+            .0
+            .crc
+    })
+}
 
-    fn build_app() -> App {
-        let mut app = App::new();
+/// Calculate the CRC of only the tracked resources, without touching any entities. A cheap
+/// checksum of global state, at the full width of [`DesyncPlugin::crc_algorithm`]. Fails if a
+/// tracked resource doesn't serialize
+pub fn calculate_resource_crc(world: &World) -> Result<u64, DesyncError> {
+    checked(world, |world| {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let crc_input = desync_data.serialize_resources(world).collect::<String>();
+        desync_data.crc_algorithm.checksum(crc_input.as_bytes())
+    })
+}
+
+/// Insert [`InitCrc`]. Added by the plugin to run once, before the first `update_crc` which
+/// calculates anything
+pub fn record_init_crc(world: &mut World) {
+    match calculate_crc(world) {
+        Ok(crc) => world.insert_resource(InitCrc(crc)),
+        Err(error) => error!("couldn't calculate the initial CRC: {error}"),
+    }
+}
+
+/// Whether the plugin is enabled and has something registered to hash, so the CRC can be
+/// calculated
+fn tracks_anything(desync_data: Res<DesyncPluginData>) -> bool {
+    desync_data.enabled && desync_data.tracks_anything()
+}
+
+/// Update [`Crc`] and everything derived from it for this tick. Added by the plugin. If the CRC
+/// can't be calculated, the error is logged and the previous CRC is left in place. Until a
+/// component, resource or event type is registered nothing happens, not even the tick advancing,
+/// so the plugin can be added before the plugins registering what they track, or inserting the
+/// resources a custom `entity_sort` reads
+pub fn update_crc(world: &mut World) {
+    let desync_data = world.resource::<DesyncPluginData>();
+    if !desync_data.enabled || !desync_data.tracks_anything() {
+        // operations made while disabled shouldn't be hashed into the first tick after enabling
+        if let Some(mut log) = world.get_resource_mut::<ComponentOpLog>() {
+            log.clear();
+        }
+        return;
+    }
+    if world.contains_resource::<ComponentLimit>() {
+        check_component_limit(world);
+    }
+    let errors = world.resource::<DesyncPluginData>().errors.clone();
+    errors.take();
+    let (mut status, full_crc) = if world.contains_resource::<CrcAudit>() {
+        let report = calculate_crc_scoped(world, CrcScope::Shared, true);
+        let audit = CrcAudit::from_report(&report, world);
+        world.insert_resource(audit);
+        (DesyncStatus::from_report(&report), report.full_crc)
+    } else if world.contains_resource::<EntityCrcCache>() {
+        calculate_status_with::<EntityCrcCache>(world)
+    } else if world.contains_resource::<ArchetypeCrcCache>() {
+        calculate_status_with::<ArchetypeCrcCache>(world)
+    } else {
+        let combine = world.resource::<DesyncPluginData>().combine;
+        calculate_status(world, CrcScope::Shared, combine, &|_, _| true)
+    };
+    if let Some(error) = errors.take() {
+        // the previous CRC stays in place, and nothing is compared against this tick
+        error!(
+            "CRC for tick {} failed: {error}",
+            world.resource::<DesyncTick>().0
+        );
+        if let Some(mut log) = world.get_resource_mut::<ComponentOpLog>() {
+            log.clear();
+        }
+        world.resource_mut::<DesyncTick>().0 += 1;
+        return;
+    }
+    let crc = status.crc;
+    let mut crc_res = world.resource_mut::<Crc>();
+    *crc_res = Crc(crc);
+    world.resource_mut::<FullCrc>().0 = full_crc;
+
+    let tick = match world.resource::<DesyncPluginData>().tick_source.clone() {
+        Some(tick_source) => {
+            let tick = tick_source(world);
+            world.resource_mut::<DesyncTick>().0 = tick;
+            tick
+        }
+        None => world.resource::<DesyncTick>().0,
+    };
+    status.tick = tick;
+    world.insert_resource(status);
+    if let Some(StrictCrc(expected)) = world.get_resource::<StrictCrc>().copied() {
+        assert_eq!(
+            crc, expected,
+            "CRC {crc} at tick {tick} doesn't match the expected {expected}"
+        );
+    }
+    world.resource_mut::<CrcHistory>().push(tick, crc);
+    world.resource_mut::<RollingCrc>().push(crc);
+    if let Some(on_crc) = &world.resource::<DesyncPluginData>().on_crc {
+        on_crc(tick, crc);
+    }
+    if world.contains_resource::<RollbackChecksums>() {
+        record_rollback_checksum(world, tick, full_crc);
+    }
+    if world.contains_resource::<Recording>() {
+        record_tick(world, tick, crc);
+    }
+    if world.contains_resource::<CrcMessages>() {
+        queue_crc_message(world, tick, crc);
+    }
+    if world.contains_resource::<ReplayValidator>() {
+        validate_replay(world, tick, crc);
+    }
+    if let Some(comparison) = world.get_resource::<GoldenComparison>() {
+        comparison.send(tick, crc);
+    }
+    if world.contains_resource::<GroupCrcs>() {
+        update_group_crcs(world);
+    }
+    if world.contains_resource::<LocalCrc>() {
+        match calculate_local_crc(world) {
+            Ok(local_crc) => world.resource_mut::<LocalCrc>().0 = local_crc,
+            Err(error) => error!("couldn't calculate the local CRC: {error}"),
+        }
+    }
+    if world.contains_resource::<EntityHashes>() {
+        update_entity_hashes(world);
+    }
+    if world.contains_resource::<ComponentChurn>() {
+        update_churn(world);
+    }
+
+    if let Some(mut log) = world.get_resource_mut::<ComponentOpLog>() {
+        log.clear();
+    }
+    world.resource_mut::<DesyncTick>().0 += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{FixedMain, FixedPostUpdate, FixedUpdate, RunFixedMainLoop, Startup};
+    use bevy_ecs::{
+        entity::EntityHashMap,
+        query::{With, Without},
+        schedule::{IntoSystemSetConfigs, ScheduleLabel},
+        system::{Commands, Query},
+    };
+    use std::panic::AssertUnwindSafe;
+
+    use super::*;
+
+    #[derive(Component, Serialize)]
+    struct Foo(u64);
+
+    fn build_app() -> App {
+        let mut app = App::new();
         app.add_plugins(DesyncPlugin::default())
             .track_desync::<Foo>();
         app
@@ -253,6 +1841,777 @@ mod tests {
         assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
     }
 
+    #[derive(Component, Serialize)]
+    struct Position(i32, i32);
+
+    #[derive(Resource)]
+    struct Origin(i32, i32);
+
+    #[test]
+    fn relative_to_resource() {
+        let crc = |origin: (i32, i32), position: (i32, i32)| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync_relative::<Position, Origin, _>(|p, o| (p.0 - o.0, p.1 - o.1));
+            app.world.insert_resource(Origin(origin.0, origin.1));
+            app.world
+                .spawn((Position(position.0, position.1), TrackDesync));
+            calculate_crc(&app.world).unwrap()
+        };
+        // same position relative to each peer's origin
+        assert_eq!(crc((0, 0), (3, 4)), crc((100, -50), (103, -46)));
+        assert_ne!(crc((0, 0), (3, 4)), crc((100, -50), (3, 4)));
+    }
+
+    #[test]
+    fn marker_presence_hashed() {
+        #[derive(Component)]
+        struct Stunned;
+
+        #[derive(Component)]
+        struct Frozen;
+
+        let crc = |insert: fn(&mut bevy_ecs::world::EntityWorldMut)| {
+            let mut app = build_app();
+            app.track_presence::<Stunned>();
+            app.track_presence::<Frozen>();
+            insert(&mut app.world.spawn((Foo(1), TrackDesync)));
+            calculate_crc(&app.world).unwrap()
+        };
+        let stunned = crc(|e| {
+            e.insert(Stunned);
+        });
+        assert_ne!(stunned, crc(|_| {}));
+        assert_ne!(
+            stunned,
+            crc(|e| {
+                e.insert(Frozen);
+            })
+        );
+    }
+
+    #[test]
+    fn strict_crc_panics_on_divergence() {
+        let mut app = build_app();
+        let entity = app.world.spawn((Foo(1), TrackDesync)).id();
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+        app.world.insert_resource(StrictCrc(crc));
+        app.update();
+
+        app.world.entity_mut(entity).insert(Foo(2));
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| app.update()));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains(&format!("at tick 2 doesn't match the expected {crc}")));
+    }
+
+    #[test]
+    fn filtered_by_zone() {
+        let spawn = |far: u64| {
+            let mut app = build_app();
+            app.world.spawn((Foo(1), TrackDesync));
+            app.world.spawn((Foo(far), TrackDesync));
+            app
+        };
+        let (app_1, app_2) = (spawn(100), spawn(200));
+        // only the far zone desynced
+        let near = |entity: Entity, world: &World| world.get::<Foo>(entity).unwrap().0 < 50;
+        assert_eq!(
+            calculate_crc_filtered(&app_1.world, near),
+            calculate_crc_filtered(&app_2.world, near)
+        );
+        let far = |entity, world: &World| !near(entity, world);
+        assert_ne!(
+            calculate_crc_filtered(&app_1.world, far),
+            calculate_crc_filtered(&app_2.world, far)
+        );
+        assert_eq!(
+            calculate_crc_filtered(&app_1.world, |_, _| true),
+            calculate_crc(&app_1.world)
+        );
+    }
+
+    #[test]
+    fn init_crc_differs() {
+        let build_app = |value| {
+            let mut app = build_app();
+            app.add_systems(Startup, move |mut commands: Commands| {
+                commands.spawn((Foo(value), TrackDesync));
+            });
+            app
+        };
+        let mut app_1 = build_app(0);
+        let mut app_2 = build_app(1);
+        let mut app_3 = build_app(0);
+        for app in [&mut app_1, &mut app_2, &mut app_3] {
+            app.update();
+            // later changes don't touch it
+            app.world.spawn((Foo(2), TrackDesync));
+            app.update();
+        }
+        assert_ne!(
+            app_1.world.resource::<InitCrc>(),
+            app_2.world.resource::<InitCrc>()
+        );
+        assert_eq!(
+            app_1.world.resource::<InitCrc>(),
+            app_3.world.resource::<InitCrc>()
+        );
+        assert_eq!(
+            app_1.world.resource::<InitCrc>().0,
+            app_1.world.resource::<CrcHistory>().get(0).unwrap()
+        );
+    }
+
+    /// Stands in for a third party type which doesn't implement `Serialize`
+    struct Velocity {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Component)]
+    struct Body {
+        velocity: Velocity,
+    }
+
+    #[test]
+    fn custom_serialize_function() {
+        let crc = |x: f32| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync_with::<Body>(|body| {
+                    format!("{:.2},{:.2}", body.velocity.x, body.velocity.y)
+                });
+            let velocity = Velocity { x, y: 1.0 };
+            app.world.spawn((Body { velocity }, TrackDesync));
+            app.update();
+            let report = calculate_crc_detailed(&app.world).unwrap();
+            assert_eq!(report.crc, app.world.resource::<Crc>().0);
+            report
+        };
+        let report = crc(0.5);
+        assert_eq!(report.entities[0].components[0].serialized, "0.50,1.00");
+        // rounded before hashing
+        assert_eq!(report.crc, crc(0.501).crc);
+        assert_ne!(report.crc, crc(0.6).crc);
+    }
+
+    #[derive(Component, Serialize)]
+    struct LogLine(String);
+
+    #[test]
+    fn transform_strips_volatile_prefix() {
+        let crc = |line: &str| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync_transformed::<LogLine>(|json| match json.split_once("] ") {
+                    Some((_, rest)) => format!("\"{rest}"),
+                    None => json,
+                });
+            app.world.spawn((LogLine(line.to_string()), TrackDesync));
+            calculate_crc(&app.world).unwrap()
+        };
+        assert_eq!(crc("[12:00:01] spawned"), crc("[12:00:02] spawned"));
+        assert_ne!(crc("[12:00:01] spawned"), crc("[12:00:01] despawned"));
+    }
+
+    #[test]
+    fn toggle_enabled() {
+        let mut app = build_app();
+        let entity = app.world.spawn((Foo(0), TrackDesync)).id();
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+
+        app.set_desync_enabled(false);
+        assert!(!app.world.desync_enabled());
+        app.world.get_mut::<Foo>(entity).unwrap().0 = 1;
+        app.update();
+        assert_eq!(app.world.resource::<Crc>().0, crc);
+        assert_eq!(app.world.resource::<DesyncTick>().0, 1);
+
+        app.world.set_desync_enabled(true);
+        app.update();
+        assert_ne!(app.world.resource::<Crc>().0, crc);
+        assert_eq!(app.world.resource::<DesyncTick>().0, 2);
+    }
+
+    #[derive(Component, Serialize)]
+    struct DebugInfo(u32);
+
+    #[test]
+    fn untrack_at_runtime() {
+        let mut app = build_app();
+        app.track_desync::<DebugInfo>();
+        let entity = app.world.spawn((Foo(0), DebugInfo(0), TrackDesync)).id();
+        let mut untracked = build_app();
+        untracked.world.spawn((Foo(0), DebugInfo(0), TrackDesync));
+        let untracked_crc = calculate_crc(&untracked.world).unwrap();
+        app.update();
+        assert_ne!(app.world.resource::<Crc>().0, untracked_crc);
+
+        app.untrack_desync::<DebugInfo>();
+        app.world.get_mut::<DebugInfo>(entity).unwrap().0 = 1;
+        app.update();
+        assert_eq!(app.world.resource::<Crc>().0, untracked_crc);
+        // still tracked
+        app.world.get_mut::<Foo>(entity).unwrap().0 = 1;
+        assert_ne!(calculate_crc(&app.world).unwrap(), untracked_crc);
+        // untracking twice, or something never tracked, does nothing
+        app.world.untrack_desync::<DebugInfo>();
+        app.world.untrack_desync::<Predicted>();
+    }
+
+    #[derive(Component)]
+    struct Replicated;
+
+    #[derive(Component)]
+    struct Predicted;
+
+    #[test]
+    fn compound_filter() {
+        let build_app = || {
+            let mut app = build_app();
+            app.track_desync_filter::<(With<Replicated>, Without<Predicted>)>();
+            app
+        };
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1.world.spawn((Foo(0), Replicated));
+        app_2.world.spawn((Foo(0), Replicated));
+        // predicted, unreplicated and marked entities are all outside the filter
+        let predicted = app_1.world.spawn((Foo(1), Replicated, Predicted)).id();
+        app_1.world.spawn(Foo(2));
+        app_1.world.spawn((Foo(3), TrackDesync));
+
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+        assert_eq!(app_1.world.resource::<DesyncStatus>().entity_count, 1);
+
+        app_1.world.entity_mut(predicted).remove::<Predicted>();
+        app_1.update();
+        app_2.update();
+        assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+
+    #[derive(Resource)]
+    struct Frame(u64);
+
+    #[test]
+    fn tick_source_tags_history() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            tick_source: Some(Arc::new(|world| world.resource::<Frame>().0)),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.insert_resource(Frame(100));
+        let entity = app.world.spawn((Foo(0), TrackDesync)).id();
+        let mut crcs = Vec::new();
+        for frame in [100, 103, 106] {
+            app.world.resource_mut::<Frame>().0 = frame;
+            app.world.get_mut::<Foo>(entity).unwrap().0 = frame;
+            app.update();
+            crcs.push(app.world.resource::<Crc>().0);
+        }
+        let history = app.world.resource::<CrcHistory>();
+        assert_eq!(history.get(103), Some(crcs[1]));
+        assert_eq!(history.get(106), Some(crcs[2]));
+        assert_eq!(history.get(1), None);
+        assert_eq!(app.world.resource::<DesyncTick>().0, 107);
+    }
+
+    #[test]
+    fn on_crc_matches_history() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_crc_seen = seen.clone();
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            tick_source: Some(Arc::new(|world| world.resource::<Frame>().0)),
+            on_crc: Some(Arc::new(move |tick, crc| {
+                on_crc_seen.lock().unwrap().push((tick, crc))
+            })),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.insert_resource(Frame(0));
+        let entity = app.world.spawn((Foo(0), TrackDesync)).id();
+        for frame in [10, 20, 30] {
+            app.world.resource_mut::<Frame>().0 = frame;
+            app.world.get_mut::<Foo>(entity).unwrap().0 = frame;
+            app.update();
+        }
+        let history = app.world.resource::<CrcHistory>();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            history.iter().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn fixed_schedule() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            schedule: Some(FixedPostUpdate.intern()),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        // what bevy_time does when a frame takes three fixed timesteps
+        app.add_systems(RunFixedMainLoop, |world: &mut World| {
+            for _ in 0..3 {
+                world.run_schedule(FixedMain);
+            }
+        })
+        .add_systems(FixedUpdate, |mut query: Query<&mut Foo>| {
+            for mut foo in query.iter_mut() {
+                foo.0 += 1;
+            }
+        });
+        app.world.spawn((Foo(0), TrackDesync));
+
+        app.update();
+        app.update();
+        assert_eq!(app.world.resource::<DesyncTick>().0, 6);
+        let history = app.world.resource::<CrcHistory>();
+        let ticks = history.iter().map(|(tick, _)| *tick).collect::<Vec<_>>();
+        assert_eq!(ticks, (0..6).collect::<Vec<_>>());
+
+        // each step stepped Foo by one before it was hashed
+        let mut reference = build_app();
+        let entity = reference.world.spawn((Foo(0), TrackDesync)).id();
+        for &(tick, crc) in history.iter() {
+            reference.world.get_mut::<Foo>(entity).unwrap().0 = tick + 1;
+            assert_eq!(calculate_crc(&reference.world).unwrap(), crc);
+        }
+    }
+
+    #[test]
+    fn rolling_crc_spans_window() {
+        let build_app = || {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                rolling_window: 2,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            app
+        };
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1.world.spawn((Foo(0), TrackDesync));
+        let foo_2 = app_2.world.spawn((Foo(1), TrackDesync)).id();
+        app_1.update();
+        app_2.update();
+
+        // recover from the desync
+        *app_2.world.get_mut::<Foo>(foo_2).unwrap() = Foo(0);
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+        assert_ne!(
+            app_1.world.resource::<RollingCrc>().crc(),
+            app_2.world.resource::<RollingCrc>().crc()
+        );
+
+        app_1.update();
+        app_2.update();
+        assert_eq!(
+            app_1.world.resource::<RollingCrc>().crc(),
+            app_2.world.resource::<RollingCrc>().crc()
+        );
+    }
+
+    #[test]
+    fn commutative_combine_skips_sort() {
+        for combine in [CombineStrategy::Xor, CombineStrategy::Sum] {
+            let mut app_1 = App::new();
+            app_1
+                .add_plugins(DesyncPlugin {
+                    combine,
+                    ..Default::default()
+                })
+                .track_desync::<Foo>();
+            let mut app_2 = App::new();
+            app_2
+                .add_plugins(DesyncPlugin {
+                    combine,
+                    entity_sort: Arc::new(Box::new(|_| panic!("sort should be skipped"))),
+                    ..Default::default()
+                })
+                .track_desync::<Foo>();
+            app_1.world.spawn((Foo(0), TrackDesync));
+            app_1.world.spawn((Foo(1), TrackDesync));
+            app_2.world.spawn((Foo(1), TrackDesync));
+            app_2.world.spawn((Foo(0), TrackDesync));
+
+            app_1.update();
+            app_2.update();
+            assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+        }
+    }
+
+    #[derive(Component, Serialize)]
+    struct Float(f32, f64);
+
+    #[test]
+    fn non_finite_floats() {
+        let build_app = |sentinel_non_finite| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                float_options: FloatOptions {
+                    sentinel_non_finite,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .track_desync::<Float>();
+            app
+        };
+        let crc = |sentinel_non_finite, value: Float| {
+            let mut app = build_app(sentinel_non_finite);
+            app.world.spawn((value, TrackDesync));
+            app.update();
+            app.world.resource::<Crc>().0
+        };
+        let other_nan = f32::from_bits(f32::NAN.to_bits() + 1);
+
+        // canonical JSON tells them apart without sentinels too
+        assert_ne!(
+            crc(false, Float(f32::NAN, f64::INFINITY)),
+            crc(false, Float(f32::INFINITY, f64::NEG_INFINITY))
+        );
+        // the sentinels are strings, unlike the tokens canonical JSON writes
+        assert_ne!(
+            crc(false, Float(f32::NAN, f64::INFINITY)),
+            crc(true, Float(f32::NAN, f64::INFINITY))
+        );
+
+        // nor are they hashed like a string holding the same text
+        #[derive(Component, Serialize)]
+        #[serde(untagged)]
+        enum FloatOrText {
+            Float(f32),
+            Text(&'static str),
+        }
+        let value_crc = |value: FloatOrText| {
+            let mut app = build_app(false);
+            app.track_desync::<FloatOrText>();
+            app.world.spawn((value, TrackDesync));
+            app.update();
+            app.world.resource::<Crc>().0
+        };
+        for (value, text) in [
+            (f32::NAN, "NaN"),
+            (f32::INFINITY, "+Inf"),
+            (f32::NAN, "!nan"),
+        ] {
+            assert_ne!(
+                value_crc(FloatOrText::Float(value)),
+                value_crc(FloatOrText::Text(text))
+            );
+        }
+
+        // different NaN bit patterns match across peers
+        assert_eq!(
+            crc(true, Float(f32::NAN, f64::INFINITY)),
+            crc(true, Float(other_nan, f64::INFINITY))
+        );
+        // NaN and infinities are all distinct
+        assert_ne!(
+            crc(true, Float(f32::NAN, f64::INFINITY)),
+            crc(true, Float(f32::INFINITY, f64::INFINITY))
+        );
+        assert_ne!(
+            crc(true, Float(0.0, f64::INFINITY)),
+            crc(true, Float(0.0, f64::NEG_INFINITY))
+        );
+    }
+
+    #[test]
+    fn tracking_predicate_restricts_entities() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        for app in [&mut app_1, &mut app_2] {
+            app.world
+                .insert_resource(TrackingPredicate::new(|entity, world| {
+                    world.get::<Foo>(entity).unwrap().0 < 10
+                }));
+            app.world.spawn((Foo(0), TrackDesync));
+        }
+        app_1.world.spawn((Foo(10), TrackDesync));
+        let foo_2 = app_2.world.spawn((Foo(11), TrackDesync)).id();
+
+        // entities outside the predicate don't affect the crc
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        // now bring the entity into interest
+        *app_2.world.get_mut::<Foo>(foo_2).unwrap() = Foo(1);
+        app_1.update();
+        app_2.update();
+        assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+
+    #[derive(Resource, Serialize)]
+    struct Score(u32);
+
+    #[test]
+    fn resource_crc() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        for app in [&mut app_1, &mut app_2] {
+            app.track_desync_resource::<Score>();
+            app.world.spawn((Foo(0), TrackDesync));
+        }
+        // missing resources are skipped
+        let untracked = calculate_crc(&app_1.world).unwrap();
+        assert_eq!(
+            calculate_resource_crc(&app_1.world).unwrap(),
+            calculate_resource_crc(&app_2.world).unwrap()
+        );
+        assert_eq!(calculate_crc(&app_2.world).unwrap(), untracked);
+
+        app_1.world.insert_resource(Score(0));
+        app_2.world.insert_resource(Score(1));
+        assert_ne!(
+            calculate_resource_crc(&app_1.world).unwrap(),
+            calculate_resource_crc(&app_2.world).unwrap()
+        );
+        assert_ne!(
+            calculate_crc(&app_1.world).unwrap(),
+            calculate_crc(&app_2.world).unwrap()
+        );
+        assert_ne!(calculate_crc(&app_1.world).unwrap(), untracked);
+    }
+
+    #[test]
+    fn resource_crc_uses_configured_algorithm() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            crc_algorithm: CrcAlgorithm::Crc64Xz,
+            ..Default::default()
+        })
+        .track_desync_resource::<Score>();
+        app.world.insert_resource(Score(1));
+        let input = app
+            .world
+            .resource::<DesyncPluginData>()
+            .serialize_resources(&app.world)
+            .collect::<String>();
+        let crc = calculate_resource_crc(&app.world).unwrap();
+        assert_eq!(crc, CrcAlgorithm::Crc64Xz.checksum(input.as_bytes()));
+        assert!(crc > u16::MAX as u64);
+    }
+
+    #[test]
+    fn entity_order_generation_tie_break() {
+        let entity = |index: u64, generation: u64| Entity::from_bits(generation << 32 | index);
+        let mut a = vec![entity(1, 2), entity(0, 3), entity(1, 1)];
+        let mut b = vec![entity(1, 1), entity(1, 2), entity(0, 3)];
+        EntityOrder::IndexGeneration.sort(&mut a);
+        EntityOrder::IndexGeneration.sort(&mut b);
+        assert_eq!(a, vec![entity(0, 3), entity(1, 1), entity(1, 2)]);
+        assert_eq!(a, b);
+
+        // bits order compares generation first
+        EntityOrder::Bits.sort(&mut a);
+        assert_eq!(a, vec![entity(1, 1), entity(1, 2), entity(0, 3)]);
+    }
+
+    #[test]
+    fn default_sort_stable_with_reused_indices() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        let mut entities = (0..8)
+            .map(|i| app.world.spawn((Foo(i), TrackDesync)).id())
+            .collect::<Vec<_>>();
+        // despawned entities' indices are reused with a new generation
+        for round in 0..3 {
+            for entity in entities.drain(..4) {
+                app.world.despawn(entity);
+            }
+            for i in 0..4 {
+                entities.push(app.world.spawn((Foo(round * 4 + i), TrackDesync)).id());
+            }
+        }
+        assert!(entities.iter().any(|e| e.generation() > 1));
+
+        let sorted = sort_entities_ids(&app.world);
+        let mut expected = entities.clone();
+        expected.sort_by_key(|e| e.to_bits());
+        assert_eq!(sorted, expected);
+        assert_eq!(sort_entities_ids(&app.world), sorted);
+        let crc = calculate_crc(&app.world).unwrap();
+        assert_eq!(calculate_crc(&app.world).unwrap(), crc);
+    }
+
+    #[test]
+    fn crc_input_checksums_to_crc() {
+        for (crc_algorithm, schema_prefix) in
+            [(CrcAlgorithm::Ibm16, false), (CrcAlgorithm::Crc64Xz, true)]
+        {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                crc_algorithm,
+                schema_prefix,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            app.track_desync_resource::<Score>();
+            app.insert_resource(Score(7));
+            app.world.spawn((Foo(1), TrackDesync));
+            app.world.spawn((Foo(2), TrackDesync));
+            app.update();
+
+            let input = collect_crc_input(&app.world).unwrap();
+            assert!(input.ends_with(b"7"));
+            assert_eq!(
+                crc_algorithm.checksum(&input),
+                app.world.resource::<FullCrc>().0
+            );
+            assert_eq!(
+                crc_algorithm.checksum(&input) as u16,
+                calculate_crc(&app.world).unwrap()
+            );
+        }
+    }
+
+    #[derive(Component, Resource, Serialize)]
+    struct Numbered<const N: u32>(u32);
+
+    fn register_numbered<const N: u32>(app: &mut App, entity: Entity) {
+        app.track_desync_in::<Numbered<N>>(["odd", "even"][N as usize % 2]);
+        app.track_desync_resource::<Numbered<N>>();
+        app.insert_resource(Numbered::<N>(N));
+        app.world.entity_mut(entity).insert(Numbered::<N>(N));
+    }
+
+    #[test]
+    fn hash_input_independent_of_registration_order() {
+        let registrations: [fn(&mut App, Entity); 8] = [
+            register_numbered::<0>,
+            register_numbered::<1>,
+            register_numbered::<2>,
+            register_numbered::<3>,
+            register_numbered::<4>,
+            register_numbered::<5>,
+            register_numbered::<6>,
+            register_numbered::<7>,
+        ];
+        let build = |rotation: usize| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin::default());
+            let entity = app.world.spawn(TrackDesync).id();
+            let mut registrations = registrations;
+            registrations.rotate_left(rotation);
+            for register in registrations {
+                register(&mut app, entity);
+            }
+            app.update();
+            let input = collect_crc_input(&app.world).unwrap();
+            (input, app.world.remove_resource::<GroupCrcs>().unwrap())
+        };
+        let (input, group_crcs) = build(0);
+        for rotation in 1..registrations.len() {
+            let (rotated_input, rotated_group_crcs) = build(rotation);
+            assert_eq!(rotated_input, input);
+            assert_eq!(rotated_group_crcs, group_crcs);
+        }
+        // entity components, then resources
+        assert_eq!(input, b"0123456701234567");
+    }
+
+    #[derive(Component, Serialize)]
+    struct AiState(u8);
+
+    #[test]
+    fn authority_only_components() {
+        let mut server = App::new();
+        server
+            .add_plugins(DesyncPlugin {
+                role: DesyncRole::Authority,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+        server.track_desync_authority_only::<AiState>();
+        let mut client = build_app();
+        let ai = server.world.spawn((Foo(0), AiState(0), TrackDesync)).id();
+        client.world.spawn((Foo(0), TrackDesync));
+        server.update();
+        client.update();
+        assert_eq!(
+            server.world.resource::<Crc>(),
+            client.world.resource::<Crc>()
+        );
+        assert!(!client.world.contains_resource::<LocalCrc>());
+
+        let local_crc = server.world.resource::<LocalCrc>().0;
+        *server.world.get_mut::<AiState>(ai).unwrap() = AiState(1);
+        server.update();
+        client.update();
+        assert_eq!(
+            server.world.resource::<Crc>(),
+            client.world.resource::<Crc>()
+        );
+        assert_ne!(server.world.resource::<LocalCrc>().0, local_crc);
+    }
+
+    #[derive(Event, Serialize)]
+    struct Input(u8);
+
+    #[test]
+    fn events_affect_crc() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        for app in [&mut app_1, &mut app_2] {
+            app.track_desync_events::<Input>();
+            app.world.spawn((Foo(0), TrackDesync));
+            app.world.send_event(Input(0));
+        }
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        app_1.world.send_event(Input(1));
+        app_1.world.send_event(Input(2));
+        app_2.world.send_event(Input(2));
+        app_2.world.send_event(Input(1));
+        app_1.update();
+        app_2.update();
+        assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        // the events have been swapped out
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+
+    #[derive(Resource, Serialize)]
+    struct Seed(u64);
+
+    #[test]
+    fn resource_registration_order() {
+        let mut app_1 = build_app();
+        app_1.track_desync_resource::<Score>();
+        app_1.track_desync_resource::<Seed>();
+        let mut app_2 = build_app();
+        app_2.track_desync_resource::<Seed>();
+        app_2.track_desync_resource::<Score>();
+        // registering twice doesn't hash twice
+        app_2.track_desync_resource::<Score>();
+        for app in [&mut app_1, &mut app_2] {
+            app.world.insert_resource(Score(1));
+            app.world.insert_resource(Seed(2));
+        }
+        assert_eq!(
+            calculate_resource_crc(&app_1.world).unwrap(),
+            calculate_resource_crc(&app_2.world).unwrap()
+        );
+    }
+
     #[derive(Clone, Default, Resource)]
     struct EntityMap {
         entity_map: EntityHashMap<Entity>,
@@ -270,6 +2629,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn skipped_until_something_registered() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            // panics until the resource is inserted
+            entity_sort: Arc::new(Box::new(|world| {
+                let mut entities = world
+                    .resource::<EntityMap>()
+                    .entity_map
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>();
+                entities.sort();
+                entities
+            })),
+            ..Default::default()
+        });
+        let entity = app.world.spawn((Foo(1), TrackDesync)).id();
+        app.update();
+        app.update();
+        assert_eq!(*app.world.resource::<Crc>(), Crc(0));
+        assert_eq!(app.world.resource::<DesyncTick>().0, 0);
+        assert!(app.world.resource::<CrcHistory>().is_empty());
+
+        // the rest of setup, e.g. from a later plugin
+        app.track_desync::<Foo>();
+        let mut map = EntityMap::default();
+        map.entity_map.insert(entity, entity);
+        app.insert_resource(map);
+        app.update();
+        let crc = calculate_crc(&app.world).unwrap();
+        assert_ne!(crc, 0);
+        assert_eq!(*app.world.resource::<Crc>(), Crc(crc));
+        assert_eq!(app.world.resource::<CrcHistory>().get(0), Some(crc));
+    }
+
     #[test]
     fn entity_mapping_sync_and_desync() {
         let mut app_1 = build_app();
@@ -313,4 +2708,334 @@ mod tests {
         app_2.update();
         assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
     }
+
+    #[test]
+    fn sort_returns_despawned_entity() {
+        let mut app = build_app();
+        let despawned = app.world.spawn((Foo(1), TrackDesync)).id();
+        app.world.spawn((Foo(0), TrackDesync));
+        app.update();
+        let with_both = app.world.resource::<Crc>().0;
+
+        // as if the entity was despawned between sorting and hashing
+        app.world.despawn(despawned);
+        app.world.resource_mut::<DesyncPluginData>().entity_sort = Arc::new(Box::new(move |w| {
+            let mut entities = sort_entities_ids(w);
+            entities.insert(0, despawned);
+            entities
+        }));
+        assert!(get_tracked_components(despawned, &app.world).is_empty());
+        app.update();
+        assert_ne!(app.world.resource::<Crc>().0, with_both);
+
+        let mut only_live = build_app();
+        only_live.world.spawn((Foo(0), TrackDesync));
+        assert_eq!(
+            app.world.resource::<Crc>().0,
+            calculate_crc(&only_live.world).unwrap()
+        );
+        assert_eq!(
+            calculate_crc_detailed(&app.world).unwrap().entities.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn opt_out_tracks_unmarked_entities() {
+        #[derive(Component)]
+        struct Untracked;
+
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            tracking_mode: TrackingMode::OptOut,
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        let tracked = app.world.spawn(Foo(0)).id();
+        app.world.spawn((Foo(1), IgnoreDesync));
+        app.world.spawn(Untracked);
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        assert_eq!(report.entities.len(), 1);
+        assert_eq!(report.entities[0].entity, tracked);
+        assert_eq!(sort_entities_ids(&app.world), vec![tracked]);
+    }
+
+    #[test]
+    fn custom_sort_decides_tracking() {
+        let mut app = build_app();
+        app.world.spawn((Foo(0), TrackDesync));
+        let unmarked = app.world.spawn(Foo(1)).id();
+        let crc = calculate_crc(&app.world).unwrap();
+        app.world.resource_mut::<DesyncPluginData>().entity_sort = Arc::new(Box::new(move |w| {
+            let mut entities = sort_entities_ids(w);
+            entities.push(unmarked);
+            entities
+        }));
+        assert_eq!(
+            calculate_crc_detailed(&app.world).unwrap().entities.len(),
+            2
+        );
+        assert_ne!(calculate_crc(&app.world).unwrap(), crc);
+    }
+
+    #[test]
+    fn missing_entity_policies() {
+        let build_app = |missing_entities| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                missing_entities,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            app
+        };
+        let crc = |policy, from_self, despawn_b: bool| {
+            let mut app = build_app(policy);
+            let a = app.world.spawn((Foo(0), TrackDesync)).id();
+            let b = app.world.spawn((Foo(1), TrackDesync)).id();
+            let mut entity_map = EntityHashMap::default();
+            entity_map.insert(a, a);
+            entity_map.insert(b, b);
+            if despawn_b {
+                app.world.despawn(b);
+            }
+            app.world.insert_resource(EntityMap { entity_map });
+            app.world.resource_mut::<DesyncPluginData>().entity_sort =
+                Arc::new(Box::new(move |w| {
+                    sort_from_entity_map::<EntityMap>(w, from_self)
+                }));
+            app.update();
+            app.world.resource::<Crc>().0
+        };
+        let only_a = {
+            let mut app = build_app(MissingEntityPolicy::Skip);
+            app.world.spawn((Foo(0), TrackDesync));
+            app.update();
+            app.world.resource::<Crc>().0
+        };
+        let both = crc(MissingEntityPolicy::Skip, true, false);
+        assert_eq!(crc(MissingEntityPolicy::Skip, false, false), both);
+
+        // b is mapped to nothing: skipped, it's as if b was never there
+        assert_eq!(crc(MissingEntityPolicy::Skip, false, true), only_a);
+
+        // the placeholder differs from both a dropped entity and the real one
+        let sentinel = crc(MissingEntityPolicy::Sentinel, false, true);
+        assert_ne!(sentinel, only_a);
+        assert_ne!(sentinel, both);
+
+        // nothing is hashed, so the CRC is never set
+        assert_eq!(
+            crc(MissingEntityPolicy::Error, false, true),
+            Crc::default().0
+        );
+        assert_eq!(crc(MissingEntityPolicy::Error, false, false), both);
+    }
+
+    #[test]
+    fn missing_mapped_entity_reported() {
+        for despawn in [true, false] {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                missing_entities: MissingEntityPolicy::Error,
+                ..Default::default()
+            })
+            .track_desync::<Foo>();
+            let a = app.world.spawn((Foo(0), TrackDesync)).id();
+            let b = app.world.spawn(Foo(1)).id();
+            let mut entity_map = EntityHashMap::default();
+            entity_map.insert(a, a);
+            entity_map.insert(b, b);
+            if despawn {
+                app.world.despawn(b);
+            }
+            app.world.insert_resource(EntityMap { entity_map });
+            app.world.resource_mut::<DesyncPluginData>().entity_sort =
+                Arc::new(Box::new(|w| sort_from_entity_map::<EntityMap>(w, false)));
+            // gone, or there but untracked
+            assert_eq!(
+                calculate_crc(&app.world),
+                Err(DesyncError::MissingEntity(b))
+            );
+        }
+    }
+
+    /// Fails to serialize while set
+    #[derive(Component, Resource)]
+    struct Flaky(bool);
+
+    impl Serialize for Flaky {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0 {
+                true => Err(serde::ser::Error::custom("flaked")),
+                false => serializer.serialize_unit(),
+            }
+        }
+    }
+
+    #[test]
+    fn errors_leave_previous_crc() {
+        let mut app = build_app();
+        app.track_desync::<Flaky>();
+        let entity = app.world.spawn((Foo(1), Flaky(false), TrackDesync)).id();
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+        assert_eq!(calculate_crc(&app.world), Ok(crc));
+
+        app.world.entity_mut(entity).insert((Foo(2), Flaky(true)));
+        let Err(DesyncError::Serialize { type_name, error }) = calculate_crc(&app.world) else {
+            panic!("Flaky didn't fail");
+        };
+        assert!(type_name.ends_with("Flaky"));
+        assert!(error.contains("flaked"));
+        let result =
+            std::panic::catch_unwind(AssertUnwindSafe(|| calculate_crc_unchecked(&app.world)));
+        assert!(result.is_err());
+        app.update();
+        assert_eq!(app.world.resource::<Crc>().0, crc);
+        assert_eq!(app.world.resource::<DesyncTick>().0, 2);
+        assert_eq!(app.world.resource::<CrcHistory>().get(1), None);
+
+        // the mapper sorted by is missing
+        let mut app = build_app();
+        app.world.resource_mut::<DesyncPluginData>().entity_sort =
+            Arc::new(Box::new(|w| sort_from_entity_map::<EntityMap>(w, true)));
+        assert_eq!(
+            calculate_crc(&app.world),
+            Err(DesyncError::MissingMapper(
+                std::any::type_name::<EntityMap>()
+            ))
+        );
+    }
+
+    #[test]
+    fn entry_points_fail_on_errors() {
+        let flaky_app = || {
+            let mut app = build_app();
+            app.track_desync::<Flaky>();
+            let entity = app.world.spawn((Foo(1), Flaky(false), TrackDesync)).id();
+            (app, entity)
+        };
+        let (mut app, entity) = flaky_app();
+        let (peer, _) = flaky_app();
+        let snapshot = DesyncSnapshot::take(&mut app.world);
+        app.world.entity_mut(entity).insert(Flaky(true));
+        let world = &app.world;
+        let strategies = [CombineStrategy::Xor, CombineStrategy::Concatenate];
+        let mapper = EntityMap {
+            entity_map: [(entity, entity)].into_iter().collect(),
+        };
+        assert!(calculate_local_crc(world).is_err());
+        assert!(calculate_crc_detailed(world).is_err());
+        assert!(delta_crc(world, &snapshot).is_err());
+        assert!(calculate_crc_changed_since(world, snapshot.tick()).is_err());
+        assert!(MerkleCrc::calculate(world).is_err());
+        assert!(WeightedCrc::calculate(world).is_err());
+        assert!(DualCrc::calculate(world, strategies).is_err());
+        assert!(diff_worlds(world, &peer.world).is_err());
+        assert!(diff_worlds(&peer.world, world).is_err());
+        assert!(minimal_blame(world, &peer.world, &mapper).is_err());
+        assert!(minimal_blame(&peer.world, world, &mapper).is_err());
+        assert!(export_tracked_records(world).is_err());
+        assert!(tracked_state_to_json(world).is_err());
+        assert!(per_entity_message(world).is_err());
+        assert!(calculate_crc_cached(&mut app.world).is_err());
+
+        let (mut app, _) = flaky_app();
+        app.track_desync_resource::<Flaky>();
+        app.world.insert_resource(Flaky(true));
+        assert!(calculate_resource_crc(&app.world).is_err());
+    }
+
+    #[test]
+    fn despawned_mapped_entity_skipped() {
+        /// Like a mapper whose reverse lookup was already cleaned up, while the forward entries
+        /// are still stale. Not `Clone`, as it doesn't need to be
+        #[derive(Resource)]
+        struct CleanedUpMap {
+            entity_map: EntityHashMap<Entity>,
+            despawned: Entity,
+        }
+
+        impl EntityMapper for CleanedUpMap {
+            fn map_entity(&mut self, entity: Entity) -> Entity {
+                let mapped = self.entity_map[&entity];
+                assert_ne!(mapped, self.despawned);
+                mapped
+            }
+        }
+
+        impl EnumerateEntities for CleanedUpMap {
+            fn iter_entities(&self) -> Vec<(Entity, Entity)> {
+                self.entity_map.iter().map(|(a, b)| (*a, *b)).collect()
+            }
+        }
+
+        let mut app = build_app();
+        let a = app.world.spawn((Foo(0), TrackDesync)).id();
+        let b = app.world.spawn((Foo(1), TrackDesync)).id();
+        let entity_map = EntityHashMap::from_iter([(a, a), (b, b)]);
+        app.world.insert_resource(CleanedUpMap {
+            entity_map,
+            despawned: b,
+        });
+        app.world.resource_mut::<DesyncPluginData>().entity_sort =
+            Arc::new(Box::new(|w| sort_from_entity_map::<CleanedUpMap>(w, false)));
+        app.world.despawn(b);
+        app.update();
+
+        let mut only_a = build_app();
+        only_a.world.spawn((Foo(0), TrackDesync));
+        assert_eq!(
+            app.world.resource::<Crc>().0,
+            calculate_crc(&only_a.world).unwrap()
+        );
+    }
+
+    #[test]
+    fn registration_order_independent() {
+        #[derive(Component, Serialize)]
+        struct Bar(u64);
+
+        let mut app_1 = App::new();
+        app_1
+            .add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        app_1.track_desync::<Bar>();
+        let mut app_2 = App::new();
+        app_2
+            .add_plugins(DesyncPlugin::default())
+            .track_desync::<Bar>();
+        app_2.track_desync::<Foo>();
+        assert!(app_1.world.component_id::<Foo>() < app_1.world.component_id::<Bar>());
+        assert!(app_2.world.component_id::<Bar>() < app_2.world.component_id::<Foo>());
+        for app in [&mut app_1, &mut app_2] {
+            app.world.spawn((Foo(1), Bar(2), TrackDesync));
+            app.update();
+        }
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+
+    #[test]
+    fn ordered_by_system_set() {
+        fn apply_network(mut query: Query<&mut Foo>) {
+            for mut foo in query.iter_mut() {
+                foo.0 = 5;
+            }
+        }
+
+        let mut app = build_app();
+        app.add_systems(First, apply_network)
+            .configure_sets(First, DesyncSet::CalculateCrc.after(apply_network));
+        app.world.spawn((Foo(0), TrackDesync));
+        app.update();
+
+        let mut expected = build_app();
+        expected.world.spawn((Foo(5), TrackDesync));
+        expected.update();
+        assert_eq!(
+            app.world.resource::<Crc>(),
+            expected.world.resource::<Crc>()
+        );
+    }
 }
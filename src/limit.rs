@@ -0,0 +1,133 @@
+use bevy_ecs::{
+    archetype::ArchetypeId, entity::Entity, event::Event, system::Resource, world::World,
+};
+use bevy_utils::tracing::warn;
+use std::collections::HashSet;
+
+use crate::{CrcScope, DesyncPluginData};
+
+/// Sent by `update_crc` the first time tracked entities with more than
+/// [`crate::DesyncPlugin::component_limit`] tracked components are seen. Tracking that many
+/// components on one entity is usually a mistake, and is expensive to hash every tick.
+#[derive(Clone, Debug, PartialEq, Event)]
+pub struct ComponentLimitExceeded {
+    /// Every entity with this set of components
+    pub entities: Vec<Entity>,
+    /// Type names of the tracked components
+    pub components: Vec<String>,
+}
+
+/// Configured limit, and the archetypes which have already been reported
+#[derive(Debug, Resource)]
+pub(crate) struct ComponentLimit {
+    limit: usize,
+    reported: HashSet<ArchetypeId>,
+}
+
+impl ComponentLimit {
+    pub(crate) fn new(limit: usize) -> Self {
+        ComponentLimit {
+            limit,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+/// Warn about entities over the limit. Entities in the same archetype share a component set, so
+/// this only needs to look at archetypes
+pub(crate) fn check_component_limit(world: &mut World) {
+    let desync_data = world.resource::<DesyncPluginData>();
+    let limit = world.resource::<ComponentLimit>();
+    let mut exceeded = Vec::new();
+    for archetype in world
+        .archetypes()
+        .iter()
+        .filter(|a| desync_data.tracks_archetype(a, world) && !a.is_empty())
+        .filter(|a| !limit.reported.contains(&a.id()))
+    {
+        let mut components = archetype
+            .components()
+            .filter(|c| desync_data.is_tracked(c, CrcScope::Local))
+            .collect::<Vec<_>>();
+        components.sort();
+        if components.len() <= limit.limit {
+            continue;
+        }
+        let components = components
+            .iter()
+            .map(|c| world.components().get_info(*c).unwrap().name().to_string())
+            .collect::<Vec<_>>();
+        warn!(
+            "{} tracked entities have {} tracked components, more than the limit of {}: {:?}",
+            archetype.len(),
+            components.len(),
+            limit.limit,
+            components
+        );
+        let entities = archetype.entities().iter().map(|e| e.id()).collect();
+        exceeded.push((
+            archetype.id(),
+            ComponentLimitExceeded {
+                entities,
+                components,
+            },
+        ));
+    }
+    for (archetype, event) in exceeded {
+        world
+            .resource_mut::<ComponentLimit>()
+            .reported
+            .insert(archetype);
+        world.send_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{component::Component, event::Events};
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct A;
+
+    #[derive(Component, Serialize)]
+    struct B;
+
+    #[derive(Component, Serialize)]
+    struct C;
+
+    fn exceeded(app: &App) -> Vec<ComponentLimitExceeded> {
+        let events = app.world.resource::<Events<ComponentLimitExceeded>>();
+        events.get_reader().read(events).cloned().collect()
+    }
+
+    #[test]
+    fn warns_over_limit() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            component_limit: Some(2),
+            ..Default::default()
+        })
+        .track_desync::<A>();
+        app.track_desync::<B>();
+        app.track_desync::<C>();
+        app.world.spawn((A, B, TrackDesync));
+        let entity = app.world.spawn((A, B, C, TrackDesync)).id();
+
+        app.update();
+        let events = exceeded(&app);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entities, vec![entity]);
+        assert_eq!(events[0].components.len(), 3);
+        assert!(events[0].components[2].ends_with("C"));
+
+        // only reported once
+        app.update();
+        app.update();
+        assert!(exceeded(&app).is_empty());
+    }
+}
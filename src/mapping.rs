@@ -0,0 +1,460 @@
+use bevy_ecs::{
+    change_detection::DetectChanges,
+    component::Tick,
+    entity::Entity,
+    system::Resource,
+    world::{World, WorldId},
+};
+use bevy_reflect::{Enum, Reflect, ReflectRef};
+use bevy_utils::{HashMap, HashSet};
+use serde::ser::{Error, Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    DesyncPluginData, EnumerateEntities, ErrorSlot, MissingEntityPolicy, MISSING_ENTITY_SENTINEL,
+};
+
+/// Maps the entities referenced by a component onto the shared key space of an entity map, the
+/// same way [`crate::sort_from_entity_map`] lines up the entities themselves: with `from_self`
+/// this world holds the source side of the map, otherwise the destination side.
+pub(crate) struct EntityLookup {
+    canonical: Arc<HashMap<Entity, Entity>>,
+    policy: MissingEntityPolicy,
+    errors: ErrorSlot,
+}
+
+/// The map of an [`EntityLookup`], kept by each registration using one so it's only rebuilt when
+/// the `Mapper` resource changes, not for every component serialized
+#[derive(Default)]
+pub(crate) struct LookupCache(Mutex<Option<CachedLookup>>);
+
+struct CachedLookup {
+    world: WorldId,
+    /// When the mapper last changed. Changes made later are always at a newer tick, as long as
+    /// this is older than the tick the map was built at
+    changed: Tick,
+    canonical: Arc<HashMap<Entity, Entity>>,
+}
+
+impl LookupCache {
+    /// The map of canonical entities, reused if the mapper hasn't changed since it was built
+    fn canonical<Mapper: EnumerateEntities + Resource>(
+        &self,
+        world: &World,
+        from_self: bool,
+    ) -> Arc<HashMap<Entity, Entity>> {
+        let Some(mapper) = world.get_resource_ref::<Mapper>() else {
+            return Arc::default();
+        };
+        let mut cached = self.0.lock().unwrap();
+        if let Some(cached) = cached
+            .as_ref()
+            .filter(|c| c.world == world.id() && c.changed == mapper.last_changed())
+        {
+            return cached.canonical.clone();
+        }
+        let canonical = Arc::new(
+            mapper
+                .iter_entities()
+                .into_iter()
+                .map(|(from, to)| match from_self {
+                    true => (from, from),
+                    false => (to, from),
+                })
+                .collect::<HashMap<_, _>>(),
+        );
+        // if it changed this tick it can change again without its tick moving on
+        *cached = (mapper.last_changed() != world.read_change_tick()).then(|| CachedLookup {
+            world: world.id(),
+            changed: mapper.last_changed(),
+            canonical: canonical.clone(),
+        });
+        canonical
+    }
+}
+
+/// How a referenced entity is hashed
+enum Mapped {
+    Entity(Entity),
+    /// Not in the map, and kept as a placeholder by the policy
+    Missing,
+    /// Not in the map, and dropped by the policy
+    Skipped,
+}
+
+impl EntityLookup {
+    /// Without a `Mapper` resource every referenced entity is treated as unmapped
+    pub(crate) fn new<Mapper: EnumerateEntities + Resource>(
+        world: &World,
+        from_self: bool,
+        cache: &LookupCache,
+    ) -> Self {
+        let canonical = cache.canonical::<Mapper>(world, from_self);
+        let desync_data = world.resource::<DesyncPluginData>();
+        EntityLookup {
+            canonical,
+            policy: desync_data.missing_entities,
+            errors: desync_data.errors.clone(),
+        }
+    }
+
+    fn map(&self, entity: Entity) -> Mapped {
+        match self.canonical.get(&entity) {
+            Some(canonical) => Mapped::Entity(*canonical),
+            None => match self.policy.missing(entity, &self.errors) {
+                Some(_) => Mapped::Missing,
+                None => Mapped::Skipped,
+            },
+        }
+    }
+
+    /// Serialize a collection of entities, each mapped through the lookup. Entities the policy
+    /// drops are left out, and with `sorted` the rest are sorted by what they map to
+    pub(crate) fn serialize_entities<S: Serializer>(
+        &self,
+        entities: impl Iterator<Item = Entity>,
+        sorted: bool,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entities = entities
+            .filter_map(|e| match self.map(e) {
+                Mapped::Entity(e) => Some(Some(e)),
+                Mapped::Missing => Some(None),
+                Mapped::Skipped => None,
+            })
+            .collect::<Vec<_>>();
+        if sorted {
+            entities.sort();
+        }
+        let mut seq = serializer.serialize_seq(Some(entities.len()))?;
+        for entity in entities {
+            match entity {
+                Some(entity) => seq.serialize_element(&entity.to_bits())?,
+                None => seq.serialize_element(MISSING_ENTITY_SENTINEL)?,
+            }
+        }
+        seq.end()
+    }
+
+    /// Whether `value` is an entity dropped by the policy, so should be left out of its collection
+    fn skipped(&self, value: &dyn Reflect) -> bool {
+        value
+            .downcast_ref::<Entity>()
+            .is_some_and(|e| matches!(self.map(*e), Mapped::Skipped))
+    }
+}
+
+/// Serializes a reflected value with every contained entity mapped through an [`EntityLookup`].
+/// Entities in `Vec`s and other lists keep their order, entities in `HashSet`s are sorted, and
+/// unmapped entities are dropped from collections or turn `Some` into `None` under
+/// [`MissingEntityPolicy::Skip`]. A bare `Entity` field can't be dropped, so it's hashed as the
+/// missing placeholder under either policy.
+pub(crate) struct MappedValue<'a> {
+    pub(crate) value: &'a dyn Reflect,
+    pub(crate) lookup: &'a EntityLookup,
+}
+
+impl MappedValue<'_> {
+    fn child<'a>(&'a self, value: &'a dyn Reflect) -> MappedValue<'a> {
+        MappedValue {
+            value,
+            lookup: self.lookup,
+        }
+    }
+
+    fn serialize_entity<S: Serializer>(
+        &self,
+        entity: Entity,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self.lookup.map(entity) {
+            Mapped::Entity(entity) => serializer.serialize_u64(entity.to_bits()),
+            Mapped::Missing | Mapped::Skipped => serializer.serialize_str(MISSING_ENTITY_SENTINEL),
+        }
+    }
+
+    fn serialize_fields<'a, S: Serializer>(
+        &self,
+        fields: impl Iterator<Item = (Option<&'a str>, &'a dyn Reflect)>,
+        len: usize,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut fields = fields.peekable();
+        if fields.peek().is_some_and(|(name, _)| name.is_some()) {
+            let mut map = serializer.serialize_map(Some(len))?;
+            for (name, value) in fields {
+                map.serialize_entry(name.unwrap(), &self.child(value))?;
+            }
+            map.end()
+        } else {
+            let mut seq = serializer.serialize_seq(Some(len))?;
+            for (_, value) in fields {
+                seq.serialize_element(&self.child(value))?;
+            }
+            seq.end()
+        }
+    }
+
+    fn serialize_enum<S: Serializer>(
+        &self,
+        value: &dyn Enum,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let variant = value.variant_name();
+        // `Some(entity)` of an entity the policy drops is hashed as `None`
+        if value.is_variant(bevy_reflect::VariantType::Tuple)
+            && variant == "Some"
+            && value.field_len() == 1
+            && self.lookup.skipped(value.field_at(0).unwrap())
+        {
+            return serializer.serialize_str("None");
+        }
+        if value.field_len() == 0 {
+            return serializer.serialize_str(variant);
+        }
+        let mut map = serializer.serialize_map(Some(1))?;
+        let fields = (0..value.field_len())
+            .map(|i| (value.name_at(i), value.field_at(i).unwrap()))
+            .collect::<Vec<_>>();
+        map.serialize_entry(
+            variant,
+            &EnumFields {
+                value: self,
+                fields,
+            },
+        )?;
+        map.end()
+    }
+}
+
+/// Fields of an enum variant, serialized like a struct or tuple
+struct EnumFields<'a> {
+    value: &'a MappedValue<'a>,
+    fields: Vec<(Option<&'a str>, &'a dyn Reflect)>,
+}
+
+impl Serialize for EnumFields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value
+            .serialize_fields(self.fields.iter().copied(), self.fields.len(), serializer)
+    }
+}
+
+impl Serialize for MappedValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(entity) = self.value.downcast_ref::<Entity>() {
+            return self.serialize_entity(*entity, serializer);
+        }
+        if let Some(set) = self.value.downcast_ref::<HashSet<Entity>>() {
+            // a set's iteration order isn't part of its value
+            return self
+                .lookup
+                .serialize_entities(set.iter().copied(), true, serializer);
+        }
+        match self.value.reflect_ref() {
+            ReflectRef::Struct(value) => self.serialize_fields(
+                (0..value.field_len()).map(|i| (value.name_at(i), value.field_at(i).unwrap())),
+                value.field_len(),
+                serializer,
+            ),
+            ReflectRef::TupleStruct(value) => self.serialize_fields(
+                value.iter_fields().map(|field| (None, field)),
+                value.field_len(),
+                serializer,
+            ),
+            ReflectRef::Tuple(value) => self.serialize_fields(
+                value.iter_fields().map(|field| (None, field)),
+                value.field_len(),
+                serializer,
+            ),
+            ReflectRef::List(value) => {
+                let elements = value
+                    .iter()
+                    .filter(|element| !self.lookup.skipped(*element))
+                    .collect::<Vec<_>>();
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(&self.child(element))?;
+                }
+                seq.end()
+            }
+            ReflectRef::Array(value) => {
+                let mut seq = serializer.serialize_seq(Some(value.len()))?;
+                for element in value.iter() {
+                    seq.serialize_element(&self.child(element))?;
+                }
+                seq.end()
+            }
+            ReflectRef::Map(value) => {
+                let mut map = serializer.serialize_map(Some(value.len()))?;
+                for (key, value) in value.iter() {
+                    map.serialize_entry(&self.child(key), &self.child(value))?;
+                }
+                map.end()
+            }
+            ReflectRef::Enum(value) => self.serialize_enum(value, serializer),
+            ReflectRef::Value(value) => match value.serializable() {
+                Some(serializable) => serializable.borrow().serialize(serializer),
+                None => Err(S::Error::custom(format!(
+                    "{} can't be serialized",
+                    value.reflect_type_path()
+                ))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{component::Component, entity::EntityMapper};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        calculate_crc, sort_from_entity_map, AppDesyncExt, Crc, DesyncPlugin, TrackDesync,
+    };
+
+    #[derive(Component, Reflect)]
+    struct Targets(Vec<Entity>);
+
+    #[derive(Component, Reflect)]
+    struct Leader(Option<Entity>);
+
+    #[derive(Clone, Default, Resource)]
+    struct EntityMap(HashMap<Entity, Entity>);
+
+    impl EntityMapper for EntityMap {
+        fn map_entity(&mut self, entity: Entity) -> Entity {
+            self.0[&entity]
+        }
+    }
+
+    impl EnumerateEntities for EntityMap {
+        fn iter_entities(&self) -> Vec<(Entity, Entity)> {
+            self.0.iter().map(|(a, b)| (*a, *b)).collect()
+        }
+    }
+
+    /// Spawn three entities targeting each other, in the given order, returning them by name
+    fn spawn_units(world: &mut World, order: [usize; 3]) -> [Entity; 3] {
+        let mut units = [Entity::PLACEHOLDER; 3];
+        for i in order {
+            units[i] = world.spawn(TrackDesync).id();
+        }
+        for i in 0..3 {
+            let targets = Targets(vec![units[(i + 1) % 3], units[(i + 2) % 3]]);
+            world
+                .entity_mut(units[i])
+                .insert((targets, Leader(Some(units[0]))));
+        }
+        units
+    }
+
+    fn build_app(from_self: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            entity_sort: Arc::new(Box::new(move |w| {
+                sort_from_entity_map::<EntityMap>(w, from_self)
+            })),
+            ..Default::default()
+        })
+        .track_desync_mapped::<Targets, EntityMap>(from_self);
+        app.track_desync_mapped::<Leader, EntityMap>(from_self);
+        app
+    }
+
+    #[test]
+    fn vec_of_entities_matches_after_remapping() {
+        let mut app_1 = build_app(true);
+        let mut app_2 = build_app(false);
+        // the second world spawns in a different order, so its entity ids differ
+        let units_1 = spawn_units(&mut app_1.world, [0, 1, 2]);
+        let units_2 = spawn_units(&mut app_2.world, [2, 0, 1]);
+        assert_ne!(units_1, units_2);
+        let map = EntityMap(units_1.into_iter().zip(units_2).collect());
+        app_1.world.insert_resource(map.clone());
+        app_2.world.insert_resource(map);
+
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        // references differ once one side reorders its targets
+        app_1
+            .world
+            .get_mut::<Targets>(units_1[0])
+            .unwrap()
+            .0
+            .reverse();
+        app_1.update();
+        app_2.update();
+        assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+
+    #[test]
+    fn lookup_follows_mapper_changes() {
+        let mut app_1 = build_app(true);
+        let mut app_2 = build_app(false);
+        let units_1 = spawn_units(&mut app_1.world, [0, 1, 2]);
+        let units_2 = spawn_units(&mut app_2.world, [2, 0, 1]);
+        let map = EntityMap(units_1.into_iter().zip(units_2).collect());
+        app_1.world.insert_resource(map.clone());
+        app_2.world.insert_resource(map.clone());
+        app_1.update();
+        app_2.update();
+        let crc = app_1.world.resource::<Crc>().0;
+        assert_eq!(app_2.world.resource::<Crc>().0, crc);
+
+        // changed and changed back within one tick, then on a later tick
+        let mut swapped = map.clone();
+        swapped.0.insert(units_1[0], units_2[1]);
+        swapped.0.insert(units_1[1], units_2[0]);
+        for (map, changed) in [(swapped.clone(), true), (map, false)] {
+            *app_2.world.resource_mut::<EntityMap>() = map;
+            assert_eq!(calculate_crc(&app_2.world).unwrap() != crc, changed);
+        }
+        *app_2.world.resource_mut::<EntityMap>() = swapped;
+        app_2.update();
+        assert_ne!(app_2.world.resource::<Crc>().0, crc);
+    }
+
+    #[test]
+    fn unmapped_entities_follow_policy() {
+        let crc = |policy, target: Option<usize>| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                missing_entities: policy,
+                ..Default::default()
+            })
+            .track_desync_mapped::<Targets, EntityMap>(true);
+            app.track_desync_mapped::<Leader, EntityMap>(true);
+            let mapped = app.world.spawn_empty().id();
+            let unmapped = app.world.spawn_empty().id();
+            let entity = |i| [mapped, unmapped][i];
+            app.world.spawn((
+                Targets(target.into_iter().map(entity).collect()),
+                Leader(target.map(entity)),
+                TrackDesync,
+            ));
+            app.world
+                .insert_resource(EntityMap(HashMap::from_iter([(mapped, mapped)])));
+            app.update();
+            app.world.resource::<Crc>().0
+        };
+        // dropped entirely
+        assert_eq!(
+            crc(MissingEntityPolicy::Skip, Some(1)),
+            crc(MissingEntityPolicy::Skip, None)
+        );
+        // kept as a placeholder
+        assert_ne!(
+            crc(MissingEntityPolicy::Sentinel, Some(1)),
+            crc(MissingEntityPolicy::Sentinel, None)
+        );
+        assert_ne!(
+            crc(MissingEntityPolicy::Sentinel, Some(1)),
+            crc(MissingEntityPolicy::Sentinel, Some(0))
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use bevy_ecs::{entity::Entity, world::World};
+
+use crate::{
+    checked, is_hashed, report::EntityHasher, CombineStrategy, CrcAlgorithm, CrcScope, DesyncError,
+    DesyncPluginData, MISSING_ENTITY_SENTINEL,
+};
+
+/// A binary hash tree over the tracked entities, so two peers can walk down from the root to
+/// find which entities diverged in `O(log n)` exchanges instead of comparing every entity.
+///
+/// The leaves are the hashed bytes of each entity in `entity_sort` order, followed by the tracked
+/// resources and events, and each node combines its two children the same way the plugin's
+/// [`CombineStrategy`] combines entities, hashed with the plugin's [`CrcAlgorithm`]. The root is
+/// therefore equal to the flat [`crate::FullCrc`], and its low 16 bits to [`crate::Crc`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleCrc {
+    /// Leaves first, the last level only holding the root
+    levels: Vec<Vec<MerkleNode>>,
+    /// The entity behind each leaf, `None` for missing entities and the resources and events
+    leaves: Vec<Option<Entity>>,
+    combine: CombineStrategy,
+    algorithm: CrcAlgorithm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MerkleNode {
+    crc: u64,
+    /// Number of bytes hashed under this node, needed to combine concatenated CRCs
+    len: u64,
+}
+
+impl MerkleCrc {
+    /// Build the tree of the world. Fails like [`crate::calculate_crc`]
+    pub fn calculate(world: &World) -> Result<Self, DesyncError> {
+        checked(world, Self::calculate_unchecked)
+    }
+
+    fn calculate_unchecked(world: &World) -> Self {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let algorithm = desync_data.crc_algorithm;
+        let mut leaves = Vec::new();
+        let mut nodes = Vec::new();
+        let mut push = |entity, bytes: &[u8]| {
+            leaves.push(entity);
+            nodes.push(MerkleNode {
+                crc: algorithm.checksum(bytes),
+                len: bytes.len() as u64,
+            });
+        };
+        let mut hasher = EntityHasher::default();
+        // always sorted, even when the strategy is commutative, so the tree has the same shape on
+        // every peer
+        for entity in (desync_data.entity_sort)(world) {
+            if world.get_entity(entity).is_none() {
+                if desync_data
+                    .missing_entities
+                    .missing(entity, &desync_data.errors)
+                    .is_some()
+                {
+                    push(None, MISSING_ENTITY_SENTINEL.as_bytes());
+                }
+                continue;
+            }
+            if !is_hashed(entity, world) {
+                continue;
+            }
+            hasher.hash(world, entity, CrcScope::Shared);
+            push(Some(entity), &hasher.bytes);
+        }
+        let global_input = desync_data.serialize_global_input(world);
+        if !global_input.is_empty() {
+            push(None, &global_input);
+        }
+
+        let combine = desync_data.combine;
+        let mut levels = vec![nodes];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let parents = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => combine_nodes(combine, algorithm, *left, *right),
+                    // an odd node out is promoted unchanged
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(parents);
+        }
+        MerkleCrc {
+            levels,
+            leaves,
+            combine,
+            algorithm,
+        }
+    }
+
+    /// Hash of the whole tree, equal to the flat [`crate::FullCrc`] of the same world
+    pub fn root(&self) -> u64 {
+        match self.levels.last().unwrap().first() {
+            Some(node) => node.crc,
+            // nothing hashed at all
+            None => match self.combine {
+                CombineStrategy::Concatenate => self.algorithm.checksum(&[]),
+                CombineStrategy::Xor | CombineStrategy::Sum => 0,
+            },
+        }
+    }
+
+    /// Number of levels below the root
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Hash of the `index`th node `depth` levels below the root. The children of node `i` are
+    /// nodes `2 * i` and `2 * i + 1` one level down, and the leaves are at [`MerkleCrc::depth`]
+    pub fn node(&self, depth: usize, index: usize) -> Option<u64> {
+        let level = self.levels.get(self.depth().checked_sub(depth)?)?;
+        level.get(index).map(|node| node.crc)
+    }
+
+    /// The entity hashed into the `index`th leaf. `None` for leaves which don't belong to an
+    /// entity, i.e. missing entities and the resources and events
+    pub fn leaf_entity(&self, index: usize) -> Option<Entity> {
+        self.leaves.get(index).copied().flatten()
+    }
+
+    /// Indices of the leaves which differ from `remote`, found by only descending into nodes whose
+    /// hashes differ. Trees with a different number of leaves can't be walked, so every leaf is
+    /// returned for them
+    pub fn diverging_leaves(&self, remote: &MerkleCrc) -> Vec<usize> {
+        if self.leaves.len() != remote.leaves.len() {
+            return (0..self.leaves.len().max(remote.leaves.len())).collect();
+        }
+        let mut diverging = Vec::new();
+        let mut stack = vec![(0, 0)];
+        while let Some((depth, index)) = stack.pop() {
+            if self.node(depth, index) == remote.node(depth, index) {
+                continue;
+            }
+            if depth == self.depth() {
+                diverging.push(index);
+                continue;
+            }
+            // a promoted node has no right sibling, node() returns None for both trees
+            stack.push((depth + 1, 2 * index + 1));
+            stack.push((depth + 1, 2 * index));
+        }
+        diverging
+    }
+}
+
+fn combine_nodes(
+    combine: CombineStrategy,
+    algorithm: CrcAlgorithm,
+    left: MerkleNode,
+    right: MerkleNode,
+) -> MerkleNode {
+    let crc = match combine {
+        CombineStrategy::Concatenate => {
+            // every supported algorithm is reflected with init == xorout, so crc(a ++ b) is crc(a)
+            // run through len(b) zero bytes, xored with crc(b)
+            shift_zero_bytes(algorithm, left.crc, right.len) ^ right.crc
+        }
+        CombineStrategy::Xor | CombineStrategy::Sum => {
+            algorithm.truncate(combine.fold(left.crc, right.crc))
+        }
+    };
+    MerkleNode {
+        crc,
+        len: left.len + right.len,
+    }
+}
+
+/// The CRC register `crc` after feeding it `count` zero bytes, in `O(log count)`. Feeding a byte is
+/// linear over GF(2), so it's a bit matrix as wide as the algorithm which can be squared. Columns
+/// past the width stay zero
+fn shift_zero_bytes(algorithm: CrcAlgorithm, crc: u64, mut count: u64) -> u64 {
+    let poly = algorithm.reflected_poly();
+    let mut op = [0u64; 64];
+    for (bit, column) in op.iter_mut().enumerate().take(algorithm.width() as usize) {
+        let mut register = 1u64 << bit;
+        for _ in 0..8 {
+            register = match register & 1 {
+                1 => (register >> 1) ^ poly,
+                _ => register >> 1,
+            };
+        }
+        *column = register;
+    }
+    let mut crc = crc;
+    while count > 0 {
+        if count & 1 == 1 {
+            crc = apply(&op, crc);
+        }
+        op = op.map(|column| apply(&op, column));
+        count >>= 1;
+    }
+    crc
+}
+
+fn apply(op: &[u64; 64], value: u64) -> u64 {
+    (0..64)
+        .filter(|bit| value & (1 << bit) != 0)
+        .fold(0, |acc, bit| acc ^ op[bit])
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u32);
+
+    fn build_app(combine: CombineStrategy) -> (App, Vec<Entity>) {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            combine,
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        let entities = (0..11)
+            .map(|i| app.world.spawn((Foo(i), TrackDesync)).id())
+            .collect();
+        (app, entities)
+    }
+
+    #[test]
+    fn root_matches_flat_crc() {
+        for algorithm in [
+            CrcAlgorithm::Ibm16,
+            CrcAlgorithm::Crc32IsoHdlc,
+            CrcAlgorithm::Crc64Xz,
+        ] {
+            for combine in [
+                CombineStrategy::Concatenate,
+                CombineStrategy::Xor,
+                CombineStrategy::Sum,
+            ] {
+                let (mut app, _) = build_app(combine);
+                app.world.resource_mut::<DesyncPluginData>().crc_algorithm = algorithm;
+                app.update();
+                let merkle = MerkleCrc::calculate(&app.world).unwrap();
+                assert_eq!(merkle.depth(), 4);
+                assert_eq!(merkle.root(), app.world.resource::<crate::FullCrc>().0);
+                assert_eq!(merkle.root() as u16, app.world.resource::<crate::Crc>().0);
+            }
+        }
+    }
+
+    #[test]
+    fn single_change_only_alters_path_to_root() {
+        let (mut app, entities) = build_app(CombineStrategy::Concatenate);
+        let before = MerkleCrc::calculate(&app.world).unwrap();
+        app.world.get_mut::<Foo>(entities[6]).unwrap().0 = 100;
+        let after = MerkleCrc::calculate(&app.world).unwrap();
+        assert_eq!(after.leaf_entity(6), Some(entities[6]));
+
+        for depth in 0..=before.depth() {
+            let path = 6 >> (before.depth() - depth);
+            let mut index = 0;
+            while let Some(crc) = before.node(depth, index) {
+                assert_eq!(crc != after.node(depth, index).unwrap(), index == path);
+                index += 1;
+            }
+        }
+        assert_eq!(before.diverging_leaves(&after), vec![6]);
+    }
+}
@@ -0,0 +1,100 @@
+use bevy_ecs::world::World;
+use bevy_utils::tracing::warn;
+
+use crate::{checked, report::calculate_status, CombineStrategy, CrcScope, DesyncError};
+
+/// The CRC of a world under two combine strategies at once, for migrating between them. Peers
+/// exchange their `DualCrc`s for a few ticks, and [`DualCrc::verdict`] checks both strategies
+/// reach the same conclusion about whether the peers are in sync before switching over.
+///
+/// The two CRCs aren't expected to be equal to each other, only to agree with the remote's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DualCrc {
+    pub strategies: [CombineStrategy; 2],
+    pub crcs: [u16; 2],
+}
+
+impl DualCrc {
+    /// Calculate the CRC under both strategies. Fails like [`crate::calculate_crc`]
+    pub fn calculate(world: &World, strategies: [CombineStrategy; 2]) -> Result<Self, DesyncError> {
+        checked(world, |world| DualCrc {
+            strategies,
+            crcs: strategies.map(|combine| {
+                calculate_status(world, CrcScope::Shared, combine, &|_, _| true)
+                    .0
+                    .crc
+            }),
+        })
+    }
+
+    /// Whether this and `remote` are in sync. If the two strategies disagree this warns and
+    /// returns `None`, which means one of them can't be trusted for this world, e.g. because
+    /// identical entities cancel out under [`CombineStrategy::Xor`].
+    ///
+    /// Panics if `remote` was calculated with different strategies
+    pub fn verdict(&self, remote: &DualCrc) -> Option<bool> {
+        assert_eq!(
+            self.strategies, remote.strategies,
+            "both peers must calculate the same strategies"
+        );
+        let [a, b] = [0, 1].map(|i| self.crcs[i] == remote.crcs[i]);
+        if a != b {
+            warn!(
+                "{:?} says {} but {:?} says {}",
+                self.strategies[0],
+                in_sync(a),
+                self.strategies[1],
+                in_sync(b)
+            );
+            return None;
+        }
+        Some(a)
+    }
+}
+
+fn in_sync(synced: bool) -> &'static str {
+    match synced {
+        true => "in sync",
+        false => "desynced",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u32);
+
+    const STRATEGIES: [CombineStrategy; 2] = [CombineStrategy::Concatenate, CombineStrategy::Xor];
+
+    fn dual_crc(values: &[u32]) -> DualCrc {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        for value in values {
+            app.world.spawn((Foo(*value), TrackDesync));
+        }
+        DualCrc::calculate(&app.world, STRATEGIES).unwrap()
+    }
+
+    #[test]
+    fn strategies_agree_on_desync() {
+        let local = dual_crc(&[1, 2]);
+        assert_ne!(local.crcs[0], local.crcs[1]);
+        assert_eq!(local.verdict(&dual_crc(&[1, 2])), Some(true));
+        assert_eq!(local.verdict(&dual_crc(&[1, 3])), Some(false));
+    }
+
+    #[test]
+    fn strategies_disagree() {
+        // pairs of identical entities cancel out under xor, hiding the desync
+        let local = dual_crc(&[5, 5]);
+        assert_eq!(local.verdict(&dual_crc(&[7, 7])), None);
+    }
+}
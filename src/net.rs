@@ -0,0 +1,404 @@
+use bevy_ecs::{
+    event::Event,
+    system::Resource,
+    world::{Mut, World},
+};
+use bevy_utils::tracing::warn;
+use std::collections::VecDeque;
+
+use crate::{
+    calculate_crc_detailed, capture::capture_desync, CrcHistory, DesyncCaptures, DesyncError,
+    DesyncPluginData, DesyncReport, DesyncTick,
+};
+
+/// Message for exchanging CRCs with a peer. Carries [`crate::Crc`], the low 16 bits of the CRC,
+/// to keep the message small
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrcMessage {
+    pub tick: u64,
+    pub crc: u16,
+}
+
+impl CrcMessage {
+    /// Size of an encoded message in bytes
+    pub const SIZE: usize = 10;
+
+    /// Encode as little endian `tick` followed by `crc`
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.tick.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a message produced by [`CrcMessage::encode`]. Returns `None` if `bytes` is the wrong
+    /// length
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(CrcMessage {
+            tick: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            crc: u16::from_le_bytes(bytes[8..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Everything `update_crc` learned about the world in one place, updated alongside [`crate::Crc`].
+/// Comparing the whole status with a peer's is a stronger check than the CRC alone, since two
+/// worlds which collide on the CRC rarely also agree on the counts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub struct DesyncStatus {
+    pub crc: u16,
+    /// Tick the CRC was recorded against, see [`crate::DesyncTick`]
+    pub tick: u64,
+    /// Number of tracked entities which were hashed
+    pub entity_count: u32,
+    /// Total length of the serialized components which were hashed
+    pub byte_count: u32,
+}
+
+impl DesyncStatus {
+    /// Size of an encoded status in bytes
+    pub const SIZE: usize = 18;
+
+    pub(crate) fn from_report(report: &DesyncReport) -> Self {
+        DesyncStatus {
+            crc: report.crc,
+            tick: 0,
+            entity_count: report.entities.len() as u32,
+            byte_count: report.byte_count() as u32,
+        }
+    }
+
+    /// Encode as little endian `tick`, `crc`, `entity_count` then `byte_count`
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.tick.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.crc.to_le_bytes());
+        bytes[10..14].copy_from_slice(&self.entity_count.to_le_bytes());
+        bytes[14..].copy_from_slice(&self.byte_count.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a status produced by [`DesyncStatus::encode`]. Returns `None` if `bytes` is the
+    /// wrong length
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(DesyncStatus {
+            tick: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            crc: u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+            entity_count: u32::from_le_bytes(bytes[10..14].try_into().unwrap()),
+            byte_count: u32::from_le_bytes(bytes[14..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Sent when a CRC received from a remote doesn't match the one recorded locally for the same
+/// tick, by `update_crc` for messages handed to [`CrcMessages::receive`], and by
+/// [`crate::compare_peer_crc`]. Compares the 16 bit [`crate::Crc`]s
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct DesyncDetected {
+    pub tick: u64,
+    /// CRC recorded locally for `tick`
+    pub local: u16,
+    /// CRC the remote sent for `tick`
+    pub remote: u16,
+}
+
+/// Buffers [`CrcMessage`]s between `update_crc` and the networking layer, added by the plugin's
+/// `message_queue` option. `update_crc` queues a message for every CRC it records and checks
+/// received messages against [`CrcHistory`]; the networking layer sends whatever
+/// [`CrcMessages::take_outgoing`] returns and hands received messages to [`CrcMessages::receive`].
+#[derive(Clone, Debug, Default, Resource)]
+pub struct CrcMessages {
+    outgoing: VecDeque<CrcMessage>,
+    /// Received messages for ticks this app hasn't recorded yet
+    incoming: VecDeque<CrcMessage>,
+    /// Ticks whose received CRC didn't match ours, oldest first
+    mismatched: Vec<u64>,
+    /// Ticks whose received CRC arrived after they'd left the history
+    unverified: Vec<u64>,
+}
+
+/// What [`flush_desync_messages`] drained
+#[derive(Clone, Debug, Default, PartialEq, Eq, Resource)]
+pub struct DesyncFlush {
+    /// Messages which hadn't been sent yet
+    pub outgoing: Vec<CrcMessage>,
+    /// Every tick whose received CRC didn't match ours
+    pub mismatched: Vec<u64>,
+    /// Ticks a CRC was received for which this app never recorded, or has dropped from its
+    /// history
+    pub unverified: Vec<u64>,
+}
+
+impl CrcMessages {
+    pub fn receive(&mut self, message: CrcMessage) {
+        self.incoming.push_back(message);
+    }
+
+    /// Messages to send to peers, oldest first
+    pub fn take_outgoing(&mut self) -> Vec<CrcMessage> {
+        self.outgoing.drain(..).collect()
+    }
+
+    /// Ticks whose received CRC didn't match ours, oldest first
+    pub fn mismatched(&self) -> &[u64] {
+        &self.mismatched
+    }
+
+    /// Number of received messages still waiting for this app to reach their tick
+    pub fn pending(&self) -> usize {
+        self.incoming.len()
+    }
+
+    /// Check every received message whose tick has been recorded, returning those which didn't
+    /// match
+    fn reconcile(&mut self, history: &CrcHistory, current_tick: u64) -> Vec<DesyncDetected> {
+        let mut pending = VecDeque::new();
+        let mut detected = Vec::new();
+        for message in self.incoming.drain(..) {
+            match history.get(message.tick) {
+                Some(crc) if crc != message.crc => {
+                    self.mismatched.push(message.tick);
+                    detected.push(DesyncDetected {
+                        tick: message.tick,
+                        local: crc,
+                        remote: message.crc,
+                    });
+                }
+                Some(_) => {}
+                None if message.tick >= current_tick => pending.push_back(message),
+                None => self.unverified.push(message.tick),
+            }
+        }
+        self.incoming = pending;
+        detected
+    }
+}
+
+/// Queue the CRC recorded for `tick` and check received messages, sending [`DesyncDetected`] for
+/// each mismatch and capturing the first for [`DesyncCaptures`] if it was added. Called by
+/// `update_crc`
+pub(crate) fn queue_crc_message(world: &mut World, tick: u64, crc: u16) {
+    let detected = world.resource_scope(|world, mut messages: Mut<CrcMessages>| {
+        messages.outgoing.push_back(CrcMessage { tick, crc });
+        messages.reconcile(world.resource::<CrcHistory>(), tick + 1)
+    });
+    if let Some(first) = detected.first() {
+        if world.contains_resource::<DesyncCaptures>() {
+            let message = CrcMessage {
+                tick: first.tick,
+                crc: first.remote,
+            };
+            capture_desync(world, message, None);
+        }
+    }
+    world.send_event_batch(detected);
+}
+
+/// Drain [`CrcMessages`] for a final reconciliation, e.g. before disconnecting or when the match
+/// ends, so the last ticks' state isn't lost. Received messages are checked against the history
+/// one last time, and any still waiting on a tick this app never reached are reported as
+/// unverified. The plugin calls this on [`bevy_app::AppExit`] when `message_queue` is set, and
+/// inserts the result as a [`DesyncFlush`] resource.
+pub fn flush_desync_messages(world: &mut World) -> DesyncFlush {
+    let history = world.resource::<CrcHistory>().clone();
+    let current_tick = world.resource::<DesyncTick>().0;
+    let mut messages = world.resource_mut::<CrcMessages>();
+    let detected = messages.reconcile(&history, current_tick);
+    let mut unverified = std::mem::take(&mut messages.unverified);
+    unverified.extend(messages.incoming.drain(..).map(|m| m.tick));
+    let flush = DesyncFlush {
+        outgoing: messages.take_outgoing(),
+        mismatched: std::mem::take(&mut messages.mismatched),
+        unverified,
+    };
+    if !flush.mismatched.is_empty() {
+        warn!("desynced on ticks {:?}", flush.mismatched);
+    }
+    world.send_event_batch(detected);
+    flush
+}
+
+/// Flush on exit, keeping the result for after the app stops
+pub(crate) fn flush_on_exit(world: &mut World) {
+    let flush = flush_desync_messages(world);
+    world.insert_resource(flush);
+}
+
+/// Per-entity hashes keyed by the configured [`crate::DesyncIdentity`], sorted by key, so a peer
+/// can line them up with its own and find exactly which entities diverged. Fails like
+/// [`crate::calculate_crc`]
+pub fn per_entity_message(world: &World) -> Result<Vec<(u64, u64)>, DesyncError> {
+    let desync_data = world.resource::<DesyncPluginData>();
+    let mut message = calculate_crc_detailed(world)?
+        .entities
+        .iter()
+        .map(|entity| (desync_data.identity.key(entity.entity, world), entity.crc()))
+        .collect::<Vec<_>>();
+    message.sort();
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, AppExit, Last};
+    use bevy_ecs::{component::Component, entity::Entity, event::EventReader, system::ResMut};
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    #[derive(Component)]
+    struct NetId(u64);
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            identity: Arc::new(|entity: Entity, world: &World| {
+                world.get::<NetId>(entity).unwrap().0
+            }),
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app
+    }
+
+    #[test]
+    fn keyed_per_entity_message() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1.world.spawn((NetId(5), Health(1), TrackDesync));
+        app_1.world.spawn((NetId(9), Health(2), TrackDesync));
+        // spawned in a different order, with a different health for one entity
+        app_2.world.spawn((NetId(9), Health(3), TrackDesync));
+        app_2.world.spawn((NetId(5), Health(1), TrackDesync));
+
+        let message_1 = per_entity_message(&app_1.world).unwrap();
+        let message_2 = per_entity_message(&app_2.world).unwrap();
+        let keys = |message: &[(u64, u64)]| message.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(&message_1), vec![5, 9]);
+        assert_eq!(keys(&message_2), vec![5, 9]);
+        assert_eq!(message_1[0], message_2[0]);
+        assert_ne!(message_1[1], message_2[1]);
+    }
+
+    #[test]
+    fn status_matches_when_synced() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        for app in [&mut app_1, &mut app_2] {
+            app.world.spawn((NetId(1), Health(10), TrackDesync));
+            app.world.spawn((NetId(2), Health(20), TrackDesync));
+        }
+        app_1.update();
+        app_2.update();
+        let status = *app_1.world.resource::<DesyncStatus>();
+        assert_eq!(status, *app_2.world.resource::<DesyncStatus>());
+        assert_eq!(status.entity_count, 2);
+        assert_eq!(status.byte_count, 4);
+        assert_eq!(DesyncStatus::decode(&status.encode()), Some(status));
+
+        app_2.world.spawn((NetId(3), Health(1), TrackDesync));
+        app_1.update();
+        app_2.update();
+        let status = *app_1.world.resource::<DesyncStatus>();
+        assert_eq!(status.tick, 1);
+        assert_ne!(status, *app_2.world.resource::<DesyncStatus>());
+    }
+
+    #[test]
+    fn flush_drains_messages() {
+        let mut app = build_app();
+        app.world.init_resource::<CrcMessages>();
+        app.world.spawn((NetId(1), Health(10), TrackDesync));
+        app.update();
+        app.update();
+        let crc = app.world.resource::<Crc>().0;
+        let mut messages = app.world.resource_mut::<CrcMessages>();
+        messages.receive(CrcMessage { tick: 0, crc });
+        messages.receive(CrcMessage { tick: 1, crc: !crc });
+        // the remote got further than us
+        messages.receive(CrcMessage { tick: 5, crc });
+        app.update();
+        assert_eq!(app.world.resource::<CrcMessages>().mismatched(), &[1]);
+        assert_eq!(app.world.resource::<CrcMessages>().pending(), 1);
+
+        let flush = flush_desync_messages(&mut app.world);
+        let ticks = flush.outgoing.iter().map(|m| m.tick).collect::<Vec<_>>();
+        assert_eq!(ticks, vec![0, 1, 2]);
+        assert_eq!(flush.mismatched, vec![1]);
+        assert_eq!(flush.unverified, vec![5]);
+        let mut messages = app.world.resource_mut::<CrcMessages>();
+        assert_eq!(messages.pending(), 0);
+        assert!(messages.mismatched().is_empty());
+        assert!(messages.take_outgoing().is_empty());
+    }
+
+    #[derive(Default, Resource)]
+    struct Detected(Vec<DesyncDetected>);
+
+    fn record_detected(mut events: EventReader<DesyncDetected>, mut detected: ResMut<Detected>) {
+        detected.0.extend(events.read().copied());
+    }
+
+    #[test]
+    fn mismatch_sends_event() {
+        let [mut app_1, mut app_2] = [1, 2].map(|health| {
+            let mut app = build_app();
+            app.world.init_resource::<CrcMessages>();
+            app.world.spawn((NetId(1), Health(health), TrackDesync));
+            app
+        });
+        app_1
+            .init_resource::<Detected>()
+            .add_systems(Last, record_detected);
+        app_2.update();
+        let remote = app_2.world.resource_mut::<CrcMessages>().take_outgoing();
+        app_1.world.resource_mut::<CrcMessages>().receive(remote[0]);
+        app_1.update();
+        app_1.update();
+        assert_eq!(
+            app_1.world.resource::<Detected>().0,
+            vec![DesyncDetected {
+                tick: 0,
+                local: app_1.world.resource::<CrcHistory>().get(0).unwrap(),
+                remote: remote[0].crc,
+            }]
+        );
+    }
+
+    #[test]
+    fn flush_on_app_exit() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            message_queue: true,
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app.update();
+        assert!(!app.world.contains_resource::<DesyncFlush>());
+        app.world.send_event(AppExit);
+        app.update();
+        let flush = app.world.resource::<DesyncFlush>();
+        assert_eq!(flush.outgoing.len(), 2);
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let message = CrcMessage {
+            tick: 0x0102_0304_0506_0708,
+            crc: 0xabcd,
+        };
+        assert_eq!(CrcMessage::decode(&message.encode()), Some(message));
+        assert_eq!(CrcMessage::decode(&message.encode()[1..]), None);
+    }
+}
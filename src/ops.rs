@@ -0,0 +1,195 @@
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    system::{EntityCommands, Resource},
+    world::{EntityWorldMut, World},
+};
+
+use crate::{CrcScope, DesyncPluginData};
+
+/// Tracked component insertions and removals since the last `update_crc`, in the order they
+/// happened. Hashed into the CRC after every entity when the plugin's `hash_component_ops` option
+/// is set, so two peers which reach the same state through a different sequence of operations
+/// still diverge.
+///
+/// Only operations made through [`TrackedOpsExt`] are recorded.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct ComponentOpLog {
+    ops: Vec<ComponentOp>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComponentOp {
+    /// Key of the entity, from the configured [`crate::DesyncIdentity`]
+    pub entity: u64,
+    /// Type name of the component
+    pub component: &'static str,
+    pub kind: ComponentOpKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentOpKind {
+    Insert,
+    Remove,
+}
+
+impl ComponentOpLog {
+    /// Operations recorded since the last CRC, oldest first
+    pub fn ops(&self) -> &[ComponentOp] {
+        &self.ops
+    }
+
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    /// Encode the log for hashing
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for op in self.ops.iter() {
+            bytes.extend_from_slice(&op.entity.to_le_bytes());
+            bytes.push(match op.kind {
+                ComponentOpKind::Insert => 0,
+                ComponentOpKind::Remove => 1,
+            });
+            // type names never contain NUL, so this terminates the name unambiguously
+            bytes.extend_from_slice(op.component.as_bytes());
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+fn record_op<T: Component>(world: &mut World, entity: Entity, kind: ComponentOpKind) {
+    let Some(component_id) = world.component_id::<T>() else {
+        return;
+    };
+    if !world.contains_resource::<ComponentOpLog>() {
+        return;
+    }
+    let desync_data = world.resource::<DesyncPluginData>();
+    if !desync_data.is_tracked(&component_id, CrcScope::Local) {
+        return;
+    }
+    let op = ComponentOp {
+        entity: desync_data.identity.key(entity, world),
+        component: std::any::type_name::<T>(),
+        kind,
+    };
+    world.resource_mut::<ComponentOpLog>().ops.push(op);
+}
+
+/// Insert and remove tracked components while recording the operation in [`ComponentOpLog`]
+pub trait TrackedOpsExt {
+    fn insert_tracked<T: Component>(&mut self, component: T) -> &mut Self;
+    /// Remove a component, recording the removal only if the entity had it
+    fn remove_tracked<T: Component>(&mut self) -> &mut Self;
+}
+
+impl TrackedOpsExt for EntityWorldMut<'_> {
+    fn insert_tracked<T: Component>(&mut self, component: T) -> &mut Self {
+        let entity = self.id();
+        self.insert(component);
+        self.world_scope(|world| record_op::<T>(world, entity, ComponentOpKind::Insert));
+        self
+    }
+
+    fn remove_tracked<T: Component>(&mut self) -> &mut Self {
+        if !self.contains::<T>() {
+            return self;
+        }
+        let entity = self.id();
+        self.remove::<T>();
+        self.world_scope(|world| record_op::<T>(world, entity, ComponentOpKind::Remove));
+        self
+    }
+}
+
+impl TrackedOpsExt for EntityCommands<'_> {
+    fn insert_tracked<T: Component>(&mut self, component: T) -> &mut Self {
+        self.add(move |entity: Entity, world: &mut World| {
+            world.entity_mut(entity).insert_tracked(component);
+        })
+    }
+
+    fn remove_tracked<T: Component>(&mut self) -> &mut Self {
+        self.add(|entity: Entity, world: &mut World| {
+            world.entity_mut(entity).remove_tracked::<T>();
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct A(u8);
+
+    #[derive(Component, Serialize)]
+    struct B(u8);
+
+    fn build_app(hash_component_ops: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            hash_component_ops,
+            ..Default::default()
+        })
+        .track_desync::<A>();
+        app.track_desync::<B>();
+        app
+    }
+
+    fn crcs(hash_component_ops: bool) -> [(u16, u16); 2] {
+        let mut app_1 = build_app(hash_component_ops);
+        let mut app_2 = build_app(hash_component_ops);
+        let entity_1 = app_1.world.spawn((A(0), TrackDesync)).id();
+        let entity_2 = app_2.world.spawn((A(0), TrackDesync)).id();
+        app_1.update();
+        app_2.update();
+
+        // same end state, reached in a different order
+        app_1
+            .world
+            .entity_mut(entity_1)
+            .remove_tracked::<A>()
+            .insert_tracked(B(1))
+            .insert_tracked(A(0));
+        app_2
+            .world
+            .entity_mut(entity_2)
+            .insert_tracked(B(1))
+            .remove_tracked::<A>()
+            .insert_tracked(A(0));
+        app_1.update();
+        app_2.update();
+        let first = (
+            app_1.world.resource::<Crc>().0,
+            app_2.world.resource::<Crc>().0,
+        );
+
+        // the log is cleared every tick
+        app_1.update();
+        app_2.update();
+        let second = (
+            app_1.world.resource::<Crc>().0,
+            app_2.world.resource::<Crc>().0,
+        );
+        [first, second]
+    }
+
+    #[test]
+    fn op_order_affects_crc() {
+        let [first, second] = crcs(true);
+        assert_ne!(first.0, first.1);
+        assert_eq!(second.0, second.1);
+
+        let [first, second] = crcs(false);
+        assert_eq!(first.0, first.1);
+        assert_eq!(second.0, second.1);
+    }
+}
@@ -0,0 +1,105 @@
+use bevy_app::App;
+use bevy_ecs::bundle::Bundle;
+
+use crate::{calculate_crc_unchecked, DesyncPluginData, EntitySortFn, TrackDesync};
+
+/// Number of shuffled spawn orders tried by [`assert_order_independent`]
+const ORDERS: usize = 32;
+
+/// Spawn `bundles` with [`TrackDesync`] in many shuffled orders, each into a fresh app from
+/// `build_app` using `sort_strategy`, and assert every order hashes the same as spawning them in
+/// order. `build_app` must add [`crate::DesyncPlugin`] and register the tracked components.
+///
+/// Shuffles are seeded, so a failure always reproduces.
+pub fn assert_order_independent<B: Bundle + Clone>(
+    build_app: impl Fn() -> App,
+    bundles: &[B],
+    sort_strategy: EntitySortFn,
+) {
+    let crc = |order: &[usize]| {
+        let mut app = build_app();
+        app.world.resource_mut::<DesyncPluginData>().entity_sort = sort_strategy.clone();
+        for i in order {
+            app.world.spawn((bundles[*i].clone(), TrackDesync));
+        }
+        calculate_crc_unchecked(&app.world)
+    };
+
+    let mut order = (0..bundles.len()).collect::<Vec<_>>();
+    let expected = crc(&order);
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..ORDERS {
+        rng.shuffle(&mut order);
+        let actual = crc(&order);
+        assert_eq!(
+            actual, expected,
+            "spawning in order {order:?} changed the CRC from {expected:#06x} to {actual:#06x}"
+        );
+    }
+}
+
+/// Small deterministic generator, enough for shuffling
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Fisher-Yates
+    fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            values.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{sort_entities_ids, AppDesyncExt, CombineStrategy, DesyncPlugin};
+
+    #[derive(Clone, Component, Serialize)]
+    struct Unit(u32);
+
+    fn bundles() -> Vec<Unit> {
+        (0..8).map(Unit).collect()
+    }
+
+    fn build_app(combine: CombineStrategy) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            combine,
+            ..Default::default()
+        })
+        .track_desync::<Unit>();
+        app
+    }
+
+    #[test]
+    fn commutative_is_order_independent() {
+        assert_order_independent(
+            || build_app(CombineStrategy::Xor),
+            &bundles(),
+            Arc::new(Box::new(sort_entities_ids)),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "changed the CRC")]
+    fn id_sort_is_order_dependent() {
+        assert_order_independent(
+            || build_app(CombineStrategy::Concatenate),
+            &bundles(),
+            Arc::new(Box::new(sort_entities_ids)),
+        );
+    }
+}
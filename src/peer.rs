@@ -0,0 +1,193 @@
+use bevy_ecs::{event::Event, system::Resource, world::World};
+use std::collections::HashMap;
+
+use crate::{capture::capture_desync, CrcHistory, CrcMessage, DesyncCaptures, DesyncDetected};
+
+/// Identifies a remote peer, in whatever way the networking layer does
+pub type PeerId = u64;
+
+/// Consecutive mismatching ticks per remote peer, added by the plugin's `resync_threshold`
+/// option. A single mismatch can be a blip, e.g. a late input which rollback is about to fix,
+/// while a streak of them means the peers won't recover without a full resync.
+#[derive(Clone, Debug, Resource)]
+pub struct PeerDesyncState {
+    threshold: u32,
+    streaks: HashMap<PeerId, u32>,
+}
+
+/// Sent by [`compare_peer_crc`] when a peer's mismatch streak reaches the threshold. Sent once per
+/// streak, so a peer which stays desynced isn't reported again until it has matched at least once
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct NeedsResync {
+    pub peer: PeerId,
+    /// Length of the streak, which is the threshold
+    pub streak: u32,
+}
+
+impl PeerDesyncState {
+    pub fn new(threshold: u32) -> Self {
+        PeerDesyncState {
+            threshold,
+            streaks: HashMap::new(),
+        }
+    }
+
+    /// Number of consecutive ticks `peer`'s CRC hasn't matched ours
+    pub fn streak(&self, peer: PeerId) -> u32 {
+        self.streaks.get(&peer).copied().unwrap_or(0)
+    }
+
+    /// Record whether a tick matched `peer`. Returns whether this brought the streak to the
+    /// threshold
+    pub fn record(&mut self, peer: PeerId, matched: bool) -> bool {
+        let streak = self.streaks.entry(peer).or_default();
+        *streak = match matched {
+            true => 0,
+            false => streak.saturating_add(1),
+        };
+        !matched && *streak == self.threshold
+    }
+
+    /// Forget a peer, e.g. once it has disconnected or been resynced
+    pub fn remove_peer(&mut self, peer: PeerId) {
+        self.streaks.remove(&peer);
+    }
+}
+
+/// Compare a CRC received from `peer` with the one recorded for the same tick. If
+/// [`PeerDesyncState`] was added, this updates the peer's streak and sends [`NeedsResync`] when it
+/// reaches the threshold. A mismatch sends [`DesyncDetected`], and is captured for
+/// [`DesyncCaptures`] if it was added. Returns whether the CRCs matched, or `None` without
+/// touching the streak if the tick isn't in [`CrcHistory`]
+pub fn compare_peer_crc(world: &mut World, peer: PeerId, message: CrcMessage) -> Option<bool> {
+    let local = world.resource::<CrcHistory>().get(message.tick)?;
+    let matched = local == message.crc;
+    let resync = world
+        .get_resource_mut::<PeerDesyncState>()
+        .and_then(|mut state| state.record(peer, matched).then(|| state.streak(peer)));
+    if let Some(streak) = resync {
+        world.send_event(NeedsResync { peer, streak });
+    }
+    if !matched {
+        world.send_event(DesyncDetected {
+            tick: message.tick,
+            local,
+            remote: message.crc,
+        });
+        if world.contains_resource::<DesyncCaptures>() {
+            capture_desync(world, message, Some(peer));
+        }
+    }
+    Some(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::{component::Component, event::Events};
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Foo(u32);
+
+    #[test]
+    fn resync_at_threshold() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            resync_threshold: Some(3),
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.world.spawn((Foo(0), TrackDesync));
+        let mut crcs = Vec::new();
+        for _ in 0..6 {
+            app.update();
+            crcs.push(app.world.resource::<crate::Crc>().0);
+        }
+        let compare = |app: &mut App, peer, tick: usize, matched| {
+            let crc = crcs[tick] ^ if matched { 0 } else { 1 };
+            let message = CrcMessage {
+                tick: tick as u64,
+                crc,
+            };
+            compare_peer_crc(&mut app.world, peer, message).unwrap()
+        };
+        let resyncs = |app: &mut App| {
+            let mut events = app.world.resource_mut::<Events<NeedsResync>>();
+            events.drain().collect::<Vec<_>>()
+        };
+
+        assert!(!compare(&mut app, 1, 0, false));
+        // a match resets the streak
+        assert!(compare(&mut app, 1, 1, true));
+        compare(&mut app, 1, 2, false);
+        compare(&mut app, 2, 2, false);
+        compare(&mut app, 1, 3, false);
+        assert!(resyncs(&mut app).is_empty());
+        compare(&mut app, 1, 4, false);
+        assert_eq!(resyncs(&mut app), vec![NeedsResync { peer: 1, streak: 3 }]);
+        compare(&mut app, 1, 5, false);
+        assert!(resyncs(&mut app).is_empty());
+
+        let state = app.world.resource::<PeerDesyncState>();
+        assert_eq!(state.streak(1), 4);
+        assert_eq!(state.streak(2), 1);
+        assert_eq!(
+            compare_peer_crc(&mut app.world, 1, CrcMessage { tick: 99, crc: 0 }),
+            None
+        );
+    }
+
+    #[test]
+    fn compared_without_streaks() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        app.world.spawn((Foo(0), TrackDesync));
+        app.update();
+        let crc = app.world.resource::<crate::Crc>().0;
+
+        let message = |crc| CrcMessage { tick: 0, crc };
+        assert_eq!(
+            compare_peer_crc(&mut app.world, 1, message(crc)),
+            Some(true)
+        );
+        assert_eq!(
+            compare_peer_crc(&mut app.world, 1, message(crc ^ 1)),
+            Some(false)
+        );
+        assert!(!app.world.contains_resource::<PeerDesyncState>());
+    }
+
+    #[test]
+    fn mismatch_detected_with_default_config() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Foo>();
+        app.world.spawn((Foo(0), TrackDesync));
+        app.update();
+        let crc = app.world.resource::<crate::Crc>().0;
+
+        let message = CrcMessage {
+            tick: 0,
+            crc: crc ^ 1,
+        };
+        compare_peer_crc(&mut app.world, 1, message);
+        let detected = app
+            .world
+            .resource_mut::<Events<DesyncDetected>>()
+            .drain()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            detected,
+            vec![DesyncDetected {
+                tick: 0,
+                local: crc,
+                remote: crc ^ 1,
+            }]
+        );
+    }
+}
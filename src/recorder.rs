@@ -0,0 +1,240 @@
+use bevy_ecs::{system::Resource, world::World};
+use bevy_utils::tracing::warn;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::{
+    report::{calculate_crc_scoped, component_name},
+    CrcScope, DesyncPluginData,
+};
+
+/// Opens segment `n` of a recording
+pub type OpenSegmentFn =
+    Arc<dyn Fn(usize) -> io::Result<Box<dyn Write + Send + Sync>> + Send + Sync>;
+
+/// Records the tracked state every tick for post-mortem analysis, set on the plugin's `recorder`
+/// option. Each CRC is written as a line of JSON, tagged with its tick:
+///
+/// `{"tick":3,"crc":4660,"entities":[{"key":1,"components":{"game::Health":"10"}}]}`
+///
+/// Components are their serialized form as in [`crate::ComponentReport::serialized`], which is
+/// what's hashed unless a custom hash serializer is registered, and entities are identified by the
+/// configured [`crate::DesyncIdentity`]. Entities are in hashing order, or in key order if the
+/// combine strategy doesn't depend on the order, so recordings from two peers can be diffed line
+/// by line.
+///
+/// A recording is split into segments of about `segment_bytes`: once a segment is full, the next
+/// line starts a new one. If writing fails, a warning is logged and nothing more is recorded.
+#[derive(Clone)]
+pub struct DesyncRecorder {
+    open: OpenSegmentFn,
+    segment_bytes: u64,
+}
+
+impl DesyncRecorder {
+    pub fn new(
+        segment_bytes: u64,
+        open: impl Fn(usize) -> io::Result<Box<dyn Write + Send + Sync>> + Send + Sync + 'static,
+    ) -> Self {
+        DesyncRecorder {
+            open: Arc::new(open),
+            segment_bytes,
+        }
+    }
+
+    /// Record to `{prefix}.{n}.jsonl`, keeping only the latest `keep` segments on disk so a long
+    /// match can't fill it
+    pub fn files(prefix: impl Into<PathBuf>, segment_bytes: u64, keep: usize) -> Self {
+        let prefix = prefix.into();
+        let path = move |segment: usize| {
+            let mut path = prefix.clone().into_os_string();
+            path.push(format!(".{segment}.jsonl"));
+            PathBuf::from(path)
+        };
+        Self::new(segment_bytes, move |segment| {
+            if let Some(stale) = segment.checked_sub(keep) {
+                match fs::remove_file(path(stale)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            let file = File::create(path(segment))?;
+            Ok(Box::new(BufWriter::new(file)))
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct RecordedTick<'a> {
+    tick: u64,
+    crc: u16,
+    entities: Vec<RecordedEntity<'a>>,
+}
+
+#[derive(Serialize)]
+struct RecordedEntity<'a> {
+    key: u64,
+    components: BTreeMap<&'a str, &'a str>,
+}
+
+/// The recorder and the segment being written
+#[derive(Resource)]
+pub(crate) struct Recording {
+    recorder: DesyncRecorder,
+    writer: Option<Box<dyn Write + Send + Sync>>,
+    segment: usize,
+    written: u64,
+    failed: bool,
+}
+
+impl Recording {
+    pub(crate) fn new(recorder: DesyncRecorder) -> Self {
+        Recording {
+            recorder,
+            writer: None,
+            segment: 0,
+            written: 0,
+            failed: false,
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let full =
+            self.written > 0 && self.written + line.len() as u64 > self.recorder.segment_bytes;
+        if full {
+            if let Some(mut writer) = self.writer.take() {
+                writer.flush()?;
+            }
+            self.segment += 1;
+            self.written = 0;
+        }
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert((self.recorder.open)(self.segment)?),
+        };
+        writer.write_all(line)?;
+        // a playtest which crashes should still leave the last ticks behind
+        writer.flush()?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Write the tracked state `crc` was calculated from
+pub(crate) fn record_tick(world: &mut World, tick: u64, crc: u16) {
+    if world.resource::<Recording>().failed {
+        return;
+    }
+    let desync_data = world.resource::<DesyncPluginData>();
+    // only recorded once the CRC was calculated without errors
+    let report = calculate_crc_scoped(world, CrcScope::Shared, true);
+    let mut entities = report
+        .entities
+        .iter()
+        .map(|entity| RecordedEntity {
+            key: desync_data.identity.key(entity.entity, world),
+            components: entity
+                .components
+                .iter()
+                .map(|c| (component_name(world, &c.id), c.serialized.as_str()))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    if desync_data.combine.is_commutative() {
+        entities.sort_by_key(|entity| entity.key);
+    }
+    let mut line = serde_json::to_vec(&RecordedTick {
+        tick,
+        crc,
+        entities,
+    })
+    .unwrap();
+    line.push(b'\n');
+    let mut recording = world.resource_mut::<Recording>();
+    if let Err(e) = recording.write_line(&line) {
+        warn!("stopped recording the tracked state at tick {tick}: {e}");
+        recording.failed = true;
+        recording.writer = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    #[derive(Clone, Default)]
+    struct Segments(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    struct SegmentWriter(Segments, usize);
+
+    impl Write for SegmentWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            (self.0).0.lock().unwrap()[self.1].extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ticks_recorded_in_segments() {
+        let segments = Segments::default();
+        let open_segments = segments.clone();
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            recorder: Some(DesyncRecorder::new(250, move |segment| {
+                let mut all = open_segments.0.lock().unwrap();
+                assert_eq!(all.len(), segment);
+                all.push(Vec::new());
+                Ok(Box::new(SegmentWriter(open_segments.clone(), segment)))
+            })),
+            ..Default::default()
+        })
+        .track_desync::<Health>();
+        app.world.spawn((Health(10), TrackDesync));
+        let mut crcs = Vec::new();
+        for _ in 0..4 {
+            app.update();
+            crcs.push(app.world.resource::<Crc>().0);
+        }
+
+        let segments = segments.0.lock().unwrap();
+        // two lines fit in each segment
+        assert_eq!(segments.len(), 2);
+        let lines = segments
+            .iter()
+            .flat_map(|segment| {
+                String::from_utf8(segment.clone())
+                    .unwrap()
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        for (tick, line) in lines.iter().enumerate() {
+            let recorded = serde_json::from_str::<Value>(line).unwrap();
+            assert_eq!(recorded["tick"], tick);
+            assert_eq!(recorded["crc"], crcs[tick]);
+            let components = &recorded["entities"][0]["components"];
+            assert_eq!(components[std::any::type_name::<Health>()], "10");
+        }
+    }
+}
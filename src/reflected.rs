@@ -0,0 +1,220 @@
+use bevy_reflect::{
+    serde::SerializationData, ReflectSerialize, TypeInfo, TypeRegistry, VariantInfo,
+};
+use std::{any::TypeId, collections::HashSet, fmt};
+
+/// Why a component can't be tracked with [`crate::AppDesyncExt::track_desync_reflect`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReflectTrackError {
+    /// The type at `path`, e.g. `Player.inventory`, isn't registered in the
+    /// [`bevy_ecs::reflect::AppTypeRegistry`], so its fields can't be known ahead of time.
+    /// Register it with `App::register_type`
+    Unregistered { path: String, type_path: String },
+    /// The value at `path` has no fields to reflect and doesn't reflect `Serialize`, so there's
+    /// nothing to hash it with. Register it with `#[reflect(Serialize)]`, or skip the field
+    NotSerializable { path: String, type_path: String },
+}
+
+impl fmt::Display for ReflectTrackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectTrackError::Unregistered { path, type_path } => {
+                write!(f, "{path} is a {type_path}, which isn't registered")
+            }
+            ReflectTrackError::NotSerializable { path, type_path } => {
+                write!(
+                    f,
+                    "{path} is a {type_path}, which isn't registered with #[reflect(Serialize)]"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReflectTrackError {}
+
+/// Check every value nested in the registered type `type_id` can be serialized through
+/// reflection, as `TypedReflectSerializer` would: either it reflects `Serialize`, or it's made up
+/// of fields which can be
+pub(crate) fn check_reflect_serializable(
+    registry: &TypeRegistry,
+    type_id: TypeId,
+) -> Result<(), ReflectTrackError> {
+    let registration = registry.get(type_id).unwrap();
+    let path = registration.type_info().type_path_table().short_path();
+    let type_path = registration.type_info().type_path();
+    check_type(
+        registry,
+        type_id,
+        type_path,
+        path.to_string(),
+        &mut HashSet::new(),
+    )
+}
+
+fn check_type(
+    registry: &TypeRegistry,
+    type_id: TypeId,
+    type_path: &str,
+    path: String,
+    visited: &mut HashSet<TypeId>,
+) -> Result<(), ReflectTrackError> {
+    // recursive types are checked once
+    if !visited.insert(type_id) {
+        return Ok(());
+    }
+    let Some(registration) = registry.get(type_id) else {
+        return Err(ReflectTrackError::Unregistered {
+            path,
+            type_path: type_path.to_string(),
+        });
+    };
+    if registration.data::<ReflectSerialize>().is_some() {
+        return Ok(());
+    }
+    let skipped = registration.data::<SerializationData>();
+    let serialized = |index: &usize| !skipped.is_some_and(|data| data.is_field_skipped(*index));
+    let mut check =
+        |type_id, type_path, path| check_type(registry, type_id, type_path, path, visited);
+    match registration.type_info() {
+        TypeInfo::Struct(info) => {
+            for (_, field) in info.iter().enumerate().filter(|(i, _)| serialized(i)) {
+                let path = format!("{path}.{}", field.name());
+                check(field.type_id(), field.type_path(), path)?;
+            }
+        }
+        TypeInfo::TupleStruct(info) => {
+            for (i, field) in info.iter().enumerate().filter(|(i, _)| serialized(i)) {
+                check(field.type_id(), field.type_path(), format!("{path}.{i}"))?;
+            }
+        }
+        TypeInfo::Tuple(info) => {
+            for (i, field) in info.iter().enumerate().filter(|(i, _)| serialized(i)) {
+                check(field.type_id(), field.type_path(), format!("{path}.{i}"))?;
+            }
+        }
+        TypeInfo::List(info) => {
+            let item_path = info.item_type_path_table().path();
+            check(info.item_type_id(), item_path, format!("{path}[]"))?;
+        }
+        TypeInfo::Array(info) => {
+            let item_path = info.item_type_path_table().path();
+            check(info.item_type_id(), item_path, format!("{path}[]"))?;
+        }
+        TypeInfo::Map(info) => {
+            let key_path = info.key_type_path_table().path();
+            check(info.key_type_id(), key_path, format!("{path}.key"))?;
+            let value_path = info.value_type_path_table().path();
+            check(info.value_type_id(), value_path, format!("{path}.value"))?;
+        }
+        TypeInfo::Enum(info) => {
+            for variant in info.iter() {
+                match variant {
+                    VariantInfo::Struct(variant) => {
+                        for field in variant.iter() {
+                            let path = format!("{path}::{}.{}", variant.name(), field.name());
+                            check(field.type_id(), field.type_path(), path)?;
+                        }
+                    }
+                    VariantInfo::Tuple(variant) => {
+                        for (i, field) in variant.iter().enumerate() {
+                            let path = format!("{path}::{}.{i}", variant.name());
+                            check(field.type_id(), field.type_path(), path)?;
+                        }
+                    }
+                    VariantInfo::Unit(_) => {}
+                }
+            }
+        }
+        TypeInfo::Value(_) => {
+            return Err(ReflectTrackError::NotSerializable {
+                path,
+                type_path: type_path.to_string(),
+            })
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use bevy_reflect::{Reflect, TypePath};
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    #[derive(Reflect)]
+    struct Stats {
+        speed: f32,
+        #[reflect(skip_serializing)]
+        cache: u32,
+    }
+
+    #[derive(Component, Reflect)]
+    struct Player {
+        stats: Stats,
+        items: Vec<u32>,
+    }
+
+    #[derive(Clone, Default, Reflect)]
+    #[reflect_value]
+    struct Opaque;
+
+    #[derive(Component, Reflect)]
+    struct Holder(Opaque);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .register_type::<Stats>()
+            .register_type::<Vec<u32>>();
+        app
+    }
+
+    fn crc(speed: f32, items: Vec<u32>, cache: u32) -> u16 {
+        let mut app = build_app();
+        app.track_desync_reflect::<Player>().unwrap();
+        let stats = Stats { speed, cache };
+        app.world.spawn((Player { stats, items }, TrackDesync));
+        app.update();
+        app.world.resource::<Crc>().0
+    }
+
+    #[test]
+    fn reflected_components_hash() {
+        assert_eq!(crc(1.0, vec![1, 2], 0), crc(1.0, vec![1, 2], 0));
+        assert_ne!(crc(1.0, vec![1, 2], 0), crc(2.0, vec![1, 2], 0));
+        assert_ne!(crc(1.0, vec![1, 2], 0), crc(1.0, vec![2, 1], 0));
+        // skipped fields aren't hashed
+        assert_eq!(crc(1.0, vec![1, 2], 0), crc(1.0, vec![1, 2], 1));
+    }
+
+    #[test]
+    fn unserializable_fields_rejected() {
+        let mut app = build_app();
+        app.register_type::<Opaque>();
+        assert_eq!(
+            app.track_desync_reflect::<Holder>(),
+            Err(ReflectTrackError::NotSerializable {
+                path: "Holder.0".to_string(),
+                type_path: Opaque::type_path().to_string(),
+            })
+        );
+
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default());
+        let Err(error) = app.track_desync_reflect::<Player>() else {
+            panic!("Stats isn't registered");
+        };
+        assert_eq!(
+            error,
+            ReflectTrackError::Unregistered {
+                path: "Player.stats".to_string(),
+                type_path: Stats::type_path().to_string(),
+            }
+        );
+        assert!(error.to_string().contains("isn't registered"));
+    }
+}
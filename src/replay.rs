@@ -0,0 +1,150 @@
+use bevy_ecs::{system::Resource, world::World};
+use std::collections::HashMap;
+
+use crate::{report::calculate_crc_scoped, CrcScope, DesyncReport};
+
+/// Checks a replay against the CRCs recorded when the input stream was first played, e.g. from
+/// [`crate::CrcHistory::iter`]. Insert it before replaying, and `update_crc` compares each tick's
+/// CRC against the recording, keeping a full report of the first tick which differs.
+///
+/// Ticks without a recorded CRC aren't checked.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct ReplayValidator {
+    recorded: HashMap<u64, u16>,
+    checked: usize,
+    divergence: Option<ReplayDivergence>,
+}
+
+/// The first tick a replay diverged from its recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayDivergence {
+    pub tick: u64,
+    /// CRC from the recording
+    pub expected: u16,
+    /// Breakdown of the replayed world at `tick`
+    pub report: DesyncReport,
+}
+
+impl ReplayValidator {
+    /// Validate against recorded `(tick, crc)` pairs
+    pub fn new(recorded: impl IntoIterator<Item = (u64, u16)>) -> Self {
+        ReplayValidator {
+            recorded: recorded.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The first divergent tick, if any
+    pub fn divergence(&self) -> Option<&ReplayDivergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Number of ticks which have been compared against the recording
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    /// Whether every recorded tick has been checked without diverging
+    pub fn is_complete(&self) -> bool {
+        self.divergence.is_none() && self.checked == self.recorded.len()
+    }
+}
+
+/// Compare the CRC of `tick` against the recording. Once a divergence is found nothing more is
+/// checked, as every later tick is expected to differ too
+pub(crate) fn validate_replay(world: &mut World, tick: u64, crc: u16) {
+    let validator = world.resource::<ReplayValidator>();
+    if validator.divergence.is_some() {
+        return;
+    }
+    let Some(expected) = validator.recorded.get(&tick).copied() else {
+        return;
+    };
+    let divergence = (expected != crc).then(|| ReplayDivergence {
+        tick,
+        expected,
+        // only validated once the CRC was calculated without errors
+        report: calculate_crc_scoped(world, CrcScope::Shared, true),
+    });
+    let mut validator = world.resource_mut::<ReplayValidator>();
+    validator.checked += 1;
+    validator.divergence = divergence;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, Update};
+    use bevy_ecs::{
+        component::Component,
+        system::{Local, Query, Res},
+    };
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{AppDesyncExt, CrcHistory, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Counter(u32);
+
+    #[derive(Resource)]
+    struct Inputs(Vec<u32>);
+
+    fn apply_input(mut frame: Local<usize>, inputs: Res<Inputs>, mut query: Query<&mut Counter>) {
+        for mut counter in query.iter_mut() {
+            counter.0 += inputs.0[*frame];
+        }
+        *frame += 1;
+    }
+
+    fn play(inputs: Vec<u32>, validator: Option<ReplayValidator>) -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Counter>();
+        app.add_systems(Update, apply_input);
+        let frames = inputs.len();
+        app.insert_resource(Inputs(inputs));
+        if let Some(validator) = validator {
+            app.insert_resource(validator);
+        }
+        app.world.spawn((Counter(0), TrackDesync));
+        for _ in 0..frames {
+            app.update();
+        }
+        app
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let inputs = vec![1, 2, 3, 4, 5, 6];
+        let recording = play(inputs.clone(), None);
+        let recorded = recording.world.resource::<CrcHistory>().iter().copied();
+
+        let replay = play(inputs, Some(ReplayValidator::new(recorded)));
+        let validator = replay.world.resource::<ReplayValidator>();
+        assert_eq!(validator.divergence(), None);
+        assert_eq!(validator.checked(), 6);
+        assert!(validator.is_complete());
+    }
+
+    #[test]
+    fn perturbed_replay_diverges() {
+        let inputs = vec![1, 2, 3, 4, 5, 6];
+        let recording = play(inputs.clone(), None);
+        let recorded = recording.world.resource::<CrcHistory>().iter().copied();
+
+        let mut perturbed = inputs;
+        perturbed[3] = 0;
+        let replay = play(perturbed, Some(ReplayValidator::new(recorded)));
+        let validator = replay.world.resource::<ReplayValidator>();
+        // the CRC of tick 4 is taken before frame 4's input is applied
+        let divergence = validator.divergence().unwrap();
+        assert_eq!(divergence.tick, 4);
+        assert_eq!(
+            recording.world.resource::<CrcHistory>().get(4),
+            Some(divergence.expected)
+        );
+        assert_ne!(divergence.report.crc, divergence.expected);
+        assert_eq!(divergence.report.entities[0].components[0].serialized, "6");
+        assert!(!validator.is_complete());
+    }
+}
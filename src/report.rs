@@ -0,0 +1,1060 @@
+use bevy_ecs::{
+    archetype::ArchetypeId, component::ComponentId, entity::Entity, system::Resource, world::World,
+};
+use bevy_utils::tracing::{debug, enabled, trace, trace_span, Level};
+use serde_json::Value;
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    algorithm::CrcDigest, checked, get_tracked_components, is_hashed, tracked_components_into,
+    unordered_tracked_entities, CombineStrategy, CrcAlgorithm, CrcScope, DesyncError,
+    DesyncPluginData, DesyncStatus, MISSING_ENTITY_SENTINEL,
+};
+
+/// Breakdown of the values that went into a world's CRC
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DesyncReport {
+    pub crc: u16,
+    /// The CRC at the full width of the configured [`crate::CrcAlgorithm`]. `crc` is its low 16
+    /// bits
+    pub full_crc: u64,
+    /// Tracked entities in hashing order
+    pub entities: Vec<EntityReport>,
+}
+
+/// Tracked components of a single entity, in hashing order
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityReport {
+    pub entity: Entity,
+    /// Hash of the sorted type names of the tracked components, hashed before them when the
+    /// plugin's `schema_prefix` option is set. Empty otherwise
+    pub schema: Vec<u8>,
+    pub components: Vec<ComponentReport>,
+    /// The plugin's `crc_algorithm`, which [`EntityReport::crc`] hashes with
+    pub algorithm: CrcAlgorithm,
+}
+
+impl DesyncReport {
+    /// Total number of bytes hashed for every component in the report
+    pub fn byte_count(&self) -> usize {
+        self.entities
+            .iter()
+            .flat_map(|entity| entity.components.iter())
+            .map(|component| component.hashed.len())
+            .sum()
+    }
+
+    /// Hash of every component on its own, by entity. Comparing the sub-checksums of two reports
+    /// whose CRCs differ narrows the desync down to the entity and component responsible
+    pub fn sub_checksums(&self) -> HashMap<Entity, HashMap<ComponentId, u64>> {
+        self.entities
+            .iter()
+            .map(|entity| {
+                let components = entity.components.iter().map(|c| (c.id, c.crc())).collect();
+                (entity.entity, components)
+            })
+            .collect()
+    }
+}
+
+impl EntityReport {
+    /// Hash of just this entity's tracked components, at the full width of `algorithm`
+    pub fn crc(&self) -> u64 {
+        let mut digest = self.algorithm.digest();
+        for bytes in self.hashed() {
+            digest.update(bytes);
+        }
+        digest.finalize()
+    }
+
+    /// Everything hashed for this entity, in order: the schema, then each component
+    pub(crate) fn hashed(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.schema.as_slice())
+            .chain(self.components.iter().map(|c| c.hashed.as_slice()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComponentReport {
+    pub id: ComponentId,
+    /// Human readable form of the component. This is the same as `hashed` unless the component
+    /// was registered with [`crate::AppDesyncExt::track_desync_with_serializers`]
+    pub serialized: String,
+    /// The serialized component, exactly as it was hashed
+    pub hashed: Vec<u8>,
+    /// The plugin's `crc_algorithm`, which [`ComponentReport::crc`] hashes with
+    pub algorithm: CrcAlgorithm,
+}
+
+impl ComponentReport {
+    /// Hash of just this component, at the full width of `algorithm`
+    pub fn crc(&self) -> u64 {
+        self.algorithm.checksum(&self.hashed)
+    }
+}
+
+/// Calculate the CRC of the world, keeping a record of every serialized component that was hashed.
+/// Fails like [`crate::calculate_crc`]
+pub fn calculate_crc_detailed(world: &World) -> Result<DesyncReport, DesyncError> {
+    checked(world, |world| {
+        calculate_crc_scoped(world, CrcScope::Shared, true)
+    })
+}
+
+/// Calculate the CRC, recording every component. Components are only serialized into their
+/// readable form if `readable` is set, otherwise `ComponentReport::serialized` is left empty
+pub(crate) fn calculate_crc_scoped(world: &World, scope: CrcScope, readable: bool) -> DesyncReport {
+    let combine = world.resource::<DesyncPluginData>().combine;
+    let mut entities = Vec::new();
+    let (status, full_crc) = hash_tracked(
+        world,
+        scope,
+        combine,
+        &|_, _| true,
+        None,
+        |entity, hasher| {
+            entities.push(hasher.report(world, entity, readable));
+        },
+    );
+    DesyncReport {
+        crc: status.crc,
+        full_crc,
+        entities,
+    }
+}
+
+/// Where the bytes of a concatenated CRC go
+enum ConcatInput<'a> {
+    /// Fed to the checksum as they come, rather than concatenated into one buffer
+    Digest(CrcDigest),
+    Collect(&'a mut Vec<u8>),
+}
+
+impl ConcatInput<'_> {
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            ConcatInput::Digest(digest) => digest.update(bytes),
+            ConcatInput::Collect(input) => input.extend_from_slice(bytes),
+        }
+    }
+}
+
+/// Hash the tracked state straight into the checksum, without keeping a report, returning the
+/// status with its tick left at zero, and the full width CRC. Only the sorted entities `filter`
+/// accepts are hashed, and `combine` may differ from the configured strategy
+pub(crate) fn calculate_status(
+    world: &World,
+    scope: CrcScope,
+    combine: CombineStrategy,
+    filter: &dyn Fn(Entity, &World) -> bool,
+) -> (DesyncStatus, u64) {
+    hash_tracked(world, scope, combine, filter, None, |_, _| {})
+}
+
+/// The concatenated bytes [`calculate_status`] would checksum, collected into `input`
+pub(crate) fn collect_input(world: &World, input: &mut Vec<u8>) {
+    let combine = CombineStrategy::Concatenate;
+    hash_tracked(
+        world,
+        CrcScope::Shared,
+        combine,
+        &|_, _| true,
+        Some(input),
+        |_, _| {},
+    );
+}
+
+/// Hash the sorted entities `filter` accepts, then the tracked resources and events. `visit` is
+/// called with each entity once it's serialized. With `input`, the concatenated bytes are
+/// collected there instead of being checksummed, and the CRC is left at zero
+fn hash_tracked(
+    world: &World,
+    scope: CrcScope,
+    combine: CombineStrategy,
+    filter: &dyn Fn(Entity, &World) -> bool,
+    input: Option<&mut Vec<u8>>,
+    mut visit: impl FnMut(Entity, &EntityHasher),
+) -> (DesyncStatus, u64) {
+    let mut status = DesyncStatus::default();
+    let desync_data = world.resource::<DesyncPluginData>();
+    let algorithm = desync_data.crc_algorithm;
+    debug_assert!(input.is_none() || combine == CombineStrategy::Concatenate);
+    let mut digest = match input {
+        Some(input) => ConcatInput::Collect(input),
+        None => ConcatInput::Digest(algorithm.digest()),
+    };
+    let mut combined = 0u64;
+    let mut entities = if combine.is_commutative() {
+        // order doesn't matter, so don't pay for the sort
+        unordered_tracked_entities(world)
+    } else {
+        (desync_data.entity_sort)(world)
+    };
+    entities.retain(|entity| filter(*entity, world));
+    let entities = and_descendants(world, entities);
+    let _span = trace_span!("calculate_crc", ?scope).entered();
+    #[cfg(feature = "parallel")]
+    let mut hashers = hash_parallel(world, &entities, scope).into_iter();
+    #[cfg(not(feature = "parallel"))]
+    let mut hasher = EntityHasher::default();
+    for entity in entities.iter() {
+        #[cfg(feature = "parallel")]
+        let hashed = hashers.next().unwrap();
+        if world.get_entity(*entity).is_none() {
+            // e.g. despawned between sorting and hashing
+            debug!("{entity:?} returned by entity_sort doesn't exist");
+            if desync_data
+                .missing_entities
+                .missing(*entity, &desync_data.errors)
+                .is_some()
+            {
+                match combine {
+                    CombineStrategy::Concatenate => {
+                        digest.update(MISSING_ENTITY_SENTINEL.as_bytes())
+                    }
+                    CombineStrategy::Xor | CombineStrategy::Sum => {
+                        combined = combine.fold(
+                            combined,
+                            algorithm.checksum(MISSING_ENTITY_SENTINEL.as_bytes()),
+                        )
+                    }
+                }
+            }
+            continue;
+        }
+        #[cfg(feature = "parallel")]
+        let Some(hasher) = &hashed
+        else {
+            continue;
+        };
+        #[cfg(not(feature = "parallel"))]
+        let hasher = {
+            if !is_hashed(*entity, world) {
+                continue;
+            }
+            hasher.hash(world, *entity, scope);
+            &hasher
+        };
+        if enabled!(Level::TRACE) {
+            hasher.trace(world, *entity);
+        }
+        match combine {
+            CombineStrategy::Concatenate => digest.update(&hasher.bytes),
+            CombineStrategy::Xor | CombineStrategy::Sum => {
+                combined = combine.fold(combined, hasher.crc_with(algorithm))
+            }
+        }
+        status.entity_count += 1;
+        status.byte_count += hasher.byte_count() as u32;
+        visit(*entity, hasher);
+    }
+
+    let global_input = desync_data.serialize_global_input(world);
+    if !global_input.is_empty() {
+        match combine {
+            CombineStrategy::Concatenate => digest.update(&global_input),
+            CombineStrategy::Xor | CombineStrategy::Sum => {
+                combined = combine.fold(combined, algorithm.checksum(&global_input))
+            }
+        }
+    }
+
+    let full_crc = match (combine, digest) {
+        (CombineStrategy::Concatenate, ConcatInput::Digest(digest)) => digest.finalize(),
+        (CombineStrategy::Concatenate, ConcatInput::Collect(_)) => return (status, 0),
+        (CombineStrategy::Xor | CombineStrategy::Sum, _) => algorithm.truncate(combined),
+    };
+    status.crc = full_crc as u16;
+    trace!(crc = status.crc, full_crc, "calculated CRC");
+    (status, full_crc)
+}
+
+/// `entities`, followed by their descendants under the plugin's `include_hierarchy` option
+fn and_descendants(world: &World, entities: Vec<Entity>) -> Vec<Entity> {
+    match world.resource::<DesyncPluginData>().includes_hierarchy() {
+        #[cfg(feature = "hierarchy")]
+        true => crate::hierarchy::with_descendants(world, entities),
+        _ => entities,
+    }
+}
+
+/// Serialize each of `entities` which is hashed across threads, `None` for the rest. Each entity
+/// gets its own buffer, so they can be serialized in parallel and hashed in order
+#[cfg(feature = "parallel")]
+fn hash_parallel(world: &World, entities: &[Entity], scope: CrcScope) -> Vec<Option<EntityHasher>> {
+    use rayon::prelude::*;
+    entities
+        .par_iter()
+        .map(|entity| {
+            is_hashed(*entity, world).then(|| {
+                let mut hasher = EntityHasher::default();
+                hasher.hash(world, *entity, scope);
+                hasher
+            })
+        })
+        .collect()
+}
+
+/// Serializes an entity's tracked components into a buffer which is reused from one entity to the
+/// next, so hashing doesn't allocate for each component
+#[derive(Default)]
+pub(crate) struct EntityHasher {
+    /// Everything hashed for the entity, in order: the schema, then each component
+    pub(crate) bytes: Vec<u8>,
+    /// Tracked components in hashing order
+    pub(crate) components: Vec<ComponentId>,
+    /// Where each of `components` is in `bytes`
+    spans: Vec<Range<usize>>,
+    /// The schema prefix of each archetype seen so far, as every entity in it tracks the same
+    /// components
+    schemas: HashMap<(ArchetypeId, CrcScope), Vec<u8>>,
+}
+
+impl EntityHasher {
+    /// Serialize the tracked components of `entity`, replacing the last entity's
+    pub(crate) fn hash(&mut self, world: &World, entity: Entity, scope: CrcScope) {
+        let desync_data = world.resource::<DesyncPluginData>();
+        self.bytes.clear();
+        self.spans.clear();
+        tracked_components_into(entity, world, scope, &mut self.components);
+        if desync_data.schema_prefix {
+            let archetype = world.entity(entity).archetype().id();
+            let schema = self.schemas.entry((archetype, scope)).or_insert_with(|| {
+                let algorithm = desync_data.crc_algorithm;
+                let mut digest = algorithm.digest();
+                // already sorted by name
+                for id in self.components.iter() {
+                    // type names never contain NUL, so this terminates the name unambiguously
+                    digest.update(component_name(world, id).as_bytes());
+                    digest.update(&[0]);
+                }
+                let width = algorithm.width() as usize / 8;
+                digest.finalize().to_le_bytes()[..width].to_vec()
+            });
+            self.bytes.extend_from_slice(schema);
+        }
+        for id in self.components.iter() {
+            let ptr = world.get_by_id(entity, *id).unwrap();
+            let start = self.bytes.len();
+            desync_data.serialize_into(world, ptr, id, &mut self.bytes);
+            self.spans.push(start..self.bytes.len());
+        }
+    }
+
+    /// The schema prefix, empty unless the plugin's `schema_prefix` option is set
+    fn schema(&self) -> &[u8] {
+        let end = self
+            .spans
+            .first()
+            .map_or(self.bytes.len(), |span| span.start);
+        &self.bytes[..end]
+    }
+
+    /// Each tracked component and its serialized bytes, in hashing order
+    pub(crate) fn components(&self) -> impl Iterator<Item = (ComponentId, &[u8])> {
+        self.components
+            .iter()
+            .zip(self.spans.iter())
+            .map(|(id, span)| (*id, &self.bytes[span.clone()]))
+    }
+
+    /// Length of the serialized components, leaving out the schema
+    pub(crate) fn byte_count(&self) -> usize {
+        self.bytes.len() - self.schema().len()
+    }
+
+    /// Hash of just this entity's tracked components under `algorithm`
+    pub(crate) fn crc_with(&self, algorithm: CrcAlgorithm) -> u64 {
+        algorithm.checksum(&self.bytes)
+    }
+
+    /// Copy what was serialized into a report, serializing each component into its readable form
+    /// too if `readable` is set
+    fn report(&self, world: &World, entity: Entity, readable: bool) -> EntityReport {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let algorithm = desync_data.crc_algorithm;
+        EntityReport {
+            entity,
+            schema: self.schema().to_vec(),
+            components: self
+                .components()
+                .map(|(id, hashed)| ComponentReport {
+                    id,
+                    serialized: match readable {
+                        true => {
+                            let ptr = world.get_by_id(entity, id).unwrap();
+                            desync_data.serialize_readable(world, ptr, &id)
+                        }
+                        false => String::new(),
+                    },
+                    hashed: hashed.to_vec(),
+                    algorithm,
+                })
+                .collect(),
+            algorithm,
+        }
+    }
+
+    /// Log what went into the hash for the entity, in hashing order. Only reads what was
+    /// serialized, so the bytes hashed are the same whether or not tracing is enabled
+    pub(crate) fn trace(&self, world: &World, entity: Entity) {
+        trace!(?entity, "hashing entity");
+        for (id, hashed) in self.components() {
+            trace!(
+                ?entity,
+                component = ?id,
+                name = component_name(world, &id),
+                value = %String::from_utf8_lossy(hashed),
+            );
+        }
+    }
+}
+
+/// Serialize the tracked components of a single entity
+pub(crate) fn entity_report(
+    world: &World,
+    entity: Entity,
+    scope: CrcScope,
+    readable: bool,
+) -> EntityReport {
+    let mut hasher = EntityHasher::default();
+    hasher.hash(world, entity, scope);
+    hasher.report(world, entity, readable)
+}
+
+/// Every component that went into the last CRC, recorded by `update_crc` when the plugin's
+/// `audit` option is set. Insert or remove this resource to toggle auditing at runtime.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct CrcAudit {
+    /// One entry per tracked component per tracked entity, in hashing order
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub entity: Entity,
+    pub type_name: String,
+    pub serialized: String,
+}
+
+impl CrcAudit {
+    pub fn from_report(report: &DesyncReport, world: &World) -> Self {
+        let entries = report
+            .entities
+            .iter()
+            .flat_map(|entity| {
+                entity.components.iter().map(|component| AuditEntry {
+                    entity: entity.entity,
+                    type_name: component_name(world, &component.id).to_string(),
+                    serialized: component.serialized.clone(),
+                })
+            })
+            .collect();
+        CrcAudit { entries }
+    }
+
+    /// Entries recorded for a single entity
+    pub fn entity(&self, entity: Entity) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter().filter(move |e| e.entity == entity)
+    }
+}
+
+/// A difference between two worlds found by [`diff_worlds`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum DesyncEntry {
+    /// A tracked component which differs between the entities lined up with each other
+    Component(ComponentDiff),
+    /// A tracked entity with no counterpart in the other world
+    Missing {
+        /// The entity, as seen in the world which has it
+        entity: Entity,
+        /// The world which has it
+        side: DiffSide,
+    },
+}
+
+/// One of the two worlds passed to [`diff_worlds`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffSide {
+    A,
+    B,
+}
+
+impl DesyncEntry {
+    /// The differing component, unless this is a missing entity
+    pub fn component(&self) -> Option<&ComponentDiff> {
+        match self {
+            DesyncEntry::Component(diff) => Some(diff),
+            DesyncEntry::Missing { .. } => None,
+        }
+    }
+}
+
+/// A tracked component which differs between two worlds
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComponentDiff {
+    /// The entity, as seen in the first world
+    pub entity: Entity,
+    /// The component, as registered in the first world
+    pub component: ComponentId,
+    /// Serialized value in the first world, or `None` if the entity doesn't have the component
+    pub a: Option<String>,
+    /// Serialized value in the second world, or `None` if the entity doesn't have the component
+    pub b: Option<String>,
+    /// JSON pointers to the fields which differ, see [`json_field_diff`]. Empty if either side is
+    /// missing the component
+    pub fields: Vec<String>,
+}
+
+impl DesyncEntry {
+    fn new(entity: Entity, component: ComponentId, a: Option<String>, b: Option<String>) -> Self {
+        let fields = match (&a, &b) {
+            (Some(a), Some(b)) => json_field_diff(a, b),
+            _ => Vec::new(),
+        };
+        DesyncEntry::Component(ComponentDiff {
+            entity,
+            component,
+            a,
+            b,
+            fields,
+        })
+    }
+}
+
+/// Compare two serialized components field by field, returning a [JSON pointer] to each value
+/// which differs, e.g. `/transform/translation/0`. A field present on only one side is reported
+/// at its own path. If either string isn't valid JSON, the whole value (`""`) is reported as
+/// differing if the strings aren't equal.
+///
+/// [JSON pointer]: https://datatracker.ietf.org/doc/html/rfc6901
+pub fn json_field_diff(a: &str, b: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    match (
+        serde_json::from_str::<Value>(a),
+        serde_json::from_str::<Value>(b),
+    ) {
+        (Ok(a), Ok(b)) => diff_values(&a, &b, String::new(), &mut paths),
+        _ if a != b => paths.push(String::new()),
+        _ => {}
+    }
+    paths
+}
+
+fn diff_values(a: &Value, b: &Value, path: String, paths: &mut Vec<String>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys = a.keys().chain(b.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff_values(a, b, path, paths),
+                    _ => paths.push(path),
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let path = format!("{path}/{i}");
+                match (a.get(i), b.get(i)) {
+                    (Some(a), Some(b)) => diff_values(a, b, path, paths),
+                    _ => paths.push(path),
+                }
+            }
+        }
+        (a, b) if a != b => paths.push(path),
+        _ => {}
+    }
+}
+
+/// Compare the tracked components of two worlds, lining entities up using each world's
+/// `entity_sort`. Components are matched by name, and compared with the equality registered with
+/// [`crate::AppDesyncExt::track_desync_with_eq`] if there is one, or by their serialized form
+/// otherwise.
+///
+/// If one world has more tracked entities than the other, the entities left over once the other
+/// runs out are reported as [`DesyncEntry::Missing`], after every component difference. Entities
+/// kept as placeholders by [`crate::MissingEntityPolicy::Sentinel`] are reported as missing every
+/// component the other side has.
+///
+/// Fails if a tracked value in either world doesn't serialize
+pub fn diff_worlds(a: &World, b: &World) -> Result<Vec<DesyncEntry>, DesyncError> {
+    checked(a, |a| checked(b, |b| diff_unchecked(a, b)))?
+}
+
+fn diff_unchecked(a: &World, b: &World) -> Vec<DesyncEntry> {
+    let data_a = a.resource::<DesyncPluginData>();
+    let data_b = b.resource::<DesyncPluginData>();
+    let entities_a = tracked_entities(a);
+    let entities_b = tracked_entities(b);
+
+    let mut entries = Vec::new();
+    let missing = match entities_a.len() >= entities_b.len() {
+        true => (DiffSide::A, entities_a[entities_b.len()..].to_vec()),
+        false => (DiffSide::B, entities_b[entities_a.len()..].to_vec()),
+    };
+    for (entity_a, entity_b) in entities_a.into_iter().zip(entities_b) {
+        let components_a = tracked_components(entity_a, a);
+        let components_b = tracked_components(entity_b, b);
+        for c_a in components_a.iter() {
+            let ptr_a = a.get_by_id(entity_a, *c_a).unwrap();
+            let c_b = components_b
+                .iter()
+                .find(|c_b| component_name(b, c_b) == component_name(a, c_a));
+            match c_b {
+                Some(c_b) => {
+                    let ptr_b = b.get_by_id(entity_b, *c_b).unwrap();
+                    // components with the same name are the same type, so world a's equality
+                    // can compare them
+                    if !data_a.values_eq((a, ptr_a), (b, ptr_b), c_a) {
+                        entries.push(DesyncEntry::new(
+                            entity_a,
+                            *c_a,
+                            Some(data_a.serialize_readable(a, ptr_a, c_a)),
+                            Some(data_b.serialize_readable(b, ptr_b, c_b)),
+                        ));
+                    }
+                }
+                None => entries.push(DesyncEntry::new(
+                    entity_a,
+                    *c_a,
+                    Some(data_a.serialize_readable(a, ptr_a, c_a)),
+                    None,
+                )),
+            }
+        }
+        for c_b in components_b.iter() {
+            let name = component_name(b, c_b);
+            if components_a
+                .iter()
+                .any(|c_a| component_name(a, c_a) == name)
+            {
+                continue;
+            }
+            // the component may not be registered in world a at all, so use world a's id if it
+            // has one
+            let Some(component) = a.components().iter().find(|info| info.name() == name) else {
+                continue;
+            };
+            let ptr_b = b.get_by_id(entity_b, *c_b).unwrap();
+            entries.push(DesyncEntry::new(
+                entity_a,
+                component.id(),
+                None,
+                Some(data_b.serialize_readable(b, ptr_b, c_b)),
+            ));
+        }
+    }
+    let (side, missing) = missing;
+    entries.extend(
+        missing
+            .into_iter()
+            .map(|entity| DesyncEntry::Missing { entity, side }),
+    );
+    entries
+}
+
+fn tracked_entities(world: &World) -> Vec<Entity> {
+    let desync_data = world.resource::<DesyncPluginData>();
+    and_descendants(world, (desync_data.entity_sort)(world))
+        .into_iter()
+        .filter(|e| match world.get_entity(*e) {
+            Some(_) => is_hashed(*e, world),
+            None => desync_data
+                .missing_entities
+                .missing(*e, &desync_data.errors)
+                .is_some(),
+        })
+        .collect()
+}
+
+fn tracked_components(entity: Entity, world: &World) -> Vec<ComponentId> {
+    match world.get_entity(entity) {
+        Some(_) => get_tracked_components(entity, world),
+        None => Vec::new(),
+    }
+}
+
+pub(crate) fn component_name<'w>(world: &'w World, id: &ComponentId) -> &'w str {
+    world.components().get_info(*id).unwrap().name()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use bevy_utils::tracing::{span, subscriber, Event, Metadata, Subscriber};
+    use serde::Serialize;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    /// Serializes the raw bits of the float, so different NaNs have different serializations
+    #[derive(Component)]
+    struct Bits(f32);
+
+    impl Serialize for Bits {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u32(self.0.to_bits())
+        }
+    }
+
+    #[derive(Component, Serialize)]
+    struct Name(&'static str);
+
+    fn nan_eq(a: &Bits, b: &Bits) -> bool {
+        (a.0.is_nan() && b.0.is_nan()) || a.0 == b.0
+    }
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_with_eq::<Bits>(nan_eq);
+        app
+    }
+
+    #[test]
+    fn diff_reports_serialized_differences() {
+        let mut app_1 = App::new();
+        let mut app_2 = App::new();
+        for app in [&mut app_1, &mut app_2] {
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync::<Bits>();
+        }
+        let entity = app_1.world.spawn((Bits(f32::NAN), TrackDesync)).id();
+        app_2
+            .world
+            .spawn((Bits(f32::from_bits(f32::NAN.to_bits() + 1)), TrackDesync));
+
+        let diff = diff_worlds(&app_1.world, &app_2.world).unwrap();
+        assert_eq!(diff.len(), 1);
+        let diff = diff[0].component().unwrap();
+        assert_eq!(diff.entity, entity);
+        assert_ne!(diff.a, diff.b);
+    }
+
+    #[test]
+    fn diff_reports_missing_entities() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1.world.spawn((Bits(1.0), TrackDesync));
+        app_2.world.spawn((Bits(2.0), TrackDesync));
+        let extra = app_2.world.spawn((Bits(3.0), TrackDesync)).id();
+
+        let diff = diff_worlds(&app_1.world, &app_2.world).unwrap();
+        assert_eq!(diff.len(), 2);
+        assert!(diff[0].component().is_some());
+        assert_eq!(
+            diff[1],
+            DesyncEntry::Missing {
+                entity: extra,
+                side: DiffSide::B
+            }
+        );
+        let diff = diff_worlds(&app_2.world, &app_1.world).unwrap();
+        assert_eq!(diff[1].component(), None);
+        assert!(matches!(
+            diff[1],
+            DesyncEntry::Missing {
+                side: DiffSide::A,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn comparator_refines_diff_but_not_crc() {
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        app_1.world.spawn((Bits(f32::NAN), TrackDesync));
+        app_2
+            .world
+            .spawn((Bits(f32::from_bits(f32::NAN.to_bits() + 1)), TrackDesync));
+        app_1.world.spawn((Bits(1.0), TrackDesync));
+        app_2.world.spawn((Bits(2.0), TrackDesync));
+
+        app_1.update();
+        app_2.update();
+        assert_ne!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+
+        // only the non-NaN pair is reported
+        let diff = diff_worlds(&app_1.world, &app_2.world).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(
+            diff[0].component().unwrap().a.as_deref(),
+            Some(1.0f32.to_bits().to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn detailed_report_matches_crc() {
+        let mut app = build_app();
+        let entity = app.world.spawn((Bits(1.0), TrackDesync)).id();
+        app.update();
+
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        assert_eq!(Crc(report.crc), *app.world.resource::<Crc>());
+        assert_eq!(report.entities.len(), 1);
+        assert_eq!(report.entities[0].entity, entity);
+        assert_eq!(
+            report.entities[0].components[0].serialized,
+            1.0f32.to_bits().to_string()
+        );
+    }
+
+    #[test]
+    fn report_crcs_use_configured_algorithm() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            crc_algorithm: CrcAlgorithm::Crc64Xz,
+            ..Default::default()
+        })
+        .track_desync::<Bits>();
+        app.world.spawn((Bits(1.0), TrackDesync));
+
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        let entity = &report.entities[0];
+        let component = &entity.components[0];
+        assert_eq!(
+            entity.crc(),
+            CrcAlgorithm::Crc64Xz.checksum(&component.hashed)
+        );
+        assert_eq!(component.crc(), entity.crc());
+        assert_eq!(entity.crc(), report.full_crc);
+    }
+
+    /// Counts the events logged
+    struct CountEvents(Arc<AtomicUsize>);
+
+    impl Subscriber for CountEvents {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, _: &Event) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn tracing_leaves_crc_unchanged() {
+        let mut app = build_app();
+        app.track_desync::<Name>();
+        app.world.spawn((Bits(1.0), Name("a"), TrackDesync));
+        app.world.spawn((Bits(2.0), TrackDesync));
+        let crc = crate::calculate_crc(&app.world).unwrap();
+
+        let events = Arc::new(AtomicUsize::new(0));
+        let traced = subscriber::with_default(CountEvents(events.clone()), || {
+            crate::calculate_crc(&app.world).unwrap()
+        });
+        assert_eq!(traced, crc);
+        // each entity, each of their components, and the CRC
+        assert_eq!(events.load(Ordering::Relaxed), 2 + 3 + 1);
+    }
+
+    #[test]
+    fn many_entities_hash_in_order() {
+        let mut app = build_app();
+        let crc_algo = crc::Crc::<u16>::new(&crc::CRC_16_IBM_SDLC);
+        let mut digest = crc_algo.digest();
+        for i in 0..2000 {
+            app.world.spawn((Bits(i as f32), TrackDesync));
+            digest.update((i as f32).to_bits().to_string().as_bytes());
+        }
+        // the same with the parallel feature
+        assert_eq!(crate::calculate_crc(&app.world).unwrap(), digest.finalize());
+    }
+
+    #[test]
+    fn sub_checksums_locate_change() {
+        let mut app = build_app();
+        app.track_desync::<Name>();
+        // reverse of the default order, to check the report follows entity_sort
+        app.world.resource_mut::<DesyncPluginData>().entity_sort = Arc::new(Box::new(|world| {
+            let mut entities = crate::sort_entities_ids(world);
+            entities.reverse();
+            entities
+        }));
+        let a = app.world.spawn((Bits(1.0), Name("a"), TrackDesync)).id();
+        let b = app.world.spawn((Bits(2.0), Name("b"), TrackDesync)).id();
+        let before = calculate_crc_detailed(&app.world).unwrap();
+        assert_eq!(before.crc, crate::calculate_crc(&app.world).unwrap());
+        assert_eq!(
+            before.entities.iter().map(|e| e.entity).collect::<Vec<_>>(),
+            vec![b, a]
+        );
+
+        app.world.get_mut::<Name>(b).unwrap().0 = "c";
+        let after = calculate_crc_detailed(&app.world).unwrap();
+        assert_ne!(before.crc, after.crc);
+        let (before, after) = (before.sub_checksums(), after.sub_checksums());
+        let name = app.world.component_id::<Name>().unwrap();
+        let bits = app.world.component_id::<Bits>().unwrap();
+        assert_eq!(before[&a], after[&a]);
+        assert_eq!(before[&b][&bits], after[&b][&bits]);
+        assert_ne!(before[&b][&name], after[&b][&name]);
+    }
+
+    #[derive(Component, Serialize)]
+    struct Marker;
+
+    #[test]
+    fn schema_prefix_catches_extra_component() {
+        let crc = |schema_prefix, marked| {
+            let mut app = App::new();
+            app.add_plugins(DesyncPlugin {
+                schema_prefix,
+                ..Default::default()
+            })
+            .track_desync::<Name>();
+            // hashes as nothing, so only the schema can tell it's there
+            app.track_desync_transformed::<Marker>(|_| String::new());
+            let entity = app.world.spawn((Name("a"), TrackDesync)).id();
+            if marked {
+                app.world.entity_mut(entity).insert(Marker);
+            }
+            crate::calculate_crc(&app.world).unwrap()
+        };
+        assert_eq!(crc(false, false), crc(false, true));
+        assert_ne!(crc(true, false), crc(true, true));
+        assert_eq!(crc(true, true), crc(true, true));
+    }
+
+    #[derive(Serialize)]
+    struct Inner {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Component, Serialize)]
+    struct Outer {
+        name: &'static str,
+        inner: Inner,
+        list: Vec<u8>,
+    }
+
+    #[test]
+    fn field_diff_finds_nested_field() {
+        let a = serde_json::to_string(&Outer {
+            name: "a",
+            inner: Inner { x: 0.0, y: 1.0 },
+            list: vec![1, 2],
+        })
+        .unwrap();
+        let b = serde_json::to_string(&Outer {
+            name: "a",
+            inner: Inner { x: 0.0, y: 2.0 },
+            list: vec![1, 2, 3],
+        })
+        .unwrap();
+        assert_eq!(json_field_diff(&a, &b), vec!["/inner/y", "/list/2"]);
+        assert!(json_field_diff(&a, &a).is_empty());
+        assert_eq!(json_field_diff("1", "not json"), vec![""]);
+    }
+
+    #[test]
+    fn diff_reports_fields() {
+        let mut app_1 = App::new();
+        let mut app_2 = App::new();
+        for app in [&mut app_1, &mut app_2] {
+            app.add_plugins(DesyncPlugin::default())
+                .track_desync::<Outer>();
+        }
+        app_1.world.spawn((
+            Outer {
+                name: "a",
+                inner: Inner { x: 0.0, y: 1.0 },
+                list: vec![],
+            },
+            TrackDesync,
+        ));
+        app_2.world.spawn((
+            Outer {
+                name: "a",
+                inner: Inner { x: 3.0, y: 1.0 },
+                list: vec![],
+            },
+            TrackDesync,
+        ));
+
+        let diff = diff_worlds(&app_1.world, &app_2.world).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].component().unwrap().fields, vec!["/inner/x"]);
+    }
+
+    #[derive(Component, Serialize)]
+    struct Foo(u8);
+
+    #[test]
+    fn audit_records_every_component() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            audit: true,
+            ..Default::default()
+        })
+        .track_desync::<Foo>();
+        app.track_desync::<Bits>();
+        let both = app.world.spawn((Foo(1), Bits(0.0), TrackDesync)).id();
+        let foo = app.world.spawn((Foo(2), TrackDesync)).id();
+        // not tracked
+        app.world.spawn(Foo(3));
+        app.update();
+
+        let audit = app.world.resource::<CrcAudit>();
+        assert_eq!(audit.entries.len(), 3);
+        assert_eq!(audit.entity(both).count(), 2);
+        let entries = audit.entity(foo).collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].serialized, "2");
+        assert!(entries[0].type_name.ends_with("Foo"));
+
+        // cleared each tick
+        app.world.despawn(both);
+        app.update();
+        assert_eq!(app.world.resource::<CrcAudit>().entries.len(), 1);
+    }
+
+    #[derive(Component)]
+    struct Raw(u32);
+
+    #[test]
+    fn separate_hash_and_readable_serializers() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_with_serializers::<Raw>(
+                |raw| raw.0.to_le_bytes().to_vec(),
+                |raw| format!("Raw({})", raw.0),
+            );
+        app.world.spawn((Raw(7), TrackDesync));
+        app.update();
+
+        let crc_algo = crc::Crc::<u16>::new(&crc::CRC_16_IBM_SDLC);
+        assert_eq!(
+            app.world.resource::<Crc>().0,
+            crc_algo.checksum(&7u32.to_le_bytes())
+        );
+        let report = calculate_crc_detailed(&app.world).unwrap();
+        let component = &report.entities[0].components[0];
+        assert_eq!(component.serialized, "Raw(7)");
+        assert_eq!(component.hashed, 7u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,185 @@
+//! Checksums for rollback netcode such as ggrs, which compares a checksum of each confirmed frame
+//! between peers. Under rollback a frame can be simulated several times: first on predicted
+//! inputs, then again once the real inputs arrive. Only the last simulation of a frame at or before
+//! the session's confirmed frame is the confirmed state, which is what [`RollbackChecksums`]
+//! hands out.
+//!
+//! With bevy_ggrs, the `ggrs` feature's `GgrsChecksumPlugin` sets the confirmed frame and hands
+//! each frame's checksum to ggrs. Without it, hash each frame as it's simulated in the rollback
+//! schedule, tagged with the rollback frame count, and set the confirmed frame yourself:
+//! ```rust,ignore
+//! app.add_plugins(DesyncPlugin {
+//!     schedule: Some(GgrsSchedule.intern()),
+//!     tick_source: Some(Arc::new(|world| world.resource::<RollbackFrameCount>().0 as u64)),
+//!     rollback_checksums: Some(32),
+//!     ..Default::default()
+//! })
+//! // hash the frame once the game has simulated it
+//! .configure_sets(GgrsSchedule, DesyncSet::CalculateCrc.after(GameplaySet))
+//! .add_systems(Update, confirm_frames);
+//!
+//! fn confirm_frames(session: Res<Session<Config>>, mut checksums: ResMut<RollbackChecksums>) {
+//!     if let Session::P2P(session) = &*session {
+//!         checksums.set_confirmed_frame(session.confirmed_frame());
+//!     }
+//! }
+//! ```
+//! with the session built as usual, e.g. `SessionBuilder::<Config>::new()
+//! .with_desync_detection_mode(DesyncDetection::On { interval: 10 })`, and
+//! [`RollbackChecksums::confirmed`] handed to ggrs as the saved frame's checksum.
+
+use bevy_ecs::{system::Resource, world::World};
+use std::collections::VecDeque;
+
+/// Frame number as ggrs counts them
+pub type RollbackFrame = i32;
+
+/// Checksum of a frame, in the shape ggrs takes it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RollbackChecksum {
+    pub frame: RollbackFrame,
+    /// [`crate::FullCrc`] of the frame
+    pub checksum: u128,
+}
+
+/// Full width CRC of the most recently simulated rollback frames, added by the plugin's
+/// `rollback_checksums` option. Frames are the ticks CRCs are tagged with, so set the plugin's
+/// `tick_source` to the session's current frame. Simulating a frame again after a rollback
+/// replaces its checksum, and drops those of the frames after it, which will be simulated again
+/// too
+#[derive(Clone, Debug, Resource)]
+pub struct RollbackChecksums {
+    /// Sorted by frame
+    entries: VecDeque<RollbackChecksum>,
+    capacity: usize,
+    confirmed: Option<RollbackFrame>,
+}
+
+impl RollbackChecksums {
+    pub fn new(capacity: usize) -> Self {
+        RollbackChecksums {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            confirmed: None,
+        }
+    }
+
+    /// Set the latest frame the session has confirmed, i.e. every peer's inputs up to it have
+    /// arrived, as in ggrs's `confirmed_frame`
+    pub fn set_confirmed_frame(&mut self, frame: RollbackFrame) {
+        self.confirmed = Some(frame);
+    }
+
+    pub fn confirmed_frame(&self) -> Option<RollbackFrame> {
+        self.confirmed
+    }
+
+    /// The checksum of `frame`, if it has been confirmed and is still kept. A confirmed frame
+    /// can't be rolled back any more, so its last CRC was calculated on the confirmed state
+    pub fn get(&self, frame: RollbackFrame) -> Option<RollbackChecksum> {
+        if self.confirmed.is_none_or(|confirmed| frame > confirmed) {
+            return None;
+        }
+        self.simulated(frame)
+    }
+
+    /// The checksum from the last simulation of `frame`, confirmed or not
+    pub(crate) fn simulated(&self, frame: RollbackFrame) -> Option<RollbackChecksum> {
+        self.entries.iter().find(|e| e.frame == frame).copied()
+    }
+
+    /// The checksum of the latest confirmed frame
+    pub fn confirmed(&self) -> Option<RollbackChecksum> {
+        self.get(self.confirmed?)
+    }
+
+    fn record(&mut self, frame: RollbackFrame, checksum: u128) {
+        if self.capacity == 0 {
+            return;
+        }
+        // simulated again after a rollback, so the later frames will be too
+        while self.entries.back().is_some_and(|e| e.frame >= frame) {
+            self.entries.pop_back();
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(RollbackChecksum { frame, checksum });
+    }
+}
+
+/// Record the CRC just calculated for `tick`
+pub(crate) fn record_rollback_checksum(world: &mut World, tick: u64, full_crc: u64) {
+    let Ok(frame) = RollbackFrame::try_from(tick) else {
+        return;
+    };
+    world
+        .resource_mut::<RollbackChecksums>()
+        .record(frame, full_crc as u128);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, FullCrc, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Position(i32);
+
+    #[derive(Resource)]
+    struct Frame(RollbackFrame);
+
+    /// Simulate `frame`, with the entity at `position`
+    fn simulate(app: &mut App, frame: RollbackFrame, position: i32) -> u128 {
+        app.world.resource_mut::<Frame>().0 = frame;
+        let mut query = app.world.query::<&mut Position>();
+        query.single_mut(&mut app.world).0 = position;
+        app.update();
+        app.world.resource::<FullCrc>().0 as u128
+    }
+
+    #[test]
+    fn rolled_back_frames_recorded_again() {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin {
+            tick_source: Some(Arc::new(|world| world.resource::<Frame>().0 as u64)),
+            rollback_checksums: Some(8),
+            ..Default::default()
+        })
+        .track_desync::<Position>();
+        app.insert_resource(Frame(0));
+        app.world.spawn((Position(0), TrackDesync));
+
+        // predicted
+        for frame in 0..5 {
+            simulate(&mut app, frame, frame);
+        }
+        let checksums = app.world.resource::<RollbackChecksums>();
+        assert_eq!(checksums.confirmed(), None);
+        assert_eq!(checksums.get(1), None);
+
+        // the inputs for frame 2 onwards were mispredicted
+        let mut confirmed = Vec::new();
+        for frame in 2..4 {
+            confirmed.push(simulate(&mut app, frame, -frame));
+        }
+        let mut checksums = app.world.resource_mut::<RollbackChecksums>();
+        checksums.set_confirmed_frame(3);
+        assert_eq!(
+            checksums.confirmed(),
+            Some(RollbackChecksum {
+                frame: 3,
+                checksum: confirmed[1],
+            })
+        );
+        assert_eq!(checksums.get(2).unwrap().checksum, confirmed[0]);
+        // the mispredicted frame 4 was dropped
+        checksums.set_confirmed_frame(4);
+        assert_eq!(checksums.confirmed(), None);
+    }
+}
@@ -0,0 +1,265 @@
+use bevy_ecs::{component::ComponentId, entity::Entity, world::World};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{
+    checked,
+    report::{calculate_crc_scoped, entity_report},
+    CrcScope, DesyncError, DesyncPluginData, TrackDesync,
+};
+
+/// The tracked components of a world, saved as JSON so they can be loaded into another world.
+/// Every tracked component must have been registered with
+/// [`crate::AppDesyncExt::track_desync_saveable`].
+///
+/// Entity references inside components aren't remapped, and tracked events aren't saved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackedSnapshot {
+    /// `(type_name, json)` for each tracked component of each tracked entity, in hashing order
+    pub entities: Vec<Vec<(String, String)>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SaveLoadError {
+    /// A tracked component failed to serialize, either to be hashed or to be saved
+    Serialize(DesyncError),
+    /// The component wasn't registered with [`crate::AppDesyncExt::track_desync_saveable`]
+    NotSaveable(String),
+    /// Loading the component from its JSON failed
+    Load { component: String, error: String },
+    /// The CRC of the tracked components changed across the save/load cycle
+    Mismatch { before: u64, after: u64 },
+}
+
+impl fmt::Display for SaveLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveLoadError::Serialize(error) => write!(f, "{error}"),
+            SaveLoadError::NotSaveable(component) => {
+                write!(f, "{component} isn't registered as saveable")
+            }
+            SaveLoadError::Load { component, error } => {
+                write!(f, "failed to load {component}: {error}")
+            }
+            SaveLoadError::Mismatch { before, after } => {
+                write!(f, "CRC changed from {before:#x} to {after:#x} after load")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveLoadError {}
+
+impl TrackedSnapshot {
+    pub fn capture(world: &World) -> Result<Self, SaveLoadError> {
+        checked(world, Self::capture_unchecked).map_err(SaveLoadError::Serialize)?
+    }
+
+    /// Serialize errors are recorded in the plugin's error slot rather than returned
+    fn capture_unchecked(world: &World) -> Result<Self, SaveLoadError> {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let report = calculate_crc_scoped(world, CrcScope::Shared, false);
+        let entities = report
+            .entities
+            .iter()
+            .map(|entity| {
+                entity
+                    .components
+                    .iter()
+                    .map(|component| {
+                        let name = component_name(world, component.id);
+                        let Some(snapshot) =
+                            &desync_data.serialize_fn_registry[&component.id].snapshot
+                        else {
+                            return Err(SaveLoadError::NotSaveable(name));
+                        };
+                        let ptr = world.get_by_id(entity.entity, component.id).unwrap();
+                        // SAFETY: components match
+                        let json = unsafe { (snapshot.save)(ptr) }.unwrap_or_else(|error| {
+                            desync_data.record_serialize_error(Some(&name), error);
+                            String::new()
+                        });
+                        Ok((name, json))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TrackedSnapshot { entities })
+    }
+
+    /// Spawn the saved entities into `world`, which must have the same components registered as
+    /// the world the snapshot was captured from. Returns the spawned entities in snapshot order
+    pub fn load(&self, world: &mut World) -> Result<Vec<Entity>, SaveLoadError> {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let loaders = desync_data
+            .serialize_fn_registry
+            .iter()
+            .filter_map(|(id, fns)| {
+                let snapshot = fns.snapshot.as_ref()?;
+                Some((component_name(world, *id), snapshot.load))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut entities = Vec::with_capacity(self.entities.len());
+        for components in self.entities.iter() {
+            let entity = world.spawn(TrackDesync).id();
+            for (name, json) in components.iter() {
+                let load = loaders
+                    .get(name)
+                    .ok_or_else(|| SaveLoadError::NotSaveable(name.clone()))?;
+                load(world, entity, json).map_err(|error| SaveLoadError::Load {
+                    component: name.clone(),
+                    error,
+                })?;
+            }
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+}
+
+/// Save the tracked state of `world`, load it into a fresh world and check the tracked
+/// components hash identically. Catches serializers which don't round-trip exactly, e.g. ones
+/// which drop precision or fields on load.
+///
+/// Only components are compared, in the order they were saved, so neither events nor
+/// `entity_sort` affect the result.
+pub fn check_save_load_stable(world: &World) -> Result<(), SaveLoadError> {
+    let snapshot = TrackedSnapshot::capture(world)?;
+    let report = calculate_crc_scoped(world, CrcScope::Shared, false);
+    let before = components_crc(world, report.entities.iter().map(|e| e.entity));
+
+    let mut fresh = World::new();
+    let desync_data = world.resource::<DesyncPluginData>();
+    let mut fresh_data = desync_data.clone();
+    fresh_data.serialize_fn_registry = desync_data
+        .serialize_fn_registry
+        .values()
+        .filter_map(|fns| {
+            let snapshot = fns.snapshot.as_ref()?;
+            Some(((snapshot.init)(&mut fresh), fns.clone()))
+        })
+        .collect();
+    fresh.insert_resource(fresh_data);
+
+    let entities = snapshot.load(&mut fresh)?;
+    let after = components_crc(&fresh, entities.into_iter());
+    if before != after {
+        return Err(SaveLoadError::Mismatch { before, after });
+    }
+    Ok(())
+}
+
+/// Panicking version of [`check_save_load_stable`], for tests
+pub fn assert_save_load_stable(world: &World) {
+    if let Err(e) = check_save_load_stable(world) {
+        panic!("save/load isn't stable: {e}");
+    }
+}
+
+/// Full width CRC of the entities' tracked components, under the plugin's `crc_algorithm`
+fn components_crc(world: &World, entities: impl Iterator<Item = Entity>) -> u64 {
+    let mut digest = world.resource::<DesyncPluginData>().crc_algorithm.digest();
+    for entity in entities {
+        for component in entity_report(world, entity, CrcScope::Shared, false).components {
+            digest.update(&component.hashed);
+        }
+    }
+    digest.finalize()
+}
+
+fn component_name(world: &World, id: ComponentId) -> String {
+    world.components().get_info(id).unwrap().name().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::{Deserialize, Deserializer, Serialize};
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin};
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Health(u32);
+
+    /// Loses the lowest bit when loaded
+    #[derive(Component, Serialize)]
+    struct Lossy(u32);
+
+    impl<'de> Deserialize<'de> for Lossy {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Ok(Lossy(u32::deserialize(deserializer)? & !1))
+        }
+    }
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_saveable::<Position>();
+        app.track_desync_saveable::<Health>();
+        app.track_desync_saveable::<Lossy>();
+        app
+    }
+
+    #[test]
+    fn round_trip_preserves_crc() {
+        let mut app = build_app();
+        app.world
+            .spawn((Position { x: 0.1, y: -2.5 }, Health(3), TrackDesync));
+        app.world.spawn((Health(7), TrackDesync));
+        app.world.spawn((Lossy(4), TrackDesync));
+        assert_eq!(check_save_load_stable(&app.world), Ok(()));
+        assert_save_load_stable(&app.world);
+    }
+
+    #[test]
+    fn lossy_serializer_fails() {
+        let mut app = build_app();
+        app.world.spawn((Lossy(5), TrackDesync));
+        assert!(matches!(
+            check_save_load_stable(&app.world),
+            Err(SaveLoadError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn unsaveable_json_fails() {
+        // hashes fine, as canonical JSON writes any key as a string, but serde_json only takes
+        // string keys
+        #[derive(Component, Serialize, Deserialize)]
+        struct Cells(BTreeMap<(u8, u8), u8>);
+        let mut app = build_app();
+        app.track_desync_saveable::<Cells>();
+        app.world
+            .spawn((Cells(BTreeMap::from([((0, 1), 2)])), TrackDesync));
+        assert!(crate::calculate_crc(&app.world).is_ok());
+        let Err(SaveLoadError::Serialize(DesyncError::Serialize { type_name, error })) =
+            TrackedSnapshot::capture(&app.world)
+        else {
+            panic!("Cells saved");
+        };
+        assert!(type_name.ends_with("Cells"));
+        assert!(error.contains("key must be a string"));
+    }
+
+    #[test]
+    fn unsaveable_component() {
+        #[derive(Component, Serialize)]
+        struct Plain(u8);
+        let mut app = build_app();
+        app.track_desync::<Plain>();
+        app.world.spawn((Plain(0), TrackDesync));
+        assert!(matches!(
+            check_save_load_stable(&app.world),
+            Err(SaveLoadError::NotSaveable(_))
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+use bevy_ecs::{component::Component, entity::Entity, world::World};
+
+use crate::{unordered_tracked_entities, DesyncPluginData};
+
+/// Implemented by a position component, so entities can be ordered by [`sort_by_spatial_grid`]
+pub trait GridPosition {
+    fn grid_position(&self) -> [f32; 3];
+}
+
+/// Sort tracked entities by the grid cell their `P` component falls in, then by their
+/// [`crate::DesyncIdentity`] key. Walking the world cell by cell keeps hashing spatially coherent
+/// in large simulations.
+///
+/// Cells are computed with `floor(position / cell_size)`, which is exact IEEE arithmetic, so peers
+/// with identical positions always compute identical cells. Entities without `P` are ordered after
+/// every positioned entity, by key.
+///
+/// Usage:
+/// ```rust,ignore
+/// app.add_plugins(
+/// DesyncPlugin {
+///     entity_sort: Arc::new(Box::new(|w| sort_by_spatial_grid::<Position>(w, 16.0))),
+///     ..Default::default()
+/// })
+/// ```
+pub fn sort_by_spatial_grid<P: Component + GridPosition>(
+    world: &World,
+    cell_size: f32,
+) -> Vec<Entity> {
+    let identity = &world.resource::<DesyncPluginData>().identity;
+    let mut entities = unordered_tracked_entities(world)
+        .into_iter()
+        .map(|e| {
+            let cell = world
+                .get::<P>(e)
+                .map(|p| grid_cell(p.grid_position(), cell_size));
+            ((cell.is_none(), cell, identity.key(e, world)), e)
+        })
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|(key, _)| *key);
+    entities.into_iter().map(|(_, e)| e).collect()
+}
+
+fn grid_cell(position: [f32; 3], cell_size: f32) -> [i64; 3] {
+    // saturating casts, so non-finite positions still land in a deterministic cell
+    position.map(|p| (p as f64 / cell_size as f64).floor() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use serde::Serialize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncKey, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Position(f32, f32);
+
+    impl GridPosition for Position {
+        fn grid_position(&self) -> [f32; 3] {
+            [self.0, self.1, 0.0]
+        }
+    }
+
+    #[derive(Component)]
+    struct NetKey(u64);
+
+    impl DesyncKey for NetKey {
+        fn desync_key(&self) -> u64 {
+            self.0
+        }
+    }
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Position>();
+        app.track_desync_key::<NetKey>();
+        app.world.resource_mut::<DesyncPluginData>().entity_sort =
+            Arc::new(Box::new(|w| sort_by_spatial_grid::<Position>(w, 10.0)));
+        app
+    }
+
+    fn keys(app: &App) -> Vec<u64> {
+        sort_by_spatial_grid::<Position>(&app.world, 10.0)
+            .into_iter()
+            .map(|e| app.world.get::<NetKey>(e).unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn same_order_on_both_peers() {
+        let units = [
+            (0, Position(15.0, 1.0)),
+            (1, Position(-0.5, 3.0)),
+            (2, Position(12.0, 9.0)),
+            (3, Position(3.0, 2.0)),
+            (4, Position(3.0, 25.0)),
+        ];
+        let mut app_1 = build_app();
+        let mut app_2 = build_app();
+        for (key, position) in units.iter() {
+            app_1
+                .world
+                .spawn((Position(position.0, position.1), NetKey(*key), TrackDesync));
+        }
+        for (key, position) in units.iter().rev() {
+            app_2
+                .world
+                .spawn((Position(position.0, position.1), NetKey(*key), TrackDesync));
+        }
+        app_1.world.spawn((NetKey(5), TrackDesync));
+        app_2.world.spawn((NetKey(5), TrackDesync));
+
+        // by cell x, cell y, then key, with unpositioned entities last
+        assert_eq!(keys(&app_1), vec![1, 3, 4, 0, 2, 5]);
+        assert_eq!(keys(&app_1), keys(&app_2));
+        app_1.update();
+        app_2.update();
+        assert_eq!(app_1.world.resource::<Crc>(), app_2.world.resource::<Crc>());
+    }
+}
@@ -0,0 +1,118 @@
+use bevy_reflect::{Reflect, ReflectMut};
+use std::collections::HashMap;
+
+/// Tolerance for floats in tracked components, by field name. See
+/// [`crate::AppDesyncExt::track_desync_tolerances`]
+pub type FloatTolerances = HashMap<&'static str, f64>;
+
+/// Snap every float in `value` to a multiple of the tolerance of the closest enclosing named field.
+/// Drift within a tolerance snaps to the same multiple, apart from values right on a rounding
+/// boundary, which snap to either side. Floats outside any field in `tolerances`, or with a
+/// tolerance of zero, are left exact.
+pub(crate) fn apply_tolerances(value: &mut dyn Reflect, tolerances: &FloatTolerances) {
+    quantize(value, tolerances, None);
+}
+
+fn quantize(value: &mut dyn Reflect, tolerances: &FloatTolerances, tolerance: Option<f64>) {
+    // a field's tolerance covers everything nested inside it, unless overridden deeper down
+    let field_tolerance = |name: Option<&str>| {
+        name.and_then(|name| tolerances.get(name))
+            .copied()
+            .or(tolerance)
+    };
+    match value.reflect_mut() {
+        ReflectMut::Struct(s) => {
+            for i in 0..s.field_len() {
+                let tolerance = field_tolerance(s.name_at(i));
+                quantize(s.field_at_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::Enum(e) => {
+            for i in 0..e.field_len() {
+                let tolerance = field_tolerance(e.name_at(i));
+                quantize(e.field_at_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::TupleStruct(s) => {
+            for i in 0..s.field_len() {
+                quantize(s.field_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::Tuple(t) => {
+            for i in 0..t.field_len() {
+                quantize(t.field_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::List(l) => {
+            for i in 0..l.len() {
+                quantize(l.get_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::Array(a) => {
+            for i in 0..a.len() {
+                quantize(a.get_mut(i).unwrap(), tolerances, tolerance);
+            }
+        }
+        ReflectMut::Map(m) => {
+            for i in 0..m.len() {
+                quantize(m.get_at_mut(i).unwrap().1, tolerances, tolerance);
+            }
+        }
+        ReflectMut::Value(v) => {
+            let Some(tolerance) = tolerance.filter(|t| *t > 0.0) else {
+                return;
+            };
+            if let Some(v) = v.downcast_mut::<f32>() {
+                *v = ((*v as f64 / tolerance).round() * tolerance) as f32;
+            } else if let Some(v) = v.downcast_mut::<f64>() {
+                *v = (*v / tolerance).round() * tolerance;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::component::Component;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{calculate_crc, AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Reflect, Serialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Component, Reflect, Serialize)]
+    struct Unit {
+        translation: Position,
+        health: f32,
+    }
+
+    fn crc(unit: Unit) -> u16 {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_tolerances::<Unit>(FloatTolerances::from([
+                ("translation", 1e-2),
+                ("health", 0.0),
+            ]));
+        app.world.spawn((unit, TrackDesync));
+        calculate_crc(&app.world).unwrap()
+    }
+
+    #[test]
+    fn per_field_tolerance() {
+        let unit = |x, health| Unit {
+            translation: Position { x, y: 2.0 },
+            health,
+        };
+        // nested floats under `translation` tolerate drift
+        assert_eq!(crc(unit(1.0, 10.0)), crc(unit(1.0001, 10.0)));
+        assert_ne!(crc(unit(1.0, 10.0)), crc(unit(1.5, 10.0)));
+        // `health` is exact
+        assert_ne!(crc(unit(1.0, 10.0)), crc(unit(1.0, 10.0001)));
+    }
+}
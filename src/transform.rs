@@ -0,0 +1,104 @@
+use bevy_ecs::component::Component;
+use bevy_transform::components::{GlobalTransform, Transform};
+use serde::Serialize;
+
+/// Implemented by transform components which can be hashed with
+/// [`crate::AppDesyncExt::track_desync_trs`]
+pub trait Trs: Component {
+    /// Translation, rotation quaternion as `[x, y, z, w]`, and scale
+    fn trs(&self) -> ([f32; 3], [f32; 4], [f32; 3]);
+}
+
+impl Trs for Transform {
+    fn trs(&self) -> ([f32; 3], [f32; 4], [f32; 3]) {
+        (
+            self.translation.to_array(),
+            self.rotation.to_array(),
+            self.scale.to_array(),
+        )
+    }
+}
+
+impl Trs for GlobalTransform {
+    fn trs(&self) -> ([f32; 3], [f32; 4], [f32; 3]) {
+        let (scale, rotation, translation) = self.to_scale_rotation_translation();
+        (
+            translation.to_array(),
+            rotation.to_array(),
+            scale.to_array(),
+        )
+    }
+}
+
+/// A transform reduced to what it means physically, each value a multiple of the quantum
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct CanonicalTrs {
+    pub translation: [i64; 3],
+    pub rotation: [i64; 4],
+    pub scale: [i64; 3],
+}
+
+/// Decompose a transform into translation, rotation and scale, normalize the rotation and move it
+/// into the hemisphere with a positive `w` (`q` and `-q` are the same rotation), then quantize
+/// everything to multiples of `quantum`. Transforms which are physically identical up to the
+/// quantum map to the same value, apart from values right on a rounding boundary.
+pub fn canonical_trs(transform: &impl Trs, quantum: f32) -> CanonicalTrs {
+    let (translation, rotation, scale) = transform.trs();
+    let quantize = |v: f32| (v as f64 / quantum as f64).round() as i64;
+    CanonicalTrs {
+        translation: translation.map(quantize),
+        rotation: canonical_rotation(rotation).map(quantize),
+        scale: scale.map(quantize),
+    }
+}
+
+fn canonical_rotation(rotation: [f32; 4]) -> [f32; 4] {
+    let length = rotation.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if length == 0.0 || !length.is_finite() {
+        // nothing to normalize, hash as is
+        return rotation;
+    }
+    let rotation = rotation.map(|v| v / length);
+    // with w == 0 both signs are still equivalent, so decide on the first nonzero component
+    let [x, y, z, w] = rotation;
+    let sign = [w, x, y, z]
+        .into_iter()
+        .find(|v| *v != 0.0)
+        .map_or(1.0, f32::signum);
+    rotation.map(|v| v * sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+
+    use super::*;
+    use crate::{AppDesyncExt, Crc, DesyncPlugin, TrackDesync};
+
+    fn crc(transform: Transform) -> u16 {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync_trs::<Transform>(1e-4);
+        app.world.spawn((transform, TrackDesync));
+        app.update();
+        app.world.resource::<Crc>().0
+    }
+
+    #[test]
+    fn opposite_quaternions_hash_equally() {
+        let mut transform = Transform::from_xyz(1.0, 2.0, 3.0);
+        transform.rotate_y(0.7);
+        let mut negated = transform;
+        negated.rotation = -transform.rotation;
+        assert_ne!(transform.rotation, negated.rotation);
+        assert_eq!(
+            canonical_trs(&transform, 1e-4),
+            canonical_trs(&negated, 1e-4)
+        );
+        assert_eq!(crc(transform), crc(negated));
+
+        let mut rotated = transform;
+        rotated.rotate_y(0.1);
+        assert_ne!(crc(transform), crc(rotated));
+    }
+}
@@ -0,0 +1,120 @@
+use bevy_ecs::{component::Component, world::World};
+
+use crate::{
+    checked, is_hashed, report::EntityHasher, CrcAlgorithm, CrcScope, DesyncError,
+    DesyncPluginData, MISSING_ENTITY_SENTINEL,
+};
+
+/// Marks a tracked entity whose desyncs matter most, e.g. the player, so [`WeightedCrc`] hashes it
+/// at a wider width than everything else
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct CriticalDesync;
+
+/// A CRC which spends its collision resistance where it matters: entities marked with
+/// [`CriticalDesync`] are hashed with [`CrcAlgorithm::Crc64Xz`], everything else with the plugin's
+/// `crc_algorithm`. Two peers are in sync if both parts match.
+///
+/// Each part hashes its entities in `entity_sort` order. Missing entities, resources and events
+/// are hashed into the critical part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WeightedCrc {
+    pub critical: u64,
+    pub others: u64,
+}
+
+impl WeightedCrc {
+    /// Calculate both parts. Fails like [`crate::calculate_crc`]
+    pub fn calculate(world: &World) -> Result<Self, DesyncError> {
+        checked(world, Self::calculate_unchecked)
+    }
+
+    fn calculate_unchecked(world: &World) -> Self {
+        let desync_data = world.resource::<DesyncPluginData>();
+        let mut critical = CrcAlgorithm::Crc64Xz.digest();
+        let mut others = desync_data.crc_algorithm.digest();
+        let mut hasher = EntityHasher::default();
+        for entity in (desync_data.entity_sort)(world) {
+            let Some(entity_ref) = world.get_entity(entity) else {
+                if desync_data
+                    .missing_entities
+                    .missing(entity, &desync_data.errors)
+                    .is_some()
+                {
+                    critical.update(MISSING_ENTITY_SENTINEL.as_bytes());
+                }
+                continue;
+            };
+            if !is_hashed(entity, world) {
+                continue;
+            }
+            let digest = match entity_ref.contains::<CriticalDesync>() {
+                true => &mut critical,
+                false => &mut others,
+            };
+            hasher.hash(world, entity, CrcScope::Shared);
+            digest.update(&hasher.bytes);
+        }
+        critical.update(&desync_data.serialize_global_input(world));
+        WeightedCrc {
+            critical: critical.finalize(),
+            others: others.finalize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_ecs::query::Has;
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{AppDesyncExt, DesyncPlugin, TrackDesync};
+
+    #[derive(Component, Serialize)]
+    struct Health(u32);
+
+    fn build_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(DesyncPlugin::default())
+            .track_desync::<Health>();
+        app.world.spawn((Health(0), TrackDesync, CriticalDesync));
+        app.world.spawn((Health(0), TrackDesync));
+        app
+    }
+
+    fn weighted_crc(app: &mut App, player: u32, prop: u32) -> WeightedCrc {
+        let mut query = app.world.query::<(&mut Health, Has<CriticalDesync>)>();
+        for (mut health, critical) in query.iter_mut(&mut app.world) {
+            health.0 = if critical { player } else { prop };
+        }
+        WeightedCrc::calculate(&app.world).unwrap()
+    }
+
+    #[test]
+    fn critical_entities_hashed_wider() {
+        let mut app = build_app();
+        let base = weighted_crc(&mut app, 100, 100);
+        for bit in 0..32 {
+            let flipped = weighted_crc(&mut app, 100 ^ (1 << bit), 100);
+            assert_ne!(flipped.critical, base.critical);
+            assert_eq!(flipped.others, base.others);
+        }
+
+        // the 16 bit part collides for some pair of different props
+        let mut seen = HashMap::new();
+        let (a, b) = (0..)
+            .find_map(|prop| {
+                let crc = weighted_crc(&mut app, 0, prop).others;
+                seen.insert(crc, prop).map(|other| (other, prop))
+            })
+            .unwrap();
+        assert_eq!(weighted_crc(&mut app, 0, a), weighted_crc(&mut app, 0, b));
+        // while the same values on the critical entity don't
+        assert_ne!(
+            weighted_crc(&mut app, a, 0).critical,
+            weighted_crc(&mut app, b, 0).critical
+        );
+    }
+}